@@ -0,0 +1,59 @@
+//! Compares staging a synthetic work tree on a single thread against the
+//! worker pool used by `vit add`.
+//!
+//! Run with `cargo bench --bench add`.
+
+use std::{env, fs, path::Path, process, time::Instant};
+
+use version_it::utils;
+
+const FILE_COUNT: usize = 2_000;
+const FILE_SIZE: usize = 64 * 1024;
+
+fn main() {
+    let root = env::temp_dir().join(format!("vit-bench-add-{}", process::id()));
+    let work_tree = root.join("work");
+    fs::create_dir_all(&work_tree).unwrap();
+
+    let files = create_files(&work_tree);
+    let workers = utils::worker_count();
+
+    let sequential = stage_all(&root.join("sequential"), &files, 1);
+    let parallel = stage_all(&root.join("parallel"), &files, workers);
+
+    println!("files:      {} x {} KiB", FILE_COUNT, FILE_SIZE / 1024);
+    println!("sequential: {:?}", sequential);
+    println!("parallel:   {:?} ({} workers)", parallel, workers);
+    println!(
+        "speedup:    {:.2}x",
+        sequential.as_secs_f64() / parallel.as_secs_f64()
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+fn create_files(work_tree: &Path) -> Vec<String> {
+    (0..FILE_COUNT)
+        .map(|i| {
+            let path = work_tree.join(format!("file_{}.txt", i));
+            let content: Vec<u8> = (0..FILE_SIZE)
+                .map(|j| ((i * 31 + j * 7) % 251) as u8)
+                .collect();
+            fs::write(&path, content).unwrap();
+            path.to_str().unwrap().to_string()
+        })
+        .collect()
+}
+
+fn stage_all(vit_dir: &Path, files: &[String], workers: usize) -> std::time::Duration {
+    fs::create_dir_all(vit_dir.join("objects")).unwrap();
+
+    let start = Instant::now();
+    let hashes = utils::parallel_map(files, workers, |path| {
+        utils::hash_and_store_file(vit_dir, path).unwrap()
+    });
+    let elapsed = start.elapsed();
+
+    assert_eq!(hashes.len(), files.len());
+    elapsed
+}
@@ -1,21 +1,53 @@
 use crate::commands::{
-    get_add_command, get_branch_command, get_checkout_command,
-    get_commit_command, get_init_command, get_log_command, get_stash_command, get_status_command,
+    get_add_command, get_branch_command, get_checkout_command, get_commit_command,
+    get_config_command, get_gc_command, get_init_command, get_log_command, get_prune_command,
+    get_reflog_command, get_repack_command, get_reset_command, get_restore_command,
+    get_show_ref_command, get_stash_command, get_status_command, get_symbolic_ref_command,
+    get_tag_command, get_update_ref_command,
 };
-use clap::Command;
+use clap::{Arg, ArgAction, Command, value_parser};
 
 pub fn cli() -> Command {
     Command::new("vit")
         .about("A simpler versioning tool using rust")
         .subcommand_required(true)
         .arg_required_else_help(true)
-        .allow_external_subcommands(false)
+        .allow_external_subcommands(true)
+        .external_subcommand_value_parser(value_parser!(String))
+        .arg(
+            Arg::new("no-pager")
+                .long("no-pager")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Do not pipe output into a pager"),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .global(true)
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("always")
+                .value_parser(["auto", "always", "never"])
+                .help("When to colour output, overrides color.ui"),
+        )
         .subcommand(get_init_command())
         .subcommand(get_status_command())
         .subcommand(get_add_command())
+        .subcommand(get_reset_command())
+        .subcommand(get_restore_command())
         .subcommand(get_commit_command())
         .subcommand(get_branch_command())
         .subcommand(get_checkout_command())
         .subcommand(get_stash_command())
         .subcommand(get_log_command())
+        .subcommand(get_tag_command())
+        .subcommand(get_reflog_command())
+        .subcommand(get_show_ref_command())
+        .subcommand(get_update_ref_command())
+        .subcommand(get_symbolic_ref_command())
+        .subcommand(get_config_command())
+        .subcommand(get_repack_command())
+        .subcommand(get_gc_command())
+        .subcommand(get_prune_command())
 }
@@ -1,7 +1,9 @@
 use crate::utils;
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
 use std::{
-    env,
+    collections::HashMap,
+    env, fs,
+    io::{self, Write},
     path::{Path, PathBuf},
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -11,13 +13,36 @@ pub fn get_add_command() -> Command {
         .about("Add file to staging")
         .arg(
             Arg::new("paths")
-                .required(true)
+                .required_unless_present("patch")
                 .num_args(1..) // Accept 1 or more paths
-                .help("Files or directories to add"),
+                .help("Files to add, as paths, globs or pathspec magic such as :(exclude)"),
+        )
+        .arg(
+            Arg::new("patch")
+                .short('p')
+                .long("patch")
+                .action(ArgAction::SetTrue)
+                .help("Choose the hunks of tracked files to stage interactively"),
         )
         .arg_required_else_help(true)
 }
 
+/// Outcome of staging a single path, computed on a worker thread.
+enum StagedFile {
+    /// File is tracked but no longer exists in the work tree
+    Deleted,
+    /// File content matches the index, nothing to do
+    Unchanged,
+    /// Only the timestamps moved, content is the same
+    Touched {
+        mtime_secs: u32,
+        mtime_nsecs: u32,
+    },
+    /// Content differs from the index (or file is new), blob already stored
+    Updated(utils::IndexEntry),
+    Failed(io::Error),
+}
+
 pub fn add(paths: &[String]) {
     let current_dir: PathBuf = env::current_dir().expect("Directory not found!");
     let vit_dir: PathBuf = current_dir.join(".vit");
@@ -27,76 +52,304 @@ pub fn add(paths: &[String]) {
         return eprintln!("vit repository not initialized!");
     }
 
-    let files_to_add: Vec<String> = utils::expand_paths(paths);
+    let pathspec = match utils::Pathspec::parse(paths) {
+        Ok(pathspec) => pathspec,
+        Err(err) => return eprintln!("fatal: {}", err),
+    };
+    let mut files_to_add: Vec<String> = pathspec.work_tree_files();
     let mut index_entries: Vec<utils::IndexEntry> = utils::read_index().unwrap();
-    let add_deleted_files: bool = paths[0] == ".";
-
-    for file_path in files_to_add {
-        match index_entries
-            .iter_mut()
-            .find(|entry| entry.path == file_path)
-        {
-            Some(existing_entry) => {
-                let is_deleted_file = Path::new(&file_path);
-                if !is_deleted_file.exists() {
-                    existing_entry.status = utils::FileStatus::Deleted;
-                    return;
-                }
 
-                // Step 3a: Get file metadata (timestamp, size, etc.)
-                let metadata = std::fs::metadata(&file_path).expect("Cannot stat file");
-
-                let mtime = metadata
-                    .modified()
-                    .unwrap_or(SystemTime::now())
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap();
-
-                if existing_entry.mtime_secs != mtime.as_secs() as u32 {
-                    existing_entry.mtime_nsecs = mtime.as_nanos() as u32;
-                    existing_entry.mtime_secs = mtime.as_secs() as u32;
-
-                    let (file_hash, object) = utils::hash_file(&file_path);
-                    if existing_entry.sha256 != file_hash {
-                        // Mark file as modified
-                        existing_entry.status = utils::FileStatus::Modified;
-                        existing_entry.sha256 = file_hash;
-
-                        // Store file object
-                        let _ = utils::store_object(vit_dir.as_path(), file_hash, object);
-                        is_something_updated = true;
-                        println!("Added file: {}", file_path);
-                    }
-                }
+    // Tracked files that are gone get staged as deletions
+    files_to_add.extend(
+        index_entries
+            .iter()
+            .filter(|entry| entry.status != utils::FileStatus::Deleted)
+            .filter(|entry| pathspec.matches(&entry.path))
+            .filter(|entry| !Path::new(entry.path.as_str()).exists())
+            .map(|entry| entry.path.clone()),
+    );
+
+    if let Some(unmatched) = pathspec.unmatched(&files_to_add).first() {
+        return eprintln!("fatal: pathspec '{}' did not match any files", unmatched);
+    }
+
+    is_something_updated |= stage_paths(&vit_dir, &files_to_add, &mut index_entries, true);
+
+    if !is_something_updated {
+        println!("Everything is up to date");
+    }
+
+    // Keep the index sorted so its content does not depend on thread scheduling
+    index_entries.sort_by(|a, b| a.path.cmp(&b.path));
+    utils::write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
+}
+
+/// Stage parts of tracked files, offering every changed hunk between the
+/// index and the work tree. Without paths every tracked file is offered.
+///
+/// A partially staged file gets a blob with just the chosen hunks, its
+/// index entry keeps no modification time so the rest still shows up as
+/// not staged.
+pub fn add_patch(paths: &[String]) {
+    let current_dir: PathBuf = env::current_dir().expect("Directory not found!");
+    let vit_dir: PathBuf = current_dir.join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    let objects_path = vit_dir.join("objects");
+    let mut index_entries: Vec<utils::IndexEntry> = utils::read_index().unwrap();
+    let pathspec = match utils::Pathspec::parse(paths) {
+        Ok(pathspec) => pathspec,
+        Err(err) => return eprintln!("fatal: {}", err),
+    };
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut out = io::stdout();
+    let mut is_something_offered = false;
+
+    for entry in index_entries.iter_mut() {
+        if entry.status == utils::FileStatus::Deleted || !pathspec.matches(&entry.path) {
+            continue;
+        }
+
+        let (_, old) = match utils::read_object_by_hash(&objects_path, &entry.sha256) {
+            Ok(object) => object,
+            Err(err) => {
+                eprintln!("Unable to read the staged {}: {}", entry.path, err);
+                continue;
+            }
+        };
+        // A failure only skips the file, hunks chosen for others are kept
+        let selection = match fs::read(&entry.path) {
+            Ok(new) if new == old => continue,
+            Ok(new) if new.contains(&0) || old.contains(&0) => {
+                println!("Cannot stage binary file {} in patch mode", entry.path);
+                continue;
             }
-            None => {
-                println!("Added file: {}", file_path);
-                // Create IndexEntry
-                let new_entry = utils::IndexEntry::create(&file_path);
-                index_entries.push(new_entry);
+            Ok(new) => {
+                is_something_offered = true;
+                let selection = match utils::select_hunks(
+                    &entry.path,
+                    &old,
+                    &new,
+                    "Stage",
+                    &mut input,
+                    &mut out,
+                ) {
+                    Ok(selection) => selection,
+                    Err(err) => {
+                        eprintln!("Unable to stage {}: {}", entry.path, err);
+                        continue;
+                    }
+                };
 
-                // Hash the file
-                let (file_hash, object) = utils::hash_file(&file_path);
+                if let Some(content) = &selection.content {
+                    let sha256 = match utils::write_loose_object(&objects_path, "blob", content) {
+                        Ok(sha256) => sha256,
+                        Err(err) => {
+                            eprintln!("Unable to stage {}: {}", entry.path, err);
+                            continue;
+                        }
+                    };
+                    let status = match entry.status {
+                        utils::FileStatus::New => utils::FileStatus::New,
+                        _ => utils::FileStatus::Modified,
+                    };
 
-                // Store file object
-                let _ = utils::store_object(vit_dir.as_path(), file_hash, object);
-                is_something_updated = true;
+                    // Without metadata the file is rehashed by the next status
+                    match fs::metadata(&entry.path) {
+                        Ok(metadata) if *content == new => {
+                            *entry =
+                                utils::IndexEntry::from_metadata(&entry.path, &metadata, sha256);
+                        }
+                        _ => {
+                            entry.sha256 = sha256;
+                            entry.file_size = content.len() as u32;
+                            entry.mtime_secs = 0;
+                            entry.mtime_nsecs = 0;
+                        }
+                    }
+                    entry.status = status;
+                }
+                selection
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                is_something_offered = true;
+                let selection =
+                    match utils::select_deletion(&entry.path, "Stage", &mut input, &mut out) {
+                        Ok(selection) => selection,
+                        Err(err) => {
+                            eprintln!("Unable to stage {}: {}", entry.path, err);
+                            continue;
+                        }
+                    };
+                if selection.content.is_some() {
+                    entry.status = utils::FileStatus::Deleted;
+                }
+                selection
+            }
+            Err(err) => {
+                eprintln!("Unable to read {}: {}", entry.path, err);
+                continue;
             }
+        };
+
+        if selection.quit {
+            break;
         }
     }
+    let _ = out.flush();
+
+    if !is_something_offered {
+        return println!("No changes.");
+    }
+
+    utils::write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
+}
+
+/// Stage every tracked file that was modified or deleted in the work tree,
+/// like `add -u`. Returns whether the index changed.
+pub fn stage_tracked_files(vit_dir: &Path, index_entries: &mut Vec<utils::IndexEntry>) -> bool {
+    let tracked: Vec<String> = index_entries
+        .iter()
+        .filter(|entry| entry.status != utils::FileStatus::Deleted)
+        .map(|entry| entry.path.clone())
+        .collect();
+
+    let updated = stage_paths(vit_dir, &tracked, index_entries, false);
+    index_entries.sort_by(|a, b| a.path.cmp(&b.path));
+    updated
+}
 
-    if add_deleted_files {
-        for entry in index_entries.iter_mut() {
-            if !Path::new(entry.path.as_str()).exists() {
-                entry.status = utils::FileStatus::Deleted;
+/// Stage `files_to_add` into `index_entries`, printing every added file
+/// when `verbose`. Returns whether the index changed.
+fn stage_paths(
+    vit_dir: &Path,
+    files_to_add: &[String],
+    index_entries: &mut Vec<utils::IndexEntry>,
+    verbose: bool,
+) -> bool {
+    let mut is_something_updated = false;
+
+    // Hash and compress on a worker pool, the index is only read here
+    let staged_files = stage_files(vit_dir, files_to_add, index_entries);
+
+    let mut positions: HashMap<String, usize> = index_entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (entry.path.clone(), i))
+        .collect();
+
+    for (file_path, staged) in files_to_add.iter().zip(staged_files) {
+        match staged {
+            StagedFile::Deleted => {
+                if let Some(&i) = positions.get(file_path)
+                    && index_entries[i].status != utils::FileStatus::Deleted
+                {
+                    index_entries[i].status = utils::FileStatus::Deleted;
+                    is_something_updated = true;
+                }
+            }
+            StagedFile::Unchanged => {}
+            StagedFile::Touched {
+                mtime_secs,
+                mtime_nsecs,
+            } => {
+                if let Some(&i) = positions.get(file_path) {
+                    index_entries[i].mtime_secs = mtime_secs;
+                    index_entries[i].mtime_nsecs = mtime_nsecs;
+                }
+            }
+            StagedFile::Updated(mut new_entry) => {
+                if verbose {
+                    println!("Added file: {}", file_path);
+                }
                 is_something_updated = true;
+
+                match positions.get(file_path) {
+                    Some(&i) => {
+                        new_entry.status = match index_entries[i].status {
+                            utils::FileStatus::New => utils::FileStatus::New,
+                            _ => utils::FileStatus::Modified,
+                        };
+                        index_entries[i] = new_entry;
+                    }
+                    None => {
+                        positions.insert(file_path.clone(), index_entries.len());
+                        index_entries.push(new_entry);
+                    }
+                }
+            }
+            StagedFile::Failed(err) => {
+                eprintln!("Unable to add {}: {}", file_path, err);
             }
         }
     }
 
-    if !is_something_updated {
-        println!("Everything is up to date");
+    is_something_updated
+}
+
+fn stage_files(
+    vit_dir: &Path,
+    files_to_add: &[String],
+    index_entries: &[utils::IndexEntry],
+) -> Vec<StagedFile> {
+    let existing: HashMap<&str, &utils::IndexEntry> = index_entries
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry))
+        .collect();
+
+    utils::parallel_map(files_to_add, utils::worker_count(), |file_path| {
+        stage_file(
+            vit_dir,
+            file_path,
+            existing.get(file_path.as_str()).copied(),
+        )
+    })
+}
+
+fn stage_file(
+    vit_dir: &Path,
+    file_path: &str,
+    existing_entry: Option<&utils::IndexEntry>,
+) -> StagedFile {
+    let metadata = match fs::metadata(file_path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return match existing_entry {
+                Some(_) => StagedFile::Deleted,
+                None => StagedFile::Failed(err),
+            };
+        }
+        Err(err) => return StagedFile::Failed(err),
+    };
+
+    let mtime = metadata
+        .modified()
+        .unwrap_or(SystemTime::now())
+        .duration_since(UNIX_EPOCH)
+        .unwrap();
+
+    if let Some(entry) = existing_entry
+        && entry.mtime_secs == mtime.as_secs() as u32
+    {
+        return StagedFile::Unchanged;
     }
 
-    utils::write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
+    let file_hash = match utils::hash_and_store_file(vit_dir, file_path) {
+        Ok(hash) => hash,
+        Err(err) => return StagedFile::Failed(err),
+    };
+
+    match existing_entry {
+        Some(entry) if entry.sha256 == file_hash => StagedFile::Touched {
+            mtime_secs: mtime.as_secs() as u32,
+            mtime_nsecs: mtime.subsec_nanos(),
+        },
+        _ => StagedFile::Updated(utils::IndexEntry::from_metadata(
+            file_path, &metadata, file_hash,
+        )),
+    }
 }
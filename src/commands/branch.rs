@@ -1,79 +1,384 @@
-use clap::{Arg, ArgAction, Command};
+use crate::utils::{
+    RefStore, ancestors, is_valid_ref_short_name, loose_object_path, read_commit_file,
+    resolve_revision,
+};
+use clap::{Arg, ArgAction, ArgGroup, Command};
 use colored::Colorize;
-use std::{env, fs, path::Path};
+use std::{collections::HashSet, env, fs, path::Path};
 
 pub fn get_branch_command() -> Command {
     Command::new("branch")
-        .about("Get, create or delete a branch")
+        .about("Get, create, rename, copy or delete a branch")
         .arg(
-            Arg::new("name")
-                .required(false)
-                .help("Name of branch to be created"),
+            Arg::new("names").required(false).num_args(0..=2).help(
+                "Branch to create and its start point, or the old and new name with -m and -c",
+            ),
         )
         .arg(
             Arg::new("delete")
-                .short('D')
+                .short('d')
                 .long("delete")
-                .required(false)
                 .action(ArgAction::SetTrue)
-                .help("Delete the specified branch"),
+                .help("Delete a branch that is merged into HEAD"),
+        )
+        .arg(
+            Arg::new("force-delete")
+                .short('D')
+                .action(ArgAction::SetTrue)
+                .help("Delete a branch even when it is not merged"),
+        )
+        .arg(
+            Arg::new("move")
+                .short('m')
+                .long("move")
+                .action(ArgAction::SetTrue)
+                .help("Rename a branch together with its reflog"),
+        )
+        .arg(
+            Arg::new("force-move")
+                .short('M')
+                .action(ArgAction::SetTrue)
+                .help("Rename a branch even if the new name exists"),
+        )
+        .arg(
+            Arg::new("copy")
+                .short('c')
+                .long("copy")
+                .action(ArgAction::SetTrue)
+                .help("Copy a branch together with its reflog"),
+        )
+        .arg(
+            Arg::new("force-copy")
+                .short('C')
+                .action(ArgAction::SetTrue)
+                .help("Copy a branch even if the new name exists"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::SetTrue)
+                .help("Show the hash and subject of each branch tip"),
+        )
+        .arg(
+            Arg::new("merged")
+                .long("merged")
+                .num_args(0..=1)
+                .default_missing_value("HEAD")
+                .value_name("COMMIT")
+                .help("Only list branches merged into the commit"),
+        )
+        .arg(
+            Arg::new("no-merged")
+                .long("no-merged")
+                .num_args(0..=1)
+                .default_missing_value("HEAD")
+                .value_name("COMMIT")
+                .help("Only list branches not merged into the commit"),
+        )
+        .arg(
+            Arg::new("contains")
+                .long("contains")
+                .num_args(0..=1)
+                .default_missing_value("HEAD")
+                .value_name("COMMIT")
+                .help("Only list branches containing the commit"),
         )
+        .group(
+            ArgGroup::new("mode")
+                .args([
+                    "delete",
+                    "force-delete",
+                    "move",
+                    "force-move",
+                    "copy",
+                    "force-copy",
+                ])
+                .multiple(false),
+        )
+}
+
+/// Restricts `branch` listings, each holds the revision it was given.
+#[derive(Debug, Default)]
+pub struct BranchFilter {
+    pub merged: Option<String>,
+    pub no_merged: Option<String>,
+    pub contains: Option<String>,
+}
+
+pub fn branch(name: &str, start_point: Option<&str>) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit not initialized");
+    }
+
+    let refs = RefStore::new(&vit_dir);
+    let branch_ref = format!("refs/heads/{}", name);
+    if !is_valid_ref_short_name(name) {
+        return eprintln!("{} {}", name.red(), "is not a valid branch name!".red());
+    }
+    if refs.read(&branch_ref).is_some() {
+        return eprintln!("{} {}", name.red(), "Branch already exists!".red());
+    }
+    if let Some(existing) = refs.conflicting(&branch_ref) {
+        return eprintln!(
+            "{} {} {}",
+            "Can not create branch, ref".red(),
+            existing.red(),
+            "already exists!".red()
+        );
+    }
+
+    let start = match resolve_revision(&vit_dir, start_point.unwrap_or("HEAD")) {
+        Ok(hash) => hash,
+        // There is nothing to branch off before the first commit
+        Err(_) if start_point.is_none() && refs.read("HEAD").is_none() => {
+            let current = refs.current_branch().unwrap_or_else(|| "HEAD".to_string());
+            return eprintln!(
+                "{}",
+                format!("Not a valid object name: '{}'", current).red()
+            );
+        }
+        Err(err) => return eprintln!("{}", err.to_string().red()),
+    };
+
+    let reflog_message = format!("branch: Created from {}", start_point.unwrap_or("HEAD"));
+    if let Err(err) = refs.update(&branch_ref, &start, Some([0u8; 32]), &reflog_message) {
+        return eprintln!("{}", err.to_string().red());
+    }
+    println!("Branch '{}' created", name);
+}
+
+/// Delete a branch, without `force` only once HEAD contains its tip.
+pub fn branch_delete(name: &str, force: bool) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit not initialized");
+    }
+
+    let refs = RefStore::new(&vit_dir);
+    let branch_ref = format!("refs/heads/{}", name);
+    let Some(tip) = refs.read(&branch_ref) else {
+        return eprintln!("{} {}", name.red(), "Branch does not exist!".red());
+    };
+    if refs.head_branch().as_deref() == Some(branch_ref.as_str()) {
+        return eprintln!("{}", "Can not delete active branch".red());
+    }
+
+    if !force {
+        let objects_dir = vit_dir.join("objects");
+        let merged = resolve_revision(&vit_dir, "HEAD")
+            .and_then(|head| ancestors(&objects_dir, head))
+            .is_ok_and(|reachable| reachable.contains(&tip));
+
+        if !merged {
+            return eprintln!(
+                "{} {}",
+                format!("The branch '{}' is not fully merged.", name).red(),
+                format!("Run 'vit branch -D {}' to delete it anyway.", name).red()
+            );
+        }
+    }
+
+    if let Err(err) = refs.delete(&branch_ref, Some(tip), "branch: deleted") {
+        return eprintln!("{}", err.to_string().red());
+    }
+    println!("Branch '{}' deleted (was {})", name, &hex::encode(tip)[..7]);
+}
+
+/// Rename a branch and its reflog, HEAD follows when it is the current
+/// branch. Without `old` the current branch is renamed.
+pub fn branch_rename(old: Option<&str>, new: &str, force: bool) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit not initialized");
+    }
+
+    let refs = RefStore::new(&vit_dir);
+    let current_ref = refs.head_branch().unwrap_or_default();
+    let old = old.unwrap_or(current_ref.trim_start_matches("refs/heads/"));
+
+    // An unborn branch only lives in HEAD until its first commit
+    if current_ref == format!("refs/heads/{}", old) && refs.read("HEAD").is_none() {
+        if !is_valid_ref_short_name(new) {
+            return eprintln!("{}", format!("'{}' is not a valid branch name!", new).red());
+        }
+        let new_ref = format!("refs/heads/{}", new);
+        if let Err(err) = refs.set_symbolic("HEAD", &new_ref, "Branch: renamed") {
+            return eprintln!("{}", err.to_string().red());
+        }
+        return println!("Branch '{}' renamed to '{}'", old, new);
+    }
+
+    if let Err(message) = move_branch(&vit_dir, old, new, force, true) {
+        return eprintln!("{}", message.red());
+    }
+
+    println!("Branch '{}' renamed to '{}'", old, new);
 }
 
-pub fn branch(name: Option<String>, is_delete: bool) {
+/// Copy a branch and its reflog, without `old` the current branch is copied.
+pub fn branch_copy(old: Option<&str>, new: &str, force: bool) {
     let vit_dir = env::current_dir().unwrap().join(".vit");
     if !vit_dir.exists() {
         return eprintln!("vit not initialized");
     }
 
-    let heads_dir = vit_dir.join("refs/heads");
-    let head_ref = fs::read_to_string(vit_dir.join("HEAD")).unwrap(); // "ref: refs/heads/main"
-    let current_branch_ref = head_ref.trim_start_matches("ref: ").trim();
-
-    if let Some(branch_name) = name {
-        let current_commit = fs::read_to_string(vit_dir.join(current_branch_ref)).unwrap();
-
-        let new_branch_path = heads_dir.join(&branch_name);
-
-        let does_exists = new_branch_path.exists();
-        if is_delete {
-            if !does_exists {
-                return eprintln!("{} {}", &branch_name.red(), "Branch does not exist!".red());
-            }
-            if Path::new(vit_dir.join(current_branch_ref).as_path())
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .to_string()
-                == branch_name
-            {
-                return eprintln!("{}", "Can not delete active branch".red());
-            }
-
-            fs::remove_file(new_branch_path).unwrap();
-            println!("Branch '{}' deleted", branch_name);
+    let current_ref = RefStore::new(&vit_dir).head_branch().unwrap_or_default();
+    let old = old.unwrap_or(current_ref.trim_start_matches("refs/heads/"));
+
+    match move_branch(&vit_dir, old, new, force, false) {
+        Ok(()) => println!("Branch '{}' copied to '{}'", old, new),
+        Err(message) => eprintln!("{}", message.red()),
+    }
+}
+
+pub fn branch_list(verbose: bool, filter: &BranchFilter) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit not initialized");
+    }
+
+    let objects_dir = vit_dir.join("objects");
+    let refs = RefStore::new(&vit_dir);
+    let current_ref = refs.head_branch().unwrap_or_default();
+
+    let resolve_ancestors =
+        |revision: &Option<String>| -> Result<Option<HashSet<[u8; 32]>>, String> {
+            let Some(revision) = revision else {
+                return Ok(None);
+            };
+            let hash = resolve_revision(&vit_dir, revision).map_err(|err| err.to_string())?;
+            ancestors(&objects_dir, hash)
+                .map(Some)
+                .map_err(|err| err.to_string())
+        };
+    let (merged, no_merged) = match (
+        resolve_ancestors(&filter.merged),
+        resolve_ancestors(&filter.no_merged),
+    ) {
+        (Ok(merged), Ok(no_merged)) => (merged, no_merged),
+        (Err(message), _) | (_, Err(message)) => return eprintln!("{}", message.red()),
+    };
+    let contains = match filter
+        .contains
+        .as_deref()
+        .map(|revision| resolve_revision(&vit_dir, revision))
+    {
+        Some(Ok(hash)) => Some(hash),
+        Some(Err(err)) => return eprintln!("{}", err.to_string().red()),
+        None => None,
+    };
+
+    let mut branches = Vec::new();
+    for (branch_ref, tip) in refs.list("refs/heads/") {
+        let name = branch_ref.trim_start_matches("refs/heads/").to_string();
+
+        if merged.as_ref().is_some_and(|merged| !merged.contains(&tip))
+            || no_merged
+                .as_ref()
+                .is_some_and(|merged| merged.contains(&tip))
+        {
+            continue;
+        }
+        if let Some(commit) = contains
+            && !ancestors(&objects_dir, tip).is_ok_and(|reachable| reachable.contains(&commit))
+        {
+            continue;
+        }
+
+        branches.push((name, tip));
+    }
+
+    let width = branches
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (name, tip) in branches {
+        let is_current = current_ref == format!("refs/heads/{}", name);
+
+        if verbose {
+            let subject = read_commit_file(&loose_object_path(&objects_dir, &tip))
+                .map(|commit| commit.message.lines().next().unwrap_or("").to_string())
+                .unwrap_or_default();
+            let marker = if is_current { "*" } else { " " };
+            let padded = format!("{:<width$}", name, width = width);
+            let padded = if is_current {
+                padded.green().to_string()
+            } else {
+                padded
+            };
+
+            println!(
+                "{} {} {} {}",
+                marker.green(),
+                padded,
+                hex::encode(tip)[..7].yellow(),
+                subject
+            );
+        } else if is_current {
+            println!("{} {}", name, "*".green());
         } else {
-            if does_exists {
-                return eprintln!("{} {}", &branch_name.red(), "Branch already exists!".red());
-            }
+            println!("{}", name);
+        }
+    }
+}
+
+/// Point `new` at the tip of `old` and take the reflog along, `old` is
+/// removed when renaming.
+fn move_branch(
+    vit_dir: &Path,
+    old: &str,
+    new: &str,
+    force: bool,
+    rename: bool,
+) -> Result<(), String> {
+    let refs = RefStore::new(vit_dir);
+    let old_ref = format!("refs/heads/{}", old);
+    let new_ref = format!("refs/heads/{}", new);
+
+    let Some(tip) = refs.read(&old_ref) else {
+        return Err(format!("Branch '{}' does not exist!", old));
+    };
+    if !is_valid_ref_short_name(new) {
+        return Err(format!("'{}' is not a valid branch name!", new));
+    }
+    if old_ref == new_ref {
+        return Ok(());
+    }
 
-            fs::write(new_branch_path, current_commit).unwrap();
-            println!("Branch '{}' created", branch_name);
+    if refs.read(&new_ref).is_some() {
+        if !force {
+            return Err(format!("Branch '{}' already exists!", new));
         }
-    } else {
-        for entry in fs::read_dir(heads_dir).unwrap() {
-            let path = entry.unwrap().path();
-            if let Some(name) = path.file_name() {
-                if Path::new(vit_dir.join(current_branch_ref).as_path())
-                    .file_name()
-                    .unwrap()
-                    == name
-                {
-                    println!("{} {}", name.to_str().unwrap(), "*".green());
-                } else {
-                    println!("{}", name.to_str().unwrap());
-                }
-            }
+        if refs.head_branch().as_deref() == Some(new_ref.as_str()) {
+            return Err(format!("Can not force update the active branch '{}'", new));
         }
     }
+    // Renaming `a` to `a/b` or back is fine, the old ref goes away first
+    if let Some(existing) = refs.conflicting(&new_ref)
+        && !(rename && existing == old_ref)
+    {
+        return Err(format!(
+            "Can not create branch, ref {} already exists!",
+            existing
+        ));
+    }
+
+    let action = if rename { "renamed" } else { "copied" };
+    let reflog_message = format!("Branch: {} {} to {}", action, old_ref, new_ref);
+    if rename {
+        return refs
+            .rename(&old_ref, &new_ref, &reflog_message)
+            .map_err(|err| err.to_string());
+    }
+
+    if let Ok(log) = fs::read(vit_dir.join("logs").join(&old_ref)) {
+        let new_log = vit_dir.join("logs").join(&new_ref);
+        fs::create_dir_all(new_log.parent().unwrap()).unwrap();
+        fs::write(new_log, log).unwrap();
+    }
+    refs.update(&new_ref, &tip, None, &reflog_message)
+        .map_err(|err| err.to_string())
 }
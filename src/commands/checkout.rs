@@ -1,10 +1,11 @@
 use crate::utils::{
-    self, Action, FileChange, FileStatus, FileType, IndexEntry, calculate_diff,
-    decompress_file_content, parse_tree_entries, read_commit_file, read_index, write_index,
+    self, Action, CommitEntry, FileChange, FileStatus, FileType, IndexEntry, RefStore,
+    calculate_diff, loose_object_path, open_object, parse_commit_data, parse_tree_entries,
+    read_index, read_object_by_hash, write_blob_to_file, write_index,
 };
 use clap::{Arg, Command};
 use colored::Colorize;
-use std::{env, fs, path::Path};
+use std::{env, fs, io, path::Path};
 
 pub fn get_checkout_command() -> Command {
     Command::new("checkout")
@@ -23,45 +24,65 @@ pub fn checkout(name: &str) {
         return eprintln!("vit repository not initialized!");
     }
 
-    let heads_dir = vit_dir.join("refs/heads");
-    let head_path = vit_dir.join("HEAD");
-    let head_ref = fs::read_to_string(&head_path).unwrap();
-    let current_branch_ref = head_ref.trim_start_matches("ref: ").trim();
+    let refs = RefStore::new(&vit_dir);
+    let current_branch_ref = refs.head_branch().unwrap_or_else(|| "HEAD".to_string());
     let mut index_entries: Vec<IndexEntry> = read_index().unwrap();
 
-    if Path::new(vit_dir.join(current_branch_ref).as_path())
-        .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .to_string()
-        == name
-    {
+    let branch_ref = format!("refs/heads/{}", name);
+    if current_branch_ref == branch_ref {
         return eprintln!("{}", "Branch is already Active!".red());
     }
 
-    let current_commit_hash = fs::read_to_string(vit_dir.join(current_branch_ref)).unwrap();
-    // Read commit object and get tree hash
-    let current_commit_path = vit_dir
-        .join("objects")
-        .join(&current_commit_hash[..2])
-        .join(&current_commit_hash[2..]);
-    let current_commit_entry = read_commit_file(&current_commit_path).unwrap();
-    let current_tree_hash = hex::encode(current_commit_entry.tree);
-
-    // Get commit hash from current branch
-    let commit_hash: String = if heads_dir.join(name).exists() {
-        fs::write(&head_path, format!("ref: refs/heads/{}", name)).unwrap();
-        fs::read_to_string(heads_dir.join(name)).unwrap()
-    } else {
+    let Some(target_hash) = refs.read(&branch_ref) else {
         return eprintln!("Branch does not exist");
     };
+    // An unborn branch has no commit yet, HEAD only names it
+    let head_hash = refs.read("HEAD");
+
+    // Everything needed is read before HEAD moves, a broken target must
+    // leave HEAD where it was
+    let objects_path = vit_dir.join("objects");
+    let commit_entry = match read_commit(&objects_path, &target_hash) {
+        Ok(entry) => entry,
+        Err(err) => {
+            return eprintln!(
+                "fatal: unable to read commit {}: {}",
+                hex::encode(target_hash),
+                err
+            );
+        }
+    };
+    if let Err(err) = open_object(&loose_object_path(&objects_path, &commit_entry.tree)) {
+        return eprintln!(
+            "fatal: unable to read tree {}: {}",
+            hex::encode(commit_entry.tree),
+            err
+        );
+    }
+    let current_commit_entry = match head_hash.map(|hash| read_commit(&objects_path, &hash)) {
+        Some(Ok(entry)) => Some(entry),
+        Some(Err(err)) => return eprintln!("fatal: unable to read HEAD: {}", err),
+        None => None,
+    };
 
-    // Read commit object and get tree hash
-    let commit_path = vit_dir
-        .join("objects")
-        .join(&commit_hash[..2])
-        .join(&commit_hash[2..]);
-    let commit_entry = read_commit_file(&commit_path).unwrap();
+    let from = current_branch_ref.trim_start_matches("refs/heads/");
+    let message = format!("checkout: moving from {} to {}", from, name);
+    if let Err(err) = refs.set_symbolic("HEAD", &branch_ref, &message) {
+        return eprintln!("fatal: {}", err);
+    }
+
+    let (Some(head_hash), Some(current_commit_entry)) = (head_hash, current_commit_entry) else {
+        // Nothing is checked out yet, the whole tree is written out
+        let tree_hash = hex::encode(commit_entry.tree);
+        let tree_path = objects_path.join(&tree_hash[..2]).join(&tree_hash[2..]);
+        restore_tree(&tree_path, &current_dir, &objects_path, &mut index_entries);
+
+        write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
+        println!("Checkout to branch --> {}", &name);
+        return run_post_checkout(&vit_dir, &[0u8; 32], &target_hash);
+    };
+
+    let current_tree_hash = hex::encode(current_commit_entry.tree);
 
     let tree_hash = hex::encode(commit_entry.tree);
     let base_path = env::current_dir().unwrap();
@@ -78,11 +99,11 @@ pub fn checkout(name: &str) {
     for change in &list_of_changes {
         match &change.action {
             Action::Delete => {
-                delete_files(&change, &current_dir, &mut index_entries);
+                delete_files(change, &current_dir, &mut index_entries);
             }
             Action::Restore => {
                 restore_file(
-                    &change,
+                    change,
                     &current_dir,
                     &vit_dir.join("objects"),
                     &mut index_entries,
@@ -90,7 +111,7 @@ pub fn checkout(name: &str) {
             }
             Action::Create => {
                 create_files(
-                    &change,
+                    change,
                     &current_dir,
                     &vit_dir.join("objects"),
                     &mut index_entries,
@@ -101,6 +122,26 @@ pub fn checkout(name: &str) {
 
     write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
     println!("Checkout to branch --> {}", &name);
+    run_post_checkout(&vit_dir, &head_hash, &target_hash);
+}
+
+/// The commit `hash`, an error when it is missing or not a commit.
+fn read_commit(objects_path: &Path, hash: &[u8; 32]) -> io::Result<CommitEntry> {
+    let (object_type, content) = read_object_by_hash(objects_path, hash)?;
+    if object_type != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected commit, found {}", object_type),
+        ));
+    }
+    parse_commit_data(&content)
+}
+
+/// Tell the post-checkout hook HEAD moved from `old` to `new`, the trailing
+/// 1 marks a branch checkout. The checkout is done, so the result is ignored.
+pub fn run_post_checkout(vit_dir: &Path, old: &[u8; 32], new: &[u8; 32]) {
+    let (old, new) = (hex::encode(old), hex::encode(new));
+    let _ = utils::run_hook(vit_dir, "post-checkout", &[&old, &new, "1"]);
 }
 
 pub fn restore_tree(
@@ -109,7 +150,7 @@ pub fn restore_tree(
     objects_path: &Path,
     index_entries: &mut Vec<IndexEntry>,
 ) {
-    let tree_entries = parse_tree_entries(&tree_path).unwrap();
+    let tree_entries = parse_tree_entries(tree_path).unwrap();
 
     for entry in tree_entries {
         let mode = entry.mode;
@@ -132,21 +173,20 @@ pub fn restore_tree(
             restore_tree(&sub_tree_path, &sub_base_path, objects_path, index_entries);
         } else {
             let blob_path = objects_path.join(&hash_str[..2]).join(&hash_str[2..]);
-            let decompressed = decompress_file_content(&blob_path).unwrap();
-            let blob_data = &decompressed[decompressed.iter().position(|&b| b == 0).unwrap() + 1..];
 
             // Make sure all parent directories are present
             fs::create_dir_all(name.parent().unwrap()).unwrap();
 
             // Write file data
-            fs::write(&name, blob_data).unwrap();
+            write_blob_to_file(&blob_path, &name).unwrap();
 
             // Update the index entry
             if let Some(i_entry) = index_entries.iter_mut().find(|i| i.path == file_path) {
                 i_entry.sha256 = entry.sha256;
                 i_entry.status = FileStatus::Unchanged;
             } else {
-                let mut new_entry = IndexEntry::create(&file_path);
+                let metadata = fs::metadata(&name).unwrap();
+                let mut new_entry = IndexEntry::from_metadata(&file_path, &metadata, entry.sha256);
                 new_entry.status = FileStatus::Unchanged;
                 index_entries.push(new_entry);
             }
@@ -158,17 +198,15 @@ pub fn restore_file(
     change: &FileChange,
     current_dir: &Path,
     objects_path: &Path,
-    index_entries: &mut Vec<IndexEntry>,
+    index_entries: &mut [IndexEntry],
 ) {
     let file_path = current_dir.join(&change.path);
-    let hash_str = hex::encode(&change.sha256);
+    let hash_str = hex::encode(change.sha256);
 
     let blob_path = objects_path.join(&hash_str[..2]).join(&hash_str[2..]);
-    let decompressed = decompress_file_content(&blob_path).unwrap();
-    let blob_data = &decompressed[decompressed.iter().position(|&b| b == 0).unwrap() + 1..];
 
     // Write file data
-    fs::write(&file_path, blob_data).unwrap();
+    write_blob_to_file(&blob_path, &file_path).unwrap();
 
     // Update the index entry
     if let Some(i_entry) = index_entries.iter_mut().find(|i| i.path == change.path) {
@@ -193,7 +231,7 @@ fn delete_files(change: &FileChange, current_dir: &Path, index_entries: &mut Vec
         }
         FileType::Tree => {
             let dir_name = &change.path;
-            let list_of_files = utils::expand_paths(&[change.path.clone()]);
+            let list_of_files = utils::expand_paths(std::slice::from_ref(&change.path));
 
             for file in list_of_files {
                 let file_path = current_dir.join(&file);
@@ -218,22 +256,21 @@ fn create_files(
     match &change.file_type {
         FileType::Blob => {
             let file_path = current_dir.join(&change.path);
-            let hash_str = hex::encode(&change.sha256);
+            let hash_str = hex::encode(change.sha256);
 
             let blob_path = objects_path.join(&hash_str[..2]).join(&hash_str[2..]);
-            let decompressed = decompress_file_content(&blob_path).unwrap();
-            let blob_data = &decompressed[decompressed.iter().position(|&b| b == 0).unwrap() + 1..];
 
             // Write file data
-            fs::write(&file_path, blob_data).unwrap();
+            write_blob_to_file(&blob_path, &file_path).unwrap();
 
             // Update the index entry
-            let mut new_entry = IndexEntry::create(&change.path);
+            let metadata = fs::metadata(&file_path).unwrap();
+            let mut new_entry = IndexEntry::from_metadata(&change.path, &metadata, change.sha256);
             new_entry.status = FileStatus::Unchanged;
             index_entries.push(new_entry);
         }
         FileType::Tree => {
-            let hash_str = hex::encode(&change.sha256);
+            let hash_str = hex::encode(change.sha256);
             let tree_path = objects_path.join(&hash_str[..2]).join(&hash_str[2..]);
             let bash_path = current_dir.join(&change.path);
 
@@ -1,7 +1,13 @@
-use clap::{Arg, Command};
-use std::{env, fs};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use colored::Colorize;
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::Path,
+};
 
-use crate::utils;
+use super::{collect_status, stage_tracked_files};
+use crate::utils::{self, CleanupMode, CommitEntry, TreeChange};
 
 pub fn get_commit_command() -> Command {
     Command::new("commit")
@@ -12,51 +18,275 @@ pub fn get_commit_command() -> Command {
                 .long("message")
                 .required(false)
                 .value_name("MESSAGE")
+                .conflicts_with("file")
                 .help("Specify the commit message"),
         )
+        .arg(
+            Arg::new("file")
+                .short('F')
+                .long("file")
+                .value_name("FILE")
+                .help("Take the commit message from FILE, - reads standard input"),
+        )
+        .arg(
+            Arg::new("all")
+                .short('a')
+                .long("all")
+                .action(ArgAction::SetTrue)
+                .help("Stage modified and deleted tracked files first"),
+        )
+        .arg(
+            Arg::new("amend")
+                .long("amend")
+                .action(ArgAction::SetTrue)
+                .help("Replace the last commit instead of adding a new one"),
+        )
+        .arg(
+            Arg::new("no-edit")
+                .long("no-edit")
+                .action(ArgAction::SetTrue)
+                .requires("amend")
+                .help("Keep the message of the amended commit"),
+        )
+        .arg(
+            Arg::new("allow-empty")
+                .long("allow-empty")
+                .action(ArgAction::SetTrue)
+                .help("Record a commit that changes nothing"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("Show what would be committed without committing"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::SetTrue)
+                .help("Show the staged diff below the message in the editor"),
+        )
+        .arg(
+            Arg::new("cleanup")
+                .long("cleanup")
+                .value_name("MODE")
+                .value_parser(["strip", "whitespace", "verbatim", "scissors"])
+                .help("How to tidy up the message, defaults to commit.cleanup"),
+        )
+        .arg(
+            Arg::new("no-verify")
+                .short('n')
+                .long("no-verify")
+                .action(ArgAction::SetTrue)
+                .help("Skip the pre-commit and commit-msg hooks"),
+        )
+}
+
+#[derive(Debug, Default)]
+pub struct CommitOptions {
+    pub message: Option<String>,
+    pub message_file: Option<String>,
+    pub all: bool,
+    pub amend: bool,
+    pub no_edit: bool,
+    pub allow_empty: bool,
+    pub dry_run: bool,
+    pub verbose: bool,
+    pub cleanup: Option<String>,
+    pub no_verify: bool,
 }
 
-pub fn commit(message: Option<String>) {
+impl CommitOptions {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        CommitOptions {
+            message: matches.get_one::<String>("message").cloned(),
+            message_file: matches.get_one::<String>("file").cloned(),
+            all: matches.get_flag("all"),
+            amend: matches.get_flag("amend"),
+            no_edit: matches.get_flag("no-edit"),
+            allow_empty: matches.get_flag("allow-empty"),
+            dry_run: matches.get_flag("dry-run"),
+            verbose: matches.get_flag("verbose"),
+            cleanup: matches.get_one::<String>("cleanup").cloned(),
+            no_verify: matches.get_flag("no-verify"),
+        }
+    }
+}
+
+/// Record the index as a new commit on HEAD, or in place of HEAD with
+/// `amend`. A commit that would not change the tree of its first parent
+/// is refused unless `allow_empty` is set.
+///
+/// The pre-commit hook runs first and commit-msg gets the message in
+/// `.vit/COMMIT_EDITMSG`, either failing aborts the commit unless
+/// `no_verify` is set. post-commit runs once HEAD has moved.
+pub fn commit(options: CommitOptions) {
     let current_dir = env::current_dir().unwrap();
     let vit_dir = current_dir.join(".vit");
     if !vit_dir.exists() {
         return eprintln!("vit repository not initialized!");
     }
 
-    let commit_message = match message {
-        Some(msg) => msg,
-        None => utils::get_commit_message_from_editor("Updated:"),
+    // Asked before the message is written, not after
+    let identity = match utils::get_identity() {
+        Ok(identity) => identity,
+        Err(err) => return eprintln!("{}", err),
+    };
+
+    let objects_path = vit_dir.join("objects");
+    let refs = utils::RefStore::new(&vit_dir);
+    let head = refs.read("HEAD");
+
+    // An amended commit is replaced, its parents become the new parents
+    let amended = match head.filter(|_| options.amend) {
+        Some(hash) => Some(read_commit(&objects_path, &hash)),
+        None if options.amend => return eprintln!("fatal: You have nothing to amend."),
+        None => None,
+    };
+    let parents: Vec<[u8; 32]> = match &amended {
+        Some(entry) => entry.parents.clone(),
+        None => head.into_iter().collect(),
     };
+    let parent_tree = parents
+        .first()
+        .map(|parent| read_commit(&objects_path, parent).tree);
 
-    if commit_message.is_empty() {
-        return;
+    let mut index_entries = utils::read_index().unwrap();
+    if options.all {
+        stage_tracked_files(&vit_dir, &mut index_entries);
     }
 
+    if options.dry_run {
+        // The index is left alone, even with the files staged by `all`
+        let tree_hash = utils::build_tree(&index_entries);
+        let changes = utils::diff_trees(&objects_path, parent_tree.as_ref(), Some(&tree_hash));
+        return print_changes(&changes);
+    }
+
+    if options.all {
+        utils::write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
+    }
+
+    if !options.no_verify && !utils::run_hook(&vit_dir, "pre-commit", &[]).unwrap_or(false) {
+        return eprintln!("pre-commit hook failed, commit aborted");
+    }
+
+    // The pre-commit hook may have staged more
     let mut index_entries = utils::read_index().unwrap();
-    let head_ref = fs::read_to_string(vit_dir.join("HEAD")).unwrap();
-    let current_branch_ref = head_ref.trim_start_matches("ref: ").trim();
-    let commit_ref = vit_dir.join(current_branch_ref);
+    let tree_hash = utils::build_tree(&index_entries);
+    let changes = utils::diff_trees(&objects_path, parent_tree.as_ref(), Some(&tree_hash));
+    if changes.is_empty() && !options.allow_empty {
+        if amended.is_some() {
+            return eprintln!(
+                "You asked to amend the most recent commit, but doing so would make it empty. Use --allow-empty to amend it anyway."
+            );
+        }
+        return eprintln!(
+            "nothing to commit (use \"vit add\" to stage changes, or --allow-empty to commit anyway)"
+        );
+    }
+
+    let cleanup = match options
+        .cleanup
+        .or_else(|| utils::get_config("commit.cleanup"))
+    {
+        Some(value) => match CleanupMode::parse(&value) {
+            Some(mode) => Some(mode),
+            None => return eprintln!("fatal: Invalid cleanup mode {}", value),
+        },
+        None => None,
+    };
+
+    // Only a message written in the editor loses its comments by default
+    let mut template = None;
+    let (message, edited) = match (options.message, &options.message_file, &amended) {
+        (Some(message), _, _) => (message, false),
+        (None, Some(file), _) => match read_message_file(file) {
+            Ok(message) => (message, false),
+            Err(err) => return eprintln!("fatal: could not read log file '{}': {}", file, err),
+        },
+        (None, None, Some(entry)) if options.no_edit => (entry.message.clone(), false),
+        (None, None, amended) => {
+            let initial = match amended {
+                Some(entry) => entry.message.clone(),
+                None => match read_commit_template() {
+                    Ok(content) => {
+                        template = content.clone();
+                        content.unwrap_or_default()
+                    }
+                    Err(message) => return eprintln!("fatal: {}", message),
+                },
+            };
+
+            let mode = cleanup.unwrap_or(CleanupMode::Strip);
+            let comments = template_comments(
+                &refs,
+                &objects_path,
+                &changes,
+                &index_entries,
+                mode,
+                options.verbose,
+            );
+            match utils::get_commit_message_from_editor(&format!("{}\n{}", initial, comments)) {
+                Ok(message) => (message, true),
+                Err(message) => return eprintln!("error: {}", message),
+            }
+        }
+    };
+
+    let mode = cleanup.unwrap_or(if edited {
+        CleanupMode::Strip
+    } else {
+        CleanupMode::Whitespace
+    });
+    let mut commit_message =
+        utils::cleanup_commit_message(&message, mode, edited && options.verbose);
 
-    let prev_commit_hash = if commit_ref.exists() {
-        let prev_hash_str = fs::read_to_string(&commit_ref).unwrap();
-        hex::decode(prev_hash_str.trim()).unwrap()
+    if let Some(template) = template
+        && commit_message == utils::cleanup_commit_message(&template, mode, false)
+    {
+        return eprintln!("Aborting commit; you did not edit the message from the template.");
+    }
+    if commit_message.trim().is_empty() {
+        return eprintln!("Aborting commit due to empty commit message.");
+    }
+
+    if !options.no_verify {
+        // The hook may rewrite the message in place
+        let message_path = vit_dir.join("COMMIT_EDITMSG");
+        fs::write(&message_path, &commit_message).unwrap();
+        let hook_args = [message_path.to_str().unwrap()];
+        if !utils::run_hook(&vit_dir, "commit-msg", &hook_args).unwrap_or(false) {
+            return eprintln!("commit-msg hook failed, commit aborted");
+        }
+
+        commit_message = fs::read_to_string(&message_path).unwrap();
+        if commit_message.trim().is_empty() {
+            return eprintln!("Aborting commit due to empty commit message.");
+        }
+    }
+
+    let commit_hash: [u8; 32] = match &amended {
+        Some(original) => {
+            utils::build_amended_commit(tree_hash, &parents, &commit_message, original, &identity)
+        }
+        None => utils::build_commit(tree_hash, &parents, &commit_message, &identity),
+    };
+
+    let subject = commit_message.lines().next().unwrap_or("");
+    let reflog_message = if amended.is_some() {
+        format!("commit (amend): {}", subject)
+    } else if parents.is_empty() {
+        format!("commit (initial): {}", subject)
     } else {
-        vec![0u8; 32]
+        format!("commit: {}", subject)
     };
-    let tree_hash = utils::build_tree(&index_entries);
-    let commit_hash: [u8; 32] = utils::build_commit(tree_hash, &prev_commit_hash, &commit_message);
-
-    utils::update_head(commit_hash, &commit_ref);
-    let author_name = "Vivek";
-    let author_email = "vivek@example.com";
-    utils::write_log_entry(
-        &prev_commit_hash,
-        &commit_hash,
-        author_name,
-        author_email,
-        &commit_message,
-        current_branch_ref,
-    );
+    // Refuse to move HEAD if someone else committed in the meantime
+    let expected = head.unwrap_or([0u8; 32]);
+    if let Err(err) = refs.update("HEAD", &commit_hash, Some(expected), &reflog_message) {
+        return eprintln!("{}", err);
+    }
 
     index_entries.retain(|entry| entry.status != utils::FileStatus::Deleted);
     for entry in index_entries.iter_mut() {
@@ -64,5 +294,132 @@ pub fn commit(message: Option<String>) {
     }
 
     utils::write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
-    println!("Changes commited successfully")
+    println!("Changes commited successfully");
+
+    // Too late to stop anything, the outcome does not matter
+    let _ = utils::run_hook(&vit_dir, "post-commit", &[]);
+}
+
+fn read_commit(objects_path: &Path, hash: &[u8; 32]) -> CommitEntry {
+    let (_, content) = utils::read_object_by_hash(objects_path, hash).unwrap();
+    utils::parse_commit_data(&content).unwrap()
+}
+
+/// The message given with `-F`, `-` is standard input.
+fn read_message_file(file: &str) -> io::Result<String> {
+    if file == "-" {
+        let mut message = String::new();
+        io::stdin().read_to_string(&mut message)?;
+        return Ok(message);
+    }
+    fs::read_to_string(file)
+}
+
+/// The content of the `commit.template` file, if one is set.
+fn read_commit_template() -> Result<Option<String>, String> {
+    let Some(path) = utils::Config::load().get_path("commit.template") else {
+        return Ok(None);
+    };
+
+    fs::read_to_string(&path).map(Some).map_err(|err| {
+        format!(
+            "could not read commit message template '{}': {}",
+            path.display(),
+            err
+        )
+    })
+}
+
+/// The `#` lines below the message in the editor: instructions, what is
+/// and is not staged and, with `verbose`, the staged diff under the
+/// scissors line.
+fn template_comments(
+    refs: &utils::RefStore,
+    objects_path: &Path,
+    changes: &[TreeChange],
+    index_entries: &[utils::IndexEntry],
+    mode: CleanupMode,
+    verbose: bool,
+) -> String {
+    let mut out =
+        String::from("# Please enter the commit message for your changes. Lines starting\n");
+    if mode == CleanupMode::Strip {
+        out.push_str("# with '#' will be ignored, and an empty message aborts the commit.\n");
+    } else {
+        out.push_str("# with '#' will be kept; you may remove them yourself if you want to.\n");
+        out.push_str("# An empty message aborts the commit.\n");
+    }
+    out.push_str("#\n");
+
+    match refs.current_branch() {
+        Some(branch) => out.push_str(&format!("# On branch {}\n", branch)),
+        None => out.push_str("# HEAD detached\n"),
+    }
+
+    let lists = collect_status(index_entries);
+    let staged: Vec<(&str, String)> = changes
+        .iter()
+        .map(|change| (change_label(change), change.path.clone()))
+        .collect();
+    for (title, files) in [
+        ("Changes to be committed:", &staged),
+        ("Changes not staged for commit:", &lists.unstaged),
+    ] {
+        if !files.is_empty() {
+            out.push_str(&format!("# {}\n", title));
+            for (label, path) in files {
+                out.push_str(&format!("#\t{}   {}\n", label, path));
+            }
+            out.push_str("#\n");
+        }
+    }
+    if !lists.untracked.is_empty() {
+        out.push_str("# Untracked files:\n");
+        for path in &lists.untracked {
+            out.push_str(&format!("#\t{}\n", path));
+        }
+        out.push_str("#\n");
+    }
+
+    if verbose {
+        out.push_str(utils::SCISSORS_LINE);
+        out.push_str("\n# Do not modify or remove the line above.\n");
+        out.push_str("# Everything below it will be ignored.\n");
+        for change in changes {
+            let old = change.old.map(|hash| read_blob(objects_path, &hash));
+            let new = change.new.map(|hash| read_blob(objects_path, &hash));
+            out.push_str(&utils::unified_diff(
+                &change.path,
+                old.as_deref(),
+                new.as_deref(),
+                utils::DEFAULT_CONTEXT,
+            ));
+        }
+    }
+
+    out
+}
+
+fn read_blob(objects_path: &Path, hash: &[u8; 32]) -> Vec<u8> {
+    utils::read_object_by_hash(objects_path, hash).unwrap().1
+}
+
+fn change_label(change: &TreeChange) -> &'static str {
+    match (change.old, change.new) {
+        (None, _) => "new file:",
+        (_, None) => "deleted: ",
+        _ => "modified:",
+    }
+}
+
+/// What `--dry-run` shows, the files the commit would change.
+fn print_changes(changes: &[TreeChange]) {
+    if changes.is_empty() {
+        return println!("nothing to commit");
+    }
+
+    println!("Changes to be committed:");
+    for change in changes {
+        println!("  {} {}", change_label(change).green(), change.path.green());
+    }
 }
@@ -0,0 +1,183 @@
+use crate::utils::{
+    Config, ConfigScope, expand_config_path, parse_config_bool, parse_config_int, set_config_value,
+    unset_config_value,
+};
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use std::path::PathBuf;
+
+pub fn get_config_command() -> Command {
+    Command::new("config")
+        .about("Get and set repository or global options")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(
+            with_scope_args(Command::new("get").about("Print the value of a key"))
+                .arg(Arg::new("key").required(true).help("Key such as user.name"))
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .help("Print every value of a multi-valued key"),
+                )
+                .arg(
+                    Arg::new("type")
+                        .long("type")
+                        .value_parser(["bool", "int", "path"])
+                        .help("Check the value and print it in canonical form"),
+                ),
+        )
+        .subcommand(
+            with_scope_args(
+                Command::new("set").about("Set a key, in the repository config by default"),
+            )
+            .arg(Arg::new("key").required(true).help("Key such as user.name"))
+            .arg(Arg::new("value").required(true).help("New value"))
+            .arg(
+                Arg::new("add")
+                    .long("add")
+                    .action(ArgAction::SetTrue)
+                    .help("Add another value instead of replacing the last one"),
+            ),
+        )
+        .subcommand(
+            with_scope_args(Command::new("unset").about("Remove every value of a key"))
+                .arg(Arg::new("key").required(true).help("Key such as user.name")),
+        )
+        .subcommand(
+            with_scope_args(Command::new("list").about("List all keys and values")).arg(
+                Arg::new("show-scope")
+                    .long("show-scope")
+                    .action(ArgAction::SetTrue)
+                    .help("Prefix each line with the scope it comes from"),
+            ),
+        )
+}
+
+fn with_scope_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("global")
+                .long("global")
+                .action(ArgAction::SetTrue)
+                .help("Use ~/.vitconfig"),
+        )
+        .arg(
+            Arg::new("system")
+                .long("system")
+                .action(ArgAction::SetTrue)
+                .help("Use /etc/vitconfig"),
+        )
+        .arg(
+            Arg::new("local")
+                .long("local")
+                .action(ArgAction::SetTrue)
+                .help("Use .vit/config of the repository"),
+        )
+        .group(
+            ArgGroup::new("scope")
+                .args(["global", "system", "local"])
+                .multiple(false),
+        )
+}
+
+/// The scope picked with `--global`, `--system` or `--local`.
+pub fn config_scope_from_matches(matches: &ArgMatches) -> Option<ConfigScope> {
+    if matches.get_flag("global") {
+        Some(ConfigScope::Global)
+    } else if matches.get_flag("system") {
+        Some(ConfigScope::System)
+    } else if matches.get_flag("local") {
+        Some(ConfigScope::Local)
+    } else {
+        None
+    }
+}
+
+/// Print the value of `key`, from every scope unless one is given.
+pub fn config_get(key: &str, scope: Option<ConfigScope>, all: bool, value_type: Option<&str>) {
+    let config = load(scope);
+    let values = if all {
+        config.get_all(key)
+    } else {
+        config.get(key).into_iter().collect()
+    };
+
+    if values.is_empty() {
+        return eprintln!("error: key '{}' is not set", key);
+    }
+
+    for value in values {
+        let typed = match value_type {
+            Some("bool") => parse_config_bool(value).map(|value| value.to_string()),
+            Some("int") => parse_config_int(value).map(|value| value.to_string()),
+            Some("path") => Some(expand_config_path(value).display().to_string()),
+            _ => Some(value.to_string()),
+        };
+
+        match typed {
+            Some(value) => println!("{}", value),
+            None => {
+                return eprintln!(
+                    "fatal: bad {} config value '{}' for '{}'",
+                    value_type.unwrap_or(""),
+                    value,
+                    key
+                );
+            }
+        }
+    }
+}
+
+pub fn config_set(key: &str, value: &str, scope: Option<ConfigScope>, add: bool) {
+    let Some(path) = scope_path(scope) else {
+        return;
+    };
+
+    if let Err(err) = set_config_value(&path, key, value, add) {
+        eprintln!("error: {}", err);
+    }
+}
+
+pub fn config_unset(key: &str, scope: Option<ConfigScope>) {
+    let Some(path) = scope_path(scope) else {
+        return;
+    };
+
+    match unset_config_value(&path, key) {
+        Ok(0) => eprintln!("error: key '{}' is not set", key),
+        Ok(_) => {}
+        Err(err) => eprintln!("error: {}", err),
+    }
+}
+
+pub fn config_list(scope: Option<ConfigScope>, show_scope: bool) {
+    for entry in load(scope).entries() {
+        if show_scope {
+            println!("{}\t{}={}", entry.scope.name(), entry.key, entry.value);
+        } else {
+            println!("{}={}", entry.key, entry.value);
+        }
+    }
+}
+
+fn load(scope: Option<ConfigScope>) -> Config {
+    match scope {
+        Some(scope) => Config::load_scope(scope),
+        None => Config::load(),
+    }
+}
+
+/// The file `set` and `unset` write to, the repository config by default.
+fn scope_path(scope: Option<ConfigScope>) -> Option<PathBuf> {
+    let scope = scope.unwrap_or(ConfigScope::Local);
+    let Some(path) = scope.path() else {
+        eprintln!("fatal: no {} config file to write to", scope.name());
+        return None;
+    };
+
+    if scope == ConfigScope::Local && !path.parent().is_some_and(|dir| dir.exists()) {
+        eprintln!("vit repository not initialized!");
+        return None;
+    }
+    Some(path)
+}
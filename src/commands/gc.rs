@@ -0,0 +1,43 @@
+use crate::utils;
+use clap::Command;
+use std::env;
+
+use super::{DEFAULT_PRUNE_EXPIRE, prune, reflog_expire};
+
+pub fn get_gc_command() -> Command {
+    Command::new("gc").about("Cleanup and optimize the repository")
+}
+
+pub fn gc() {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    // Old reflog entries would keep everything they ever pointed at alive
+    reflog_expire(None, true, &[]);
+
+    // One packed-refs file reads faster than a directory of tiny ref files
+    if let Err(err) = utils::RefStore::new(&vit_dir).pack_refs() {
+        return eprintln!("Unable to pack refs: {}", err);
+    }
+
+    let reachable = match utils::reachable_objects(&vit_dir) {
+        Ok(reachable) => reachable,
+        Err(err) => return eprintln!("Unable to compute reachable objects: {}", err),
+    };
+
+    // Only reachable objects go into the pack, the rest is left loose for prune
+    match utils::repack(&vit_dir.join("objects"), true, Some(&reachable)) {
+        Ok(Some(stats)) => println!(
+            "Packed {} objects ({} deltas) into {}",
+            stats.objects,
+            stats.deltas,
+            stats.pack_path.file_name().unwrap().to_string_lossy()
+        ),
+        Ok(None) => {}
+        Err(err) => return eprintln!("Unable to repack objects: {}", err),
+    }
+
+    prune(Some(DEFAULT_PRUNE_EXPIRE.to_string()), false);
+}
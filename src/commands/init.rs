@@ -1,11 +1,23 @@
-use clap::Command;
+use crate::utils::{get_config, is_valid_ref_short_name};
+use clap::{Arg, Command};
 use std::{env, io, path::PathBuf, fs};
 
+/// Branch HEAD names in a new repository unless told otherwise.
+pub const DEFAULT_INITIAL_BRANCH: &str = "main";
+
 pub fn get_init_command() -> Command {
-    Command::new("init").about("Initialize version_it")
+    Command::new("init").about("Initialize version_it").arg(
+        Arg::new("initial-branch")
+            .short('b')
+            .long("initial-branch")
+            .value_name("NAME")
+            .help("Name of the first branch (default: init.defaultBranch or main)"),
+    )
 }
 
-pub fn init() -> io::Result<()> {
+/// Create `.vit` with HEAD on an unborn branch, it only comes into
+/// existence with the first commit.
+pub fn init(initial_branch: Option<&str>) -> io::Result<()> {
     let path_to_vit: PathBuf = env::current_dir()?.join(".vit");
 
     if path_to_vit.exists() {
@@ -13,6 +25,15 @@ pub fn init() -> io::Result<()> {
         return Ok(());
     }
 
+    let initial_branch = initial_branch
+        .map(str::to_string)
+        .or_else(|| get_config("init.defaultBranch"))
+        .unwrap_or_else(|| DEFAULT_INITIAL_BRANCH.to_string());
+    if !is_valid_ref_short_name(&initial_branch) {
+        eprintln!("fatal: invalid initial branch name: '{}'", initial_branch);
+        return Ok(());
+    }
+
 		// Create Required Directories
 		fs::create_dir_all(path_to_vit.join("objects"))?;
     fs::create_dir_all(path_to_vit.join("refs/heads"))?;
@@ -23,11 +44,11 @@ pub fn init() -> io::Result<()> {
 
 		// Create Required Files
 		fs::write(path_to_vit.join("index"), "")?;
-		fs::write(path_to_vit.join("HEAD"), "ref: refs/heads/main\n")?;
+		fs::write(path_to_vit.join("HEAD"), format!("ref: refs/heads/{}\n", initial_branch))?;
     fs::write(path_to_vit.join("config"), "[core]\n\trepositoryformatversion = 0\n\tfilemode = true\n\tbare = false\n")?;
     fs::write(path_to_vit.join("description"), "Unnamed repository; edit this file 'description' to name the repository.\n")?;
 
     println!("Initialized empty Vit repository in {}", path_to_vit.display());
 
-    return Ok(());
+    Ok(())
 }
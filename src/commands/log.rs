@@ -1,16 +1,206 @@
-use std::{env, fs};
+use std::{collections::HashMap, env, fmt::Write, path::Path};
 
-use clap::Command;
-use colored::{Colorize, control::set_override};
-use pager::Pager;
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser};
+use colored::Colorize;
+use regex::Regex;
 
-use crate::utils::read_commit_file;
+use crate::utils::{
+    CommitEntry, CommitWalker, DateMode, GraphRenderer, GraphRows, Pathspec, RefStore, SortOrder,
+    TreeChange, ancestors, diff_trees, format_date, format_tree_patch, format_tree_stat, list_refs,
+    loose_object_path, parse_date, read_commit_file, resolve_revision, setup_pager, sort_commits,
+};
 
 pub fn get_log_command() -> Command {
-    Command::new("log").about("Display commit logs")
+    Command::new("log")
+        .about("Display commit logs")
+        .arg(
+            Arg::new("revisions")
+                .num_args(0..)
+                .allow_hyphen_values(false)
+                .help("Commits to start from, ranges like a..b and a...b, ^x to exclude x"),
+        )
+        .arg(
+            Arg::new("paths")
+                .num_args(1..)
+                .last(true)
+                .help("Only show commits touching these paths"),
+        )
+        .arg(
+            Arg::new("max-count")
+                .short('n')
+                .long("max-count")
+                .value_parser(value_parser!(usize))
+                .help("Limit the number of commits to show"),
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .visible_alias("after")
+                .help("Show commits more recent than a date"),
+        )
+        .arg(
+            Arg::new("until")
+                .long("until")
+                .visible_alias("before")
+                .help("Show commits older than a date"),
+        )
+        .arg(
+            Arg::new("author")
+                .long("author")
+                .help("Only show commits whose author matches a pattern"),
+        )
+        .arg(
+            Arg::new("grep")
+                .long("grep")
+                .help("Only show commits whose message matches a pattern"),
+        )
+        .arg(
+            Arg::new("oneline")
+                .long("oneline")
+                .action(ArgAction::SetTrue)
+                .help("Show each commit on a single line"),
+        )
+        .arg(
+            Arg::new("pretty")
+                .long("pretty")
+                .visible_alias("format")
+                .help("Output format: oneline, medium or format:<string> with %H %h %an %ae %ad %s %b %P"),
+        )
+        .arg(
+            Arg::new("date")
+                .long("date")
+                .help("Date format: default, relative, iso, short, unix or local"),
+        )
+        .arg(
+            Arg::new("stat")
+                .long("stat")
+                .action(ArgAction::SetTrue)
+                .help("Show a diffstat of every commit"),
+        )
+        .arg(
+            Arg::new("patch")
+                .short('p')
+                .long("patch")
+                .action(ArgAction::SetTrue)
+                .help("Show the diff of every commit"),
+        )
+        .arg(
+            Arg::new("graph")
+                .long("graph")
+                .action(ArgAction::SetTrue)
+                .help("Draw the commit history as a graph next to the log"),
+        )
+        .arg(
+            Arg::new("all")
+                .long("all")
+                .action(ArgAction::SetTrue)
+                .help("Show commits reachable from every ref, not only HEAD"),
+        )
+        .arg(
+            Arg::new("decorate")
+                .long("decorate")
+                .action(ArgAction::SetTrue)
+                .help("Show the branches and tags pointing at each commit"),
+        )
+        .arg(
+            Arg::new("topo-order")
+                .long("topo-order")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("date-order")
+                .help("Show no parent before its children and keep branches together"),
+        )
+        .arg(
+            Arg::new("date-order")
+                .long("date-order")
+                .action(ArgAction::SetTrue)
+                .help("Show no parent before its children, otherwise newest first"),
+        )
+        .arg(
+            Arg::new("reverse")
+                .long("reverse")
+                .action(ArgAction::SetTrue)
+                .help("Show the oldest commits first"),
+        )
 }
 
-pub fn log() {
+#[derive(Debug, Default)]
+pub struct LogOptions {
+    pub revisions: Vec<String>,
+    pub paths: Vec<String>,
+    pub max_count: Option<usize>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub author: Option<String>,
+    pub grep: Option<String>,
+    pub oneline: bool,
+    pub pretty: Option<String>,
+    pub date: Option<String>,
+    pub stat: bool,
+    pub patch: bool,
+    pub reverse: bool,
+    pub graph: bool,
+    pub all: bool,
+    pub decorate: bool,
+    pub topo_order: bool,
+    pub date_order: bool,
+}
+
+impl LogOptions {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        let strings = |id: &str| -> Vec<String> {
+            matches
+                .get_many::<String>(id)
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default()
+        };
+
+        LogOptions {
+            revisions: strings("revisions"),
+            paths: strings("paths"),
+            max_count: matches.get_one::<usize>("max-count").copied(),
+            since: matches.get_one::<String>("since").cloned(),
+            until: matches.get_one::<String>("until").cloned(),
+            author: matches.get_one::<String>("author").cloned(),
+            grep: matches.get_one::<String>("grep").cloned(),
+            oneline: matches.get_flag("oneline"),
+            pretty: matches.get_one::<String>("pretty").cloned(),
+            date: matches.get_one::<String>("date").cloned(),
+            stat: matches.get_flag("stat"),
+            patch: matches.get_flag("patch"),
+            reverse: matches.get_flag("reverse"),
+            graph: matches.get_flag("graph"),
+            all: matches.get_flag("all"),
+            decorate: matches.get_flag("decorate"),
+            topo_order: matches.get_flag("topo-order"),
+            date_order: matches.get_flag("date-order"),
+        }
+    }
+}
+
+/// How every commit is printed.
+enum Pretty {
+    Medium,
+    Oneline,
+    Format(String),
+}
+
+struct LogFormat {
+    pretty: Pretty,
+    date: DateMode,
+    /// Ref names pointing at each commit, empty without `--decorate`
+    decorations: HashMap<[u8; 32], Vec<String>>,
+}
+
+/// Commit filters compiled once from the options.
+struct LogFilter {
+    since: Option<i64>,
+    until: Option<i64>,
+    author: Option<Regex>,
+    grep: Option<Regex>,
+    paths: Pathspec,
+}
+
+pub fn log(options: LogOptions) {
     let current_dir = env::current_dir().unwrap();
     let vit_dir = current_dir.join(".vit");
     let object_dir = vit_dir.join("objects");
@@ -18,31 +208,518 @@ pub fn log() {
         return eprintln!(".vit directory not found!");
     }
 
-    let head_path = vit_dir.join("HEAD");
-    let head_ref = fs::read_to_string(&head_path).unwrap();
-    let current_branch_ref = head_ref.trim_start_matches("ref: ").trim();
+    let filter = match build_filter(&options) {
+        Ok(filter) => filter,
+        Err(message) => return eprintln!("{}", message),
+    };
+    let format = match build_format(&options) {
+        Ok(format) => format,
+        Err(message) => return eprintln!("{}", message),
+    };
+
+    if options.graph && options.reverse {
+        return eprintln!("--reverse and --graph cannot be used together");
+    }
+
+    let mut walker = CommitWalker::new(&object_dir);
+    if let Err(message) = push_revisions(&vit_dir, &mut walker, &options.revisions, options.all) {
+        return eprintln!("{}", message);
+    }
+
+    let order = if options.topo_order || (options.graph && !options.date_order) {
+        Some(SortOrder::Topo)
+    } else if options.date_order {
+        Some(SortOrder::Date)
+    } else {
+        None
+    };
+
+    setup_pager();
+
+    let mut commits = walker.filter(|item| match item {
+        Ok((_, commit)) => filter.matches(&object_dir, commit),
+        Err(_) => true,
+    });
+
+    // Without reordering commits are printed as soon as they are found
+    if order.is_none() && !options.reverse {
+        for item in commits
+            .by_ref()
+            .take(options.max_count.unwrap_or(usize::MAX))
+        {
+            match item {
+                Ok((hash, commit)) => print!(
+                    "{}",
+                    render_commit(
+                        &object_dir,
+                        &hash,
+                        &commit,
+                        &options,
+                        &format,
+                        &filter.paths
+                    )
+                ),
+                Err(err) => return eprintln!("Unable to read commit: {}", err),
+            }
+        }
+        return;
+    }
+
+    let mut selected: Vec<([u8; 32], CommitEntry)> = match commits.collect() {
+        Ok(selected) => selected,
+        Err(err) => return eprintln!("Unable to read commit: {}", err),
+    };
+    if let Some(order) = order {
+        selected = sort_commits(selected, order);
+    }
+    selected.truncate(options.max_count.unwrap_or(usize::MAX));
+    if options.reverse {
+        selected.reverse();
+    }
+
+    let mut graph = options
+        .graph
+        .then(|| GraphRenderer::new(selected.iter().map(|(hash, _)| *hash).collect()));
+
+    for (hash, commit) in &selected {
+        let text = render_commit(&object_dir, hash, commit, &options, &format, &filter.paths);
+
+        match graph.as_mut() {
+            Some(graph) => print!(
+                "{}",
+                draw_graph(graph.next_commit(hash, &commit.parents), &text)
+            ),
+            None => print!("{}", text),
+        }
+    }
+}
+
+/// Put the graph columns in front of the lines of one commit.
+fn draw_graph(rows: GraphRows, text: &str) -> String {
+    // Keep the text of one commit in a single column
+    let width = std::iter::once(&rows.commit)
+        .chain(&rows.transition)
+        .chain(std::iter::once(&rows.padding))
+        .map(String::len)
+        .max()
+        .unwrap_or(0);
+
+    let mut prefixes = std::iter::once(rows.commit).chain(rows.transition);
+    let mut out = String::new();
+
+    for line in text.lines() {
+        let prefix = prefixes.next().unwrap_or_else(|| rows.padding.clone());
+        out.push_str(format!("{:width$} {}", prefix, line).trim_end());
+        out.push('\n');
+    }
+
+    // Lanes still moving after the text ran out get rows of their own
+    for prefix in prefixes {
+        out.push_str(&prefix);
+        out.push('\n');
+    }
+
+    out
+}
+
+fn build_filter(options: &LogOptions) -> Result<LogFilter, String> {
+    let date = |value: &Option<String>| match value {
+        Some(value) => parse_date(value)
+            .map(Some)
+            .ok_or_else(|| format!("Invalid date '{}'", value)),
+        None => Ok(None),
+    };
+    let pattern = |value: &Option<String>| match value {
+        Some(value) => Regex::new(value)
+            .map(Some)
+            .map_err(|err| format!("Invalid pattern '{}': {}", value, err)),
+        None => Ok(None),
+    };
+
+    Ok(LogFilter {
+        since: date(&options.since)?,
+        until: date(&options.until)?,
+        author: pattern(&options.author)?,
+        grep: pattern(&options.grep)?,
+        paths: Pathspec::parse(&options.paths)?,
+    })
+}
+
+fn build_format(options: &LogOptions) -> Result<LogFormat, String> {
+    let date = match &options.date {
+        Some(value) => {
+            DateMode::parse(value).ok_or_else(|| format!("Unknown date format '{}'", value))?
+        }
+        None => DateMode::Default,
+    };
+
+    let pretty = match options.pretty.as_deref() {
+        _ if options.oneline => Pretty::Oneline,
+        None | Some("medium") => Pretty::Medium,
+        Some("oneline") => Pretty::Oneline,
+        Some(value) => match value
+            .strip_prefix("format:")
+            .or_else(|| value.strip_prefix("tformat:"))
+        {
+            Some(template) => Pretty::Format(template.to_string()),
+            None if value.contains('%') => Pretty::Format(value.to_string()),
+            None => return Err(format!("Invalid pretty format: {}", value)),
+        },
+    };
+
+    let decorations = if options.decorate {
+        ref_decorations(&env::current_dir().unwrap().join(".vit"))
+    } else {
+        HashMap::new()
+    };
+
+    Ok(LogFormat {
+        pretty,
+        date,
+        decorations,
+    })
+}
+
+/// Queue the start points and exclusions described by the revisions,
+/// HEAD when none are given, and every ref with `all`.
+fn push_revisions(
+    vit_dir: &Path,
+    walker: &mut CommitWalker,
+    revisions: &[String],
+    all: bool,
+) -> Result<(), String> {
+    let resolve = |revision: &str| {
+        let revision = if revision.is_empty() {
+            "HEAD"
+        } else {
+            revision
+        };
+        resolve_revision(vit_dir, revision).map_err(|err| err.to_string())
+    };
+    let objects_dir = vit_dir.join("objects");
+
+    if all {
+        if let Ok(head) = resolve("HEAD") {
+            walker.push(head).map_err(|err| err.to_string())?;
+        }
+        for (_, hash) in list_refs(vit_dir) {
+            walker.push(hash).map_err(|err| err.to_string())?;
+        }
+    }
+
+    if revisions.is_empty() && !all {
+        let head = resolve("HEAD").map_err(|_| {
+            let branch = RefStore::new(vit_dir)
+                .current_branch()
+                .unwrap_or_else(|| "HEAD".to_string());
+            format!(
+                "Your current branch '{}' does not have any commits yet",
+                branch
+            )
+        })?;
+        return walker.push(head).map_err(|err| err.to_string());
+    }
+
+    for revision in revisions {
+        if let Some((left, right)) = revision.split_once("...") {
+            // Symmetric difference, hide what both sides have in common
+            let (left, right) = (resolve(left)?, resolve(right)?);
+            let left_ancestors = ancestors(&objects_dir, left).map_err(|err| err.to_string())?;
+            let right_ancestors = ancestors(&objects_dir, right).map_err(|err| err.to_string())?;
+
+            walker.hide_commits(left_ancestors.intersection(&right_ancestors).copied());
+            walker.push(left).map_err(|err| err.to_string())?;
+            walker.push(right).map_err(|err| err.to_string())?;
+        } else if let Some((left, right)) = revision.split_once("..") {
+            walker.hide(resolve(left)?).map_err(|err| err.to_string())?;
+            walker
+                .push(resolve(right)?)
+                .map_err(|err| err.to_string())?;
+        } else if let Some(excluded) = revision.strip_prefix('^') {
+            walker
+                .hide(resolve(excluded)?)
+                .map_err(|err| err.to_string())?;
+        } else {
+            walker
+                .push(resolve(revision)?)
+                .map_err(|err| err.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+impl LogFilter {
+    fn matches(&self, object_dir: &Path, commit: &CommitEntry) -> bool {
+        // Like git the limits apply to the committer date
+        let date = commit.committer_timestamp;
+        if self.since.is_some_and(|since| date < since)
+            || self.until.is_some_and(|until| date > until)
+        {
+            return false;
+        }
+
+        if let Some(author) = &self.author
+            && !author.is_match(&commit.author)
+        {
+            return false;
+        }
+
+        if let Some(grep) = &self.grep
+            && !grep.is_match(&commit.message)
+        {
+            return false;
+        }
+
+        self.paths.is_empty() || touches_paths(object_dir, commit, &self.paths)
+    }
+}
+
+/// A commit touches the paths when it differs from every parent there, a
+/// merge taking the paths unchanged from one side is left out.
+fn touches_paths(object_dir: &Path, commit: &CommitEntry, paths: &Pathspec) -> bool {
+    if commit.parents.is_empty() {
+        return !commit_changes(object_dir, commit, paths).is_empty();
+    }
+
+    commit.parents.iter().all(|parent| {
+        let parent_commit = read_commit_file(&loose_object_path(object_dir, parent)).unwrap();
+        diff_trees(object_dir, Some(&parent_commit.tree), Some(&commit.tree))
+            .iter()
+            .any(|change| paths.matches(&change.path))
+    })
+}
+
+/// Files changed by a commit compared to its first parent, limited to `paths`.
+fn commit_changes(object_dir: &Path, commit: &CommitEntry, paths: &Pathspec) -> Vec<TreeChange> {
+    let parent_tree = commit.parents.first().map(|parent| {
+        read_commit_file(&loose_object_path(object_dir, parent))
+            .unwrap()
+            .tree
+    });
+
+    let mut changes = diff_trees(object_dir, parent_tree.as_ref(), Some(&commit.tree));
+    if !paths.is_empty() {
+        changes.retain(|change| paths.matches(&change.path));
+    }
+    changes
+}
+
+/// Everything printed for one commit, ending in a newline.
+fn render_commit(
+    object_dir: &Path,
+    hash: &[u8; 32],
+    commit: &CommitEntry,
+    options: &LogOptions,
+    format: &LogFormat,
+    paths: &Pathspec,
+) -> String {
+    let commit_hash = hex::encode(hash);
+    let decoration = format_decoration(format.decorations.get(hash), true);
+    let mut out = String::new();
+
+    match &format.pretty {
+        Pretty::Oneline => {
+            let subject = commit.message.lines().next().unwrap_or("");
+            writeln!(
+                out,
+                "{}{} {}",
+                commit_hash[..7].yellow(),
+                decoration,
+                subject
+            )
+            .unwrap();
+        }
+        Pretty::Format(template) => {
+            writeln!(out, "{}", expand_format(template, hash, commit, format)).unwrap();
+        }
+        Pretty::Medium => {
+            writeln!(
+                out,
+                "{} {}{}",
+                "commit".yellow(),
+                commit_hash.yellow(),
+                decoration
+            )
+            .unwrap();
+            if commit.parents.len() > 1 {
+                let parents: Vec<String> = commit
+                    .parents
+                    .iter()
+                    .map(|parent| hex::encode(parent)[..7].to_string())
+                    .collect();
+                writeln!(out, "Merge: {}", parents.join(" ")).unwrap();
+            }
+            writeln!(out, "Author: {}", commit.author).unwrap();
+            writeln!(
+                out,
+                "Date:   {}",
+                format_date(commit.timestamp, &commit.timezone, format.date)
+            )
+            .unwrap();
+            out.push('\n');
+            for line in commit.message.lines() {
+                writeln!(out, "    {}", line).unwrap();
+            }
+            out.push('\n');
+        }
+    }
+
+    if !options.stat && !options.patch {
+        return out;
+    }
+
+    let changes = commit_changes(object_dir, commit, paths);
+    if options.stat {
+        out.push_str(&format_tree_stat(object_dir, &changes));
+        if matches!(format.pretty, Pretty::Medium) || options.patch {
+            out.push('\n');
+        }
+    }
+    if options.patch {
+        out.push_str(&format_tree_patch(object_dir, &changes));
+        out.push('\n');
+    }
 
-    let mut commit_hash = fs::read_to_string(vit_dir.join(current_branch_ref)).unwrap();
-    let start_commit = hex::encode([0u8; 32]);
+    out
+}
 
-    loop {
-        // Setup pager
-        set_override(true);
-        Pager::with_pager("less -R -F -X").setup();
+/// ` (HEAD -> main, tag: v1.0)`, or without the parentheses for `%D`.
+fn format_decoration(labels: Option<&Vec<String>>, parentheses: bool) -> String {
+    let Some(labels) = labels else {
+        return String::new();
+    };
 
-        if commit_hash == start_commit {
-            break;
+    let colored: Vec<String> = labels
+        .iter()
+        .map(|label| {
+            if let Some(branch) = label.strip_prefix("HEAD -> ") {
+                format!("{} {}", "HEAD ->".cyan().bold(), branch.green().bold())
+            } else if label == "HEAD" {
+                label.cyan().bold().to_string()
+            } else if label.starts_with("tag: ") || label.starts_with("refs/") {
+                label.yellow().bold().to_string()
+            } else {
+                label.green().bold().to_string()
+            }
+        })
+        .collect();
+
+    if parentheses {
+        format!(
+            " {}{}{}",
+            "(".yellow(),
+            colored.join(&", ".yellow().to_string()),
+            ")".yellow()
+        )
+    } else {
+        colored.join(", ")
+    }
+}
+
+/// Labels for every commit some ref points at, HEAD first.
+fn ref_decorations(vit_dir: &Path) -> HashMap<[u8; 32], Vec<String>> {
+    let mut decorations: HashMap<[u8; 32], Vec<String>> = HashMap::new();
+
+    let head_ref = RefStore::new(vit_dir).head_branch();
+    if head_ref.is_none()
+        && let Ok(hash) = resolve_revision(vit_dir, "HEAD")
+    {
+        decorations
+            .entry(hash)
+            .or_default()
+            .push("HEAD".to_string());
+    }
+
+    for (name, hash) in list_refs(vit_dir) {
+        let label = if let Some(branch) = name.strip_prefix("refs/heads/") {
+            if head_ref.as_deref() == Some(name.as_str()) {
+                format!("HEAD -> {}", branch)
+            } else {
+                branch.to_string()
+            }
+        } else if let Some(tag) = name.strip_prefix("refs/tags/") {
+            format!("tag: {}", tag)
+        } else {
+            name.clone()
+        };
+
+        let labels = decorations.entry(hash).or_default();
+        if label.starts_with("HEAD") {
+            labels.insert(0, label);
+        } else {
+            labels.push(label);
         }
-        let commit_path = object_dir.join(&commit_hash[..2]).join(&commit_hash[2..]);
-        let commit_entry = read_commit_file(&commit_path).unwrap();
+    }
 
-        println!("{} {}", "commit".yellow(), commit_hash.yellow());
-        println!("Author: {}", commit_entry.author);
-        println!("Date:   {} {}", commit_entry.timestamp, commit_entry.timezone);
-        println!();
-        println!("    {}", commit_entry.message);
-        println!();
+    decorations
+}
 
-        commit_hash = hex::encode(commit_entry.parent);
+/// Replace the `%` placeholders of a `--pretty=format:` template.
+fn expand_format(
+    template: &str,
+    hash: &[u8; 32],
+    commit: &CommitEntry,
+    format: &LogFormat,
+) -> String {
+    let (author_name, author_email) = match commit.author.split_once(" <") {
+        Some((name, email)) => (name, email.trim_end_matches('>')),
+        None => (commit.author.as_str(), ""),
+    };
+    let (subject, body) = match commit.message.split_once("\n\n") {
+        Some((subject, body)) => (subject, body),
+        None => (commit.message.trim_end(), ""),
+    };
+    let parents = |length: usize| -> String {
+        commit
+            .parents
+            .iter()
+            .map(|parent| hex::encode(parent)[..length].to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(position) = rest.find('%') {
+        out.push_str(&rest[..position]);
+        rest = &rest[position + 1..];
+
+        let placeholders: [(&str, &dyn Fn() -> String); 16] = [
+            ("H", &|| hex::encode(hash)),
+            ("h", &|| hex::encode(hash)[..7].to_string()),
+            ("T", &|| hex::encode(commit.tree)),
+            ("t", &|| hex::encode(commit.tree)[..7].to_string()),
+            ("P", &|| parents(64)),
+            ("p", &|| parents(7)),
+            ("an", &|| author_name.to_string()),
+            ("ae", &|| author_email.to_string()),
+            ("ad", &|| {
+                format_date(commit.timestamp, &commit.timezone, format.date)
+            }),
+            ("at", &|| commit.timestamp.to_string()),
+            ("s", &|| subject.replace('\n', " ")),
+            ("b", &|| body.to_string()),
+            ("d", &|| {
+                format_decoration(format.decorations.get(hash), true)
+            }),
+            ("D", &|| {
+                format_decoration(format.decorations.get(hash), false)
+            }),
+            ("n", &|| "\n".to_string()),
+            ("%", &|| "%".to_string()),
+        ];
+
+        match placeholders.iter().find(|(name, _)| rest.starts_with(name)) {
+            Some((name, value)) => {
+                out.push_str(&value());
+                rest = &rest[name.len()..];
+            }
+            None => out.push('%'),
+        }
     }
+
+    out.push_str(rest);
+    out
 }
@@ -2,18 +2,40 @@ pub mod clone;
 pub mod init;
 pub mod status;
 pub mod add;
+pub mod reset;
+pub mod restore;
 pub mod commit;
 pub mod branch;
 pub mod checkout;
 pub mod stash;
 pub mod log;
+pub mod tag;
+pub mod reflog;
+pub mod show_ref;
+pub mod update_ref;
+pub mod symbolic_ref;
+pub mod config;
+pub mod repack;
+pub mod gc;
+pub mod prune;
 
 pub use clone::*;
 pub use init::*;
 pub use status::*;
 pub use add::*;
+pub use reset::*;
+pub use restore::*;
 pub use commit::*;
 pub use branch::*;
 pub use checkout::*;
 pub use stash::*;
 pub use log::*;
+pub use tag::*;
+pub use reflog::*;
+pub use show_ref::*;
+pub use update_ref::*;
+pub use symbolic_ref::*;
+pub use config::*;
+pub use repack::*;
+pub use gc::*;
+pub use prune::*;
@@ -0,0 +1,101 @@
+use crate::utils;
+use chrono::{DateTime, Local};
+use clap::{Arg, ArgAction, Command};
+use std::{env, fs};
+
+/// Grace period used when `--expire` is not given.
+pub const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+pub fn get_prune_command() -> Command {
+    Command::new("prune")
+        .about("Delete unreachable loose objects")
+        .arg(
+            Arg::new("expire")
+                .long("expire")
+                .required(false)
+                .value_name("TIME")
+                .help("Only prune objects older than TIME (default: 2.weeks.ago, 'never' keeps everything)"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .short('n')
+                .long("dry-run")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("List the objects that would be removed without removing them"),
+        )
+}
+
+pub fn prune(expire: Option<String>, dry_run: bool) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    let expire = expire.unwrap_or_else(|| DEFAULT_PRUNE_EXPIRE.to_string());
+    let cutoff = match expire.as_str() {
+        "never" => return,
+        "all" => Local::now().timestamp(),
+        value => match utils::parse_date(value) {
+            Some(timestamp) => timestamp,
+            None => return eprintln!("Invalid expiry time: '{}'", value),
+        },
+    };
+
+    let objects_dir = vit_dir.join("objects");
+    let unreachable = match utils::unreachable_loose_objects(&vit_dir) {
+        Ok(objects) => objects,
+        Err(err) => return eprintln!("Unable to compute reachable objects: {}", err),
+    };
+
+    let mut pruned = 0;
+    for (hash, modified) in unreachable {
+        if modified > cutoff {
+            continue;
+        }
+
+        let object_path = utils::loose_object_path(&objects_dir, &hash);
+        if dry_run {
+            let object_type = utils::open_object(&object_path)
+                .map(|object| object.object_type)
+                .unwrap_or_else(|_| "unknown".to_string());
+            println!("{} {}", hex::encode(hash), object_type);
+            continue;
+        }
+
+        if let Err(err) = fs::remove_file(&object_path) {
+            eprintln!("Unable to remove {}: {}", hex::encode(hash), err);
+            continue;
+        }
+        if let Some(dir) = object_path.parent() {
+            let _ = fs::remove_dir(dir); // Only succeeds once the directory is empty
+        }
+        pruned += 1;
+    }
+
+    // Writers rename their temporary objects right away, old ones are stale
+    let temp_objects = match utils::list_temp_objects(&objects_dir) {
+        Ok(paths) => paths,
+        Err(err) => return eprintln!("Unable to list temporary objects: {}", err),
+    };
+    for path in temp_objects {
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map(|time| DateTime::<Local>::from(time).timestamp())
+            .unwrap_or(0);
+        if modified > cutoff {
+            continue;
+        }
+
+        let name = path.strip_prefix(&objects_dir).unwrap_or(&path).display();
+        if dry_run {
+            println!("Removing stale temporary file {}", name);
+        } else if let Err(err) = fs::remove_file(&path) {
+            eprintln!("Unable to remove {}: {}", name, err);
+        }
+    }
+
+    if !dry_run && pruned > 0 {
+        println!("Pruned {} unreachable objects", pruned);
+    }
+}
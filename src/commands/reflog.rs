@@ -0,0 +1,182 @@
+use crate::utils::{
+    RefStore, expand_ref_name, get_config, list_ref_names, parse_date, read_reflog,
+    remove_ref_file, setup_pager, write_reflog,
+};
+use chrono::Local;
+use clap::{Arg, ArgAction, Command};
+use colored::Colorize;
+use std::{env, path::Path};
+
+/// How long reflog entries are kept when neither `--expire` nor
+/// `gc.reflogExpire` say otherwise.
+pub const DEFAULT_REFLOG_EXPIRE: &str = "90.days.ago";
+
+pub fn get_reflog_command() -> Command {
+    Command::new("reflog")
+        .about("Show, expire or delete reflog entries")
+        .subcommand_required(false)
+        .arg(get_reflog_ref_arg())
+        .subcommand(
+            Command::new("show")
+                .about("Show the updates of a ref, newest first")
+                .arg(get_reflog_ref_arg()),
+        )
+        .subcommand(
+            Command::new("expire")
+                .about("Drop reflog entries older than a given time")
+                .arg(
+                    Arg::new("expire")
+                        .long("expire")
+                        .value_name("TIME")
+                        .help("Drop entries older than TIME (default: gc.reflogExpire or 90.days.ago, 'never' keeps everything)"),
+                )
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .help("Expire the reflogs of all refs"),
+                )
+                .arg(
+                    Arg::new("refs")
+                        .num_args(0..)
+                        .help("Refs whose reflogs to expire, defaults to HEAD"),
+                ),
+        )
+        .subcommand(
+            Command::new("delete")
+                .about("Delete single reflog entries")
+                .arg(
+                    Arg::new("entries")
+                        .required(true)
+                        .num_args(1..)
+                        .help("Entries such as main@{2}"),
+                )
+                .arg_required_else_help(true),
+        )
+}
+
+fn get_reflog_ref_arg() -> Arg {
+    Arg::new("ref")
+        .required(false)
+        .help("Ref whose reflog to show, defaults to HEAD")
+}
+
+pub fn reflog_show(ref_name: Option<&str>) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    let name = ref_name.unwrap_or("HEAD");
+    let Some(full_name) = expand_ref_name(&vit_dir, name) else {
+        return eprintln!("fatal: ambiguous argument '{}': unknown ref", name);
+    };
+
+    setup_pager();
+
+    // Entries of `refs/stash` show up as `stash@{n}`
+    let display_name = full_name
+        .strip_prefix("refs/heads/")
+        .or_else(|| full_name.strip_prefix("refs/"))
+        .unwrap_or(&full_name);
+
+    for (i, entry) in read_reflog(&vit_dir, &full_name).iter().rev().enumerate() {
+        println!(
+            "{} {}@{{{}}}: {}",
+            hex::encode(entry.new)[..7].yellow(),
+            display_name,
+            i,
+            entry.message
+        );
+    }
+}
+
+/// Drop entries older than `expire` from the reflogs of `refs`, or of every
+/// ref with `all`.
+pub fn reflog_expire(expire: Option<String>, all: bool, refs: &[String]) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    let expire = expire
+        .or_else(|| get_config("gc.reflogExpire"))
+        .unwrap_or_else(|| DEFAULT_REFLOG_EXPIRE.to_string());
+    let cutoff = match expire.as_str() {
+        "never" | "false" => return,
+        "all" | "now" => Local::now().timestamp() + 1,
+        value => match parse_date(value) {
+            Some(timestamp) => timestamp,
+            None => return eprintln!("Invalid expiry time: '{}'", value),
+        },
+    };
+
+    let ref_names = if all {
+        list_ref_names(&vit_dir, "logs")
+    } else if refs.is_empty() {
+        vec!["HEAD".to_string()]
+    } else {
+        let mut ref_names = Vec::new();
+        for name in refs {
+            match expand_ref_name(&vit_dir, name) {
+                Some(full_name) => ref_names.push(full_name),
+                None => return eprintln!("error: reflog could not be found: '{}'", name),
+            }
+        }
+        ref_names
+    };
+
+    for ref_name in ref_names {
+        expire_reflog(&vit_dir, &ref_name, cutoff);
+    }
+}
+
+/// Remove entries given as `<ref>@{n}`.
+pub fn reflog_delete(entries: &[String]) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    // Later indices first so earlier deletions do not shift them
+    let mut targets = Vec::new();
+    for entry in entries {
+        let parsed = entry.split_once("@{").and_then(|(name, rest)| {
+            let n: usize = rest.strip_suffix('}')?.parse().ok()?;
+            Some((expand_ref_name(&vit_dir, name)?, n))
+        });
+
+        match parsed {
+            Some(target) => targets.push(target),
+            None => return eprintln!("error: not a reflog: {}", entry),
+        }
+    }
+    targets.sort_by(|a, b| b.cmp(a));
+    targets.dedup();
+
+    for (ref_name, n) in targets {
+        let mut log = read_reflog(&vit_dir, &ref_name);
+        if n >= log.len() {
+            return eprintln!("error: entry {}@{{{}}} does not exist", ref_name, n);
+        }
+
+        log.remove(log.len() - 1 - n);
+        write_reflog(&vit_dir, &ref_name, &log).unwrap();
+    }
+}
+
+fn expire_reflog(vit_dir: &Path, ref_name: &str, cutoff: i64) {
+    let mut log = read_reflog(vit_dir, ref_name);
+    let before = log.len();
+    log.retain(|entry| entry.timestamp >= cutoff);
+    if log.len() == before {
+        return;
+    }
+
+    // Nothing is left to remember about a deleted ref
+    if log.is_empty() && RefStore::new(vit_dir).read(ref_name).is_none() {
+        remove_ref_file(&vit_dir.join("logs").join(ref_name), &vit_dir.join("logs"));
+    } else {
+        write_reflog(vit_dir, ref_name, &log).unwrap();
+    }
+}
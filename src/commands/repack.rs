@@ -0,0 +1,34 @@
+use crate::utils;
+use clap::{Arg, ArgAction, Command};
+use std::env;
+
+pub fn get_repack_command() -> Command {
+    Command::new("repack")
+        .about("Pack loose objects into a packfile")
+        .arg(
+            Arg::new("all")
+                .short('a')
+                .long("all")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Pack everything, including objects already in packs, into a single pack"),
+        )
+}
+
+pub fn repack(all: bool) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    match utils::repack(&vit_dir.join("objects"), all, None) {
+        Ok(Some(stats)) => println!(
+            "Packed {} objects ({} deltas) into {}",
+            stats.objects,
+            stats.deltas,
+            stats.pack_path.file_name().unwrap().to_string_lossy()
+        ),
+        Ok(None) => println!("Nothing new to pack"),
+        Err(err) => eprintln!("Unable to repack objects: {}", err),
+    }
+}
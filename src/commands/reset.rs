@@ -0,0 +1,229 @@
+use crate::utils::{
+    self, FileStatus, IndexEntry, Pathspec, TreeEntry, flatten_tree, loose_object_path,
+    open_object, parse_commit_data, read_index, read_object_by_hash, write_index,
+};
+use clap::{Arg, ArgAction, Command};
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, Write},
+    path::Path,
+};
+
+pub fn get_reset_command() -> Command {
+    Command::new("reset")
+        .about("Unstage changes, the work tree is left alone")
+        .arg(
+            Arg::new("paths")
+                .num_args(1..)
+                .help("Only unstage these paths, as paths, globs or pathspec magic"),
+        )
+        .arg(
+            Arg::new("patch")
+                .short('p')
+                .long("patch")
+                .action(ArgAction::SetTrue)
+                .help("Choose the staged hunks to unstage interactively"),
+        )
+}
+
+/// Take the index of the matching paths back to HEAD, every path without
+/// any. Files added since HEAD are no longer tracked afterwards.
+///
+/// With `patch` every staged hunk is offered instead, shown as the change
+/// unstaging it makes to the index.
+pub fn reset(paths: &[String], patch: bool) {
+    let current_dir = env::current_dir().unwrap();
+    let vit_dir = current_dir.join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    let objects_path = vit_dir.join("objects");
+    let pathspec = match Pathspec::parse(paths) {
+        Ok(pathspec) => pathspec,
+        Err(err) => return eprintln!("fatal: {}", err),
+    };
+    let head_files = match head_files(&vit_dir) {
+        Ok(files) => files,
+        Err(err) => return eprintln!("fatal: unable to read HEAD: {}", err),
+    };
+
+    let mut index_entries = read_index().unwrap();
+    let mut untracked: Vec<String> = Vec::new();
+
+    if patch {
+        if !reset_patch(
+            &objects_path,
+            &pathspec,
+            &head_files,
+            &mut index_entries,
+            &mut untracked,
+        ) {
+            return println!("No changes.");
+        }
+    } else {
+        for entry in index_entries.iter_mut() {
+            if entry.status == FileStatus::Unchanged || !pathspec.matches(&entry.path) {
+                continue;
+            }
+
+            match head_files.get(&entry.path) {
+                Some(head) => {
+                    let size = match open_object(&loose_object_path(&objects_path, &head.sha256)) {
+                        Ok(object) => object.size,
+                        Err(err) => {
+                            eprintln!("Unable to unstage {}: {}", entry.path, err);
+                            continue;
+                        }
+                    };
+                    unstage(entry, head.sha256, size);
+                }
+                None => untracked.push(entry.path.clone()),
+            }
+        }
+    }
+
+    index_entries.retain(|entry| !untracked.contains(&entry.path));
+    write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
+}
+
+/// Offer the staged hunks of every matching file. Returns whether anything
+/// was offered.
+fn reset_patch(
+    objects_path: &Path,
+    pathspec: &Pathspec,
+    head_files: &BTreeMap<String, TreeEntry>,
+    index_entries: &mut [IndexEntry],
+    untracked: &mut Vec<String>,
+) -> bool {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut out = io::stdout();
+    let mut is_something_offered = false;
+
+    for entry in index_entries.iter_mut() {
+        if entry.status == FileStatus::Unchanged || !pathspec.matches(&entry.path) {
+            continue;
+        }
+        let head = head_files.get(&entry.path).map(|head| head.sha256);
+
+        // A failure only skips the file, hunks chosen for others are kept
+        let selection = if entry.status == FileStatus::Deleted {
+            let Some(head) = head else {
+                continue;
+            };
+            let size = match open_object(&loose_object_path(objects_path, &head)) {
+                Ok(object) => object.size,
+                Err(err) => {
+                    eprintln!("Unable to unstage {}: {}", entry.path, err);
+                    continue;
+                }
+            };
+
+            is_something_offered = true;
+            match utils::select_deletion(&entry.path, "Unstage", &mut input, &mut out) {
+                Ok(selection) => {
+                    if selection.content.is_some() {
+                        unstage(entry, head, size);
+                    }
+                    selection
+                }
+                Err(err) => {
+                    eprintln!("Unable to unstage {}: {}", entry.path, err);
+                    continue;
+                }
+            }
+        } else {
+            let blobs = read_object_by_hash(objects_path, &entry.sha256).and_then(|(_, staged)| {
+                let head_content = match head {
+                    Some(head) => read_object_by_hash(objects_path, &head)?.1,
+                    None => Vec::new(),
+                };
+                Ok((staged, head_content))
+            });
+            let (staged, head_content) = match blobs {
+                Ok(blobs) => blobs,
+                Err(err) => {
+                    eprintln!("Unable to unstage {}: {}", entry.path, err);
+                    continue;
+                }
+            };
+            if staged == head_content {
+                continue;
+            }
+            if staged.contains(&0) || head_content.contains(&0) {
+                println!("Cannot unstage binary file {} in patch mode", entry.path);
+                continue;
+            }
+
+            is_something_offered = true;
+            let selection = match utils::select_hunks(
+                &entry.path,
+                &staged,
+                &head_content,
+                "Unstage",
+                &mut input,
+                &mut out,
+            ) {
+                Ok(selection) => selection,
+                Err(err) => {
+                    eprintln!("Unable to unstage {}: {}", entry.path, err);
+                    continue;
+                }
+            };
+
+            match (&selection.content, head) {
+                (Some(content), Some(head)) if *content == head_content => {
+                    unstage(entry, head, content.len() as u64);
+                }
+                (Some(content), None) if content.is_empty() => untracked.push(entry.path.clone()),
+                (Some(content), _) => {
+                    match utils::write_loose_object(objects_path, "blob", content) {
+                        Ok(sha256) => {
+                            entry.sha256 = sha256;
+                            entry.file_size = content.len() as u32;
+                            entry.mtime_secs = 0;
+                            entry.mtime_nsecs = 0;
+                        }
+                        Err(err) => {
+                            eprintln!("Unable to unstage {}: {}", entry.path, err);
+                            continue;
+                        }
+                    }
+                }
+                (None, _) => {}
+            }
+            selection
+        };
+
+        if selection.quit {
+            break;
+        }
+    }
+    let _ = out.flush();
+
+    is_something_offered
+}
+
+/// Point `entry` back at the blob of HEAD. The work tree may hold anything,
+/// without a modification time the next status compares it again.
+fn unstage(entry: &mut IndexEntry, sha256: [u8; 32], size: u64) {
+    entry.sha256 = sha256;
+    entry.file_size = size as u32;
+    entry.mtime_secs = 0;
+    entry.mtime_nsecs = 0;
+    entry.status = FileStatus::Unchanged;
+}
+
+/// Every file of the tree HEAD points at, none while HEAD is unborn.
+fn head_files(vit_dir: &Path) -> io::Result<BTreeMap<String, TreeEntry>> {
+    let Some(head) = utils::RefStore::new(vit_dir).read("HEAD") else {
+        return Ok(BTreeMap::new());
+    };
+
+    let objects_path = vit_dir.join("objects");
+    let (_, content) = read_object_by_hash(&objects_path, &head)?;
+    let commit = parse_commit_data(&content)?;
+    Ok(flatten_tree(&objects_path, &commit.tree))
+}
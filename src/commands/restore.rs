@@ -0,0 +1,140 @@
+use crate::utils::{
+    self, FileStatus, IndexEntry, Pathspec, read_index, read_object_by_hash, write_index,
+};
+use clap::{Arg, ArgAction, Command};
+use std::{
+    env, fs,
+    io::{self, Write},
+};
+
+pub fn get_restore_command() -> Command {
+    Command::new("restore")
+        .about("Discard changes in the work tree, taking files back to the index")
+        .arg(
+            Arg::new("paths")
+                .required_unless_present("patch")
+                .num_args(1..)
+                .help("Files to restore, as paths, globs or pathspec magic"),
+        )
+        .arg(
+            Arg::new("patch")
+                .short('p')
+                .long("patch")
+                .action(ArgAction::SetTrue)
+                .help("Choose the hunks to discard interactively"),
+        )
+        .arg_required_else_help(true)
+}
+
+/// Write the staged content of the matching tracked files back into the
+/// work tree, deleted files included.
+///
+/// With `patch` every changed hunk is offered instead, shown as the change
+/// discarding it makes to the work tree. Without paths every tracked file
+/// is offered.
+pub fn restore(paths: &[String], patch: bool) {
+    let current_dir = env::current_dir().unwrap();
+    let vit_dir = current_dir.join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    let objects_path = vit_dir.join("objects");
+    let pathspec = match Pathspec::parse(paths) {
+        Ok(pathspec) => pathspec,
+        Err(err) => return eprintln!("fatal: {}", err),
+    };
+    let mut index_entries: Vec<IndexEntry> = read_index().unwrap();
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut out = io::stdout();
+    let mut is_something_offered = false;
+
+    for entry in index_entries.iter_mut() {
+        if entry.status == FileStatus::Deleted || !pathspec.matches(&entry.path) {
+            continue;
+        }
+
+        let (_, staged) = match read_object_by_hash(&objects_path, &entry.sha256) {
+            Ok(object) => object,
+            Err(err) => {
+                eprintln!("Unable to read the staged {}: {}", entry.path, err);
+                continue;
+            }
+        };
+
+        // A failure only skips the file, hunks chosen for others are kept
+        let (content, quit) = match fs::read(&entry.path) {
+            Ok(work) if work == staged => continue,
+            Ok(_) if !patch => (Some(staged.clone()), false),
+            Ok(work) if work.contains(&0) || staged.contains(&0) => {
+                println!("Cannot discard binary file {} in patch mode", entry.path);
+                continue;
+            }
+            Ok(work) => {
+                is_something_offered = true;
+                match utils::select_hunks(
+                    &entry.path,
+                    &work,
+                    &staged,
+                    "Discard",
+                    &mut input,
+                    &mut out,
+                ) {
+                    Ok(selection) => (selection.content, selection.quit),
+                    Err(err) => {
+                        eprintln!("Unable to restore {}: {}", entry.path, err);
+                        continue;
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !patch => {
+                (Some(staged.clone()), false)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                is_something_offered = true;
+                match utils::select_deletion(&entry.path, "Discard", &mut input, &mut out) {
+                    Ok(selection) => (selection.content.map(|_| staged.clone()), selection.quit),
+                    Err(err) => {
+                        eprintln!("Unable to restore {}: {}", entry.path, err);
+                        continue;
+                    }
+                }
+            }
+            Err(err) => {
+                eprintln!("Unable to read {}: {}", entry.path, err);
+                continue;
+            }
+        };
+
+        if let Some(content) = content {
+            let path = current_dir.join(&entry.path);
+            let written = fs::create_dir_all(path.parent().unwrap())
+                .and_then(|_| fs::write(&path, &content))
+                .and_then(|_| fs::metadata(&path));
+
+            match written {
+                // The file matches the index again, its new time is recorded
+                Ok(metadata) if content == staged => {
+                    let status = entry.status;
+                    *entry = IndexEntry::from_metadata(&entry.path, &metadata, entry.sha256);
+                    entry.status = status;
+                }
+                Ok(_) => {}
+                Err(err) => eprintln!("Unable to restore {}: {}", entry.path, err),
+            }
+        }
+
+        if quit {
+            break;
+        }
+    }
+    let _ = out.flush();
+
+    if patch && !is_something_offered {
+        return println!("No changes.");
+    }
+
+    write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
+}
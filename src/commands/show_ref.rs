@@ -0,0 +1,122 @@
+use crate::utils::{RefStore, peel_to_commit};
+use clap::{Arg, ArgAction, Command};
+use std::env;
+
+pub fn get_show_ref_command() -> Command {
+    Command::new("show-ref")
+        .about("List refs and the hashes they point at")
+        .arg(
+            Arg::new("patterns")
+                .num_args(0..)
+                .help("Only show refs whose name ends in one of these, e.g. main or tags/v1"),
+        )
+        .arg(
+            Arg::new("heads")
+                .long("heads")
+                .action(ArgAction::SetTrue)
+                .help("Only show branches"),
+        )
+        .arg(
+            Arg::new("tags")
+                .long("tags")
+                .action(ArgAction::SetTrue)
+                .help("Only show tags"),
+        )
+        .arg(
+            Arg::new("hash")
+                .short('s')
+                .long("hash")
+                .action(ArgAction::SetTrue)
+                .help("Only show the hashes"),
+        )
+        .arg(
+            Arg::new("dereference")
+                .short('d')
+                .long("dereference")
+                .action(ArgAction::SetTrue)
+                .help("Also show the commit annotated tags point at, as <tag>^{}"),
+        )
+        .arg(
+            Arg::new("verify")
+                .long("verify")
+                .action(ArgAction::SetTrue)
+                .help("Only show refs whose full name is given, such as refs/heads/main"),
+        )
+}
+
+#[derive(Debug, Default)]
+pub struct ShowRefOptions {
+    pub patterns: Vec<String>,
+    pub heads: bool,
+    pub tags: bool,
+    pub hash_only: bool,
+    pub dereference: bool,
+    pub verify: bool,
+}
+
+pub fn show_ref(options: &ShowRefOptions) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    let refs = RefStore::new(&vit_dir);
+    let objects_dir = vit_dir.join("objects");
+
+    let matches: Vec<(String, [u8; 32])> = if options.verify {
+        let mut matches = Vec::new();
+        for name in &options.patterns {
+            let hash = if name == "HEAD" || name.starts_with("refs/") {
+                refs.read(name)
+            } else {
+                None
+            };
+            match hash {
+                Some(hash) => matches.push((name.clone(), hash)),
+                None => return eprintln!("fatal: '{}' - not a valid ref", name),
+            }
+        }
+        matches
+    } else {
+        refs.list("refs/")
+            .into_iter()
+            .filter(|(name, _)| {
+                let kind_matches = (!options.heads && !options.tags)
+                    || (options.heads && name.starts_with("refs/heads/"))
+                    || (options.tags && name.starts_with("refs/tags/"));
+
+                kind_matches
+                    && (options.patterns.is_empty()
+                        || options
+                            .patterns
+                            .iter()
+                            .any(|pattern| matches_tail(name, pattern)))
+            })
+            .collect()
+    };
+
+    for (name, hash) in matches {
+        print_ref(&name, &hash, options.hash_only);
+
+        if options.dereference
+            && let Ok(peeled) = peel_to_commit(&objects_dir, &hash)
+            && peeled != hash
+        {
+            print_ref(&format!("{}^{{}}", name), &peeled, options.hash_only);
+        }
+    }
+}
+
+fn print_ref(name: &str, hash: &[u8; 32], hash_only: bool) {
+    if hash_only {
+        println!("{}", hex::encode(hash));
+    } else {
+        println!("{} {}", hex::encode(hash), name);
+    }
+}
+
+/// `main` matches `refs/heads/main` but not `refs/heads/domain`, patterns
+/// are compared whole components at a time from the end.
+fn matches_tail(name: &str, pattern: &str) -> bool {
+    name == pattern || name.ends_with(&format!("/{}", pattern))
+}
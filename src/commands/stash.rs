@@ -1,249 +1,322 @@
 use std::{
-    env,
-    fs::{self, OpenOptions},
-    io::Read,
+    collections::BTreeMap,
+    env, fs,
     path::Path,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use crate::{
-    commands::restore_file,
-    utils::{
-        self, FileChange, FileStatus, TreeEntry, build_commit, decompress_file_content,
-        parse_tree_entries, read_commit_file, save_tree_object, write_log_entry,
-    },
+use super::run_post_checkout;
+use crate::utils::{
+    self, CommitEntry, FileStatus, IndexEntry, RefStore, TreeEntry, build_commit, build_tree,
+    diff_trees, flatten_tree, format_tree_patch, format_tree_stat, index_from_tree,
+    is_valid_ref_short_name, loose_object_path, merge_three_way, parse_tree_entries,
+    reachable_objects, read_commit_file, read_object_by_hash, read_reflog, remove_work_tree_file,
+    update_work_tree, write_reflog, write_work_tree_file,
 };
-use clap::{Arg, Command};
-
-use super::restore_tree;
+use clap::{Arg, ArgAction, Command};
 
 pub fn get_stash_command() -> Command {
     Command::new("stash")
         .about("Save changes temporary")
         .subcommand_required(false) // Don't require it here
         .arg_required_else_help(false)
+        .args(get_stash_push_args())
         .subcommand(
             Command::new("save")
                 .about("Save stash with message")
                 .arg(Arg::new("message").required(true))
+                .args(get_stash_push_args())
                 .arg_required_else_help(true),
         )
         .subcommand(Command::new("list").about("List stashed changes"))
-        .subcommand(Command::new("pop").about("Apply latest stashed changes"))
+        .subcommand(
+            Command::new("show")
+                .about("Show the changes recorded in a stash entry")
+                .arg(get_stash_ref_arg())
+                .arg(
+                    Arg::new("patch")
+                        .short('p')
+                        .long("patch")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .help("Show the full diff instead of a summary"),
+                ),
+        )
+        .subcommand(
+            Command::new("pop")
+                .about("Apply stashed changes and drop them")
+                .arg(get_stash_ref_arg())
+                .arg(get_stash_index_arg()),
+        )
+        .subcommand(
+            Command::new("drop")
+                .about("Remove a single stash entry")
+                .arg(get_stash_ref_arg()),
+        )
         .subcommand(Command::new("clear").about("clear stash"))
         .subcommand(
             Command::new("apply")
                 .about("Apply specific stashed changes")
-                .arg(Arg::new("index").required(true))
+                .arg(get_stash_ref_arg())
+                .arg(get_stash_index_arg()),
+        )
+        .subcommand(
+            Command::new("branch")
+                .about("Create a branch at the stash base and apply the stash there")
+                .arg(Arg::new("name").required(true))
+                .arg(get_stash_ref_arg())
                 .arg_required_else_help(true),
         )
 }
 
-pub fn stash(message: Option<String>) {
+fn get_stash_ref_arg() -> Arg {
+    Arg::new("stash")
+        .required(false)
+        .help("Stash entry such as stash@{0}, defaults to the latest")
+}
+
+fn get_stash_push_args() -> [Arg; 2] {
+    [
+        Arg::new("include-untracked")
+            .short('u')
+            .long("include-untracked")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Also stash untracked files and remove them from the work tree"),
+        Arg::new("keep-index")
+            .long("keep-index")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .help("Leave staged changes in the index and the work tree"),
+    ]
+}
+
+fn get_stash_index_arg() -> Arg {
+    Arg::new("restore-index")
+        .long("index")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .help("Restore the staged state of the stash as well")
+}
+
+/// Save the index and the work tree as a stash commit and reset both to HEAD.
+///
+/// The layout follows git: the stash commit holds the work tree and has
+/// HEAD as its first parent, a commit of the index as its second parent
+/// and, with `--include-untracked`, a root commit of the untracked files as
+/// its third parent.
+pub fn stash(message: Option<String>, include_untracked: bool, keep_index: bool) {
     let current_dir = env::current_dir().unwrap();
     let vit_dir = current_dir.join(".vit");
     if !vit_dir.exists() {
         return eprintln!("vit repository not initialized!");
     }
 
-    let mut index_entries: Vec<utils::IndexEntry> = utils::read_index().unwrap();
-    let mut list_of_files: Vec<String> = Vec::new();
-    let mut deleted_files: Vec<FileChange> = Vec::new();
     let objects_path = vit_dir.join("objects");
-    let stash_ref = vit_dir.join("refs/stash");
 
-    let head_ref = fs::read_to_string(vit_dir.join("HEAD")).unwrap();
-    let current_branch_ref = head_ref.trim_start_matches("ref: ").trim();
-    let commit_ref = vit_dir.join(current_branch_ref);
-    let branch_name = commit_ref.file_name().unwrap().to_str().unwrap();
-
-    for entry in &index_entries {
-        if entry.status == FileStatus::Modified {
-            list_of_files.push(entry.path.clone());
-        }
-    }
+    let refs = RefStore::new(&vit_dir);
+    let branch_name = refs.current_branch().unwrap_or_else(|| "HEAD".to_string());
 
-    let current_files: Vec<String> = utils::expand_paths(&[".".to_string()]);
-    let mut stash_content: Vec<u8> = Vec::new();
+    let Some(head_hash) = refs.read("HEAD") else {
+        return eprintln!("You do not have the initial commit yet");
+    };
+    let head_commit = read_commit_file(&loose_object_path(&objects_path, &head_hash)).unwrap();
+    let mut index_entries: Vec<IndexEntry> = utils::read_index().unwrap();
+
+    // Snapshot the index as it is
+    let index_tree_hash = build_tree(&index_entries);
+
+    // Snapshot tracked files as they are in the work tree, deleted files are left out
+    let mut work_entries: Vec<IndexEntry> = Vec::new();
+    for entry in index_entries
+        .iter()
+        .filter(|entry| entry.status != FileStatus::Deleted)
+    {
+        let Ok(metadata) = fs::metadata(&entry.path) else {
+            continue;
+        };
 
-    for file_path in current_files {
-        match index_entries
-            .iter_mut()
-            .find(|entry| entry.path == file_path)
-        {
-            Some(existing_entry) => {
-                if !Path::new(&file_path).exists() {
-                    return;
-                }
+        let mtime = metadata
+            .modified()
+            .unwrap_or(SystemTime::now())
+            .duration_since(UNIX_EPOCH)
+            .unwrap();
 
-                // Step 3a: Get file metadata (timestamp, size, etc.)
-                let metadata = std::fs::metadata(&file_path).expect("Cannot stat file");
-
-                let mtime = metadata
-                    .modified()
-                    .unwrap_or(SystemTime::now())
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap();
-
-                if existing_entry.mtime_secs != mtime.as_secs() as u32 {
-                    existing_entry.mtime_nsecs = mtime.as_nanos() as u32;
-                    existing_entry.mtime_secs = mtime.as_secs() as u32;
-
-                    let (file_hash, _object) = utils::hash_file(&file_path);
-                    if existing_entry.sha256 != file_hash {
-                        if !list_of_files.contains(&file_path) {
-                            list_of_files.push(file_path.clone());
-
-                            let tree_entry = TreeEntry {
-                                mode: existing_entry.mode.to_string(),
-                                name: file_path.clone(),
-                                sha256: file_hash,
-                            };
-                            stash_content.extend_from_slice(&tree_entry.to_bytes());
-
-                            // Hash the file
-                            let (file_hash, file_content) = utils::hash_file(&file_path);
-
-                            // Store file object
-                            utils::store_object(&vit_dir, file_hash, file_content).unwrap();
-                        }
-                    }
-                }
-            }
-            None => {}
+        let mut work_entry = entry.clone();
+        if entry.mtime_secs != mtime.as_secs() as u32 {
+            work_entry.sha256 = utils::hash_and_store_file(&vit_dir, &entry.path).unwrap();
         }
+        work_entries.push(work_entry);
     }
+    let work_tree_hash = build_tree(&work_entries);
+
+    // Snapshot untracked files when asked to
+    let mut untracked_entries: Vec<IndexEntry> = Vec::new();
+    if include_untracked {
+        for file_path in utils::expand_paths(&[".".to_string()]) {
+            if index_entries.iter().any(|entry| entry.path == file_path) {
+                continue;
+            }
 
-    for entry in index_entries.iter() {
-        if !Path::new(entry.path.as_str()).exists() {
-            let file_change = FileChange {
-                path: entry.path.clone(),
-                file_type: utils::FileType::Blob,
-                action: utils::Action::Create,
-                sha256: entry.sha256,
-            };
-            deleted_files.push(file_change);
+            let metadata = fs::metadata(&file_path).unwrap();
+            let file_hash = utils::hash_and_store_file(&vit_dir, &file_path).unwrap();
+            untracked_entries.push(IndexEntry::from_metadata(&file_path, &metadata, file_hash));
         }
     }
 
-    // Restore all deleted files
-    for change in deleted_files {
-        restore_file(&change, &current_dir, &objects_path, &mut index_entries);
+    if index_tree_hash == head_commit.tree
+        && work_tree_hash == index_tree_hash
+        && untracked_entries.is_empty()
+    {
+        println!("No local changes to save");
+        return;
     }
 
-    if list_of_files.is_empty() {
-        println!("No Updates to stash!");
-        return;
+    let identity = match utils::get_identity() {
+        Ok(identity) => identity,
+        Err(err) => return eprintln!("{}", err),
     };
 
-    let prev_stash_hash = if stash_ref.exists() {
-        let prev_hash_str = fs::read_to_string(&stash_ref).unwrap();
-        hex::decode(prev_hash_str.trim()).unwrap()
-    } else {
-        vec![0u8; 32]
-    };
+    let short_hash = &hex::encode(head_hash)[..7];
+    let subject = head_commit.message.lines().next().unwrap_or("");
+    let description = format!("{}: {} {}", branch_name, short_hash, subject);
 
-    // Save tree object
-    let tree_hash = save_tree_object(&stash_content).unwrap();
-    let stash_message: String = match message.clone() {
-        Some(mes) => mes,
-        None => format!("WIP in progress on branch {}", branch_name),
-    };
-    let stash_hash = build_commit(tree_hash, &prev_stash_hash, &stash_message);
-
-    // Update stash head
-    fs::write(stash_ref, hex::encode(stash_hash)).expect("Failed to update reference!");
-
-    // Write Log Entry
-    let author_name = "Vivek";
-    let author_email = "vivek@example.com";
-    let log_entry_path = "refs/stash";
-    write_log_entry(
-        &prev_stash_hash,
-        &stash_hash,
-        &author_name,
-        &author_email,
-        &stash_message,
-        log_entry_path,
+    let index_hash = build_commit(
+        index_tree_hash,
+        &[head_hash],
+        &format!("index on {}", description),
+        &identity,
     );
+    let mut parents = vec![head_hash, index_hash];
+    if !untracked_entries.is_empty() {
+        let untracked_tree_hash = build_tree(&untracked_entries);
+        parents.push(build_commit(
+            untracked_tree_hash,
+            &[],
+            &format!("untracked files on {}", description),
+            &identity,
+        ));
+    }
 
-    // Reset Current Commit
-    let head_ref = fs::read_to_string(vit_dir.join("HEAD")).unwrap();
-    let current_branch_ref = head_ref.trim_start_matches("ref: ").trim();
-    let commit_ref = vit_dir.join(current_branch_ref);
-    let current_commit_hash = fs::read_to_string(&commit_ref).unwrap();
-
-    let current_commit_path = objects_path
-        .join(&current_commit_hash[..2])
-        .join(&current_commit_hash[2..]);
-
-    let commit_entry = read_commit_file(&current_commit_path).unwrap();
-    let current_tree_hash = hex::encode(commit_entry.tree);
-    let current_tree_path = objects_path
-        .join(&current_tree_hash[..2])
-        .join(&current_tree_hash[2..]);
-
-    restore_tree(
-        &current_tree_path,
-        &current_dir,
-        &objects_path,
-        &mut index_entries,
-    );
+    let stash_message: String = match message {
+        Some(mes) => format!("On {}: {}", branch_name, mes),
+        None => format!("WIP on {}", description),
+    };
+    let stash_hash = build_commit(work_tree_hash, &parents, &stash_message, &identity);
+
+    // Update stash head, the reflog holds every entry
+    refs.update("refs/stash", &stash_hash, None, &stash_message)
+        .unwrap();
+
+    // Reset the work tree to HEAD, or to the index with --keep-index
+    let work_tree = flatten_tree(&objects_path, &work_tree_hash);
+    if keep_index {
+        let index_tree = flatten_tree(&objects_path, &index_tree_hash);
+        update_work_tree(&objects_path, &work_tree, &index_tree).unwrap();
+
+        for entry in index_entries.iter_mut() {
+            if let Ok(metadata) = fs::metadata(&entry.path) {
+                entry.refresh_stat(&metadata);
+            }
+        }
+    } else {
+        let head_tree = flatten_tree(&objects_path, &head_commit.tree);
+        update_work_tree(&objects_path, &work_tree, &head_tree).unwrap();
+        index_entries = index_from_tree(&head_tree);
+    }
+
+    for entry in &untracked_entries {
+        utils::remove_work_tree_file(&entry.path).unwrap();
+    }
+
+    utils::write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
+    println!("Saved working directory and index state {}", stash_message);
 }
 
-pub fn apply(index: usize) {
+pub fn apply(stash_ref: Option<&str>, restore_index: bool) {
     let current_dir = env::current_dir().unwrap();
     let vit_dir = current_dir.join(".vit");
     if !vit_dir.exists() {
         return eprintln!("vit repository not initialized!");
     }
 
-    let objects_path = vit_dir.join("objects");
-    let stash_path = vit_dir.join("logs/refs/stash");
+    let result = resolve_stash(&vit_dir, stash_ref)
+        .and_then(|(_, stash_hash)| apply_stash(&vit_dir, &stash_hash, restore_index));
 
-    if !stash_path.exists() {
-        return;
+    if let Err(message) = result {
+        eprintln!("{}", message);
+    }
+}
+
+pub fn pop(stash_ref: Option<&str>, restore_index: bool) {
+    let current_dir = env::current_dir().unwrap();
+    let vit_dir = current_dir.join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
     }
 
-    let logs_data = fs::read_to_string(&stash_path).unwrap();
-    let lines: Vec<&str> = logs_data.lines().rev().collect();
+    let (index, stash_hash) = match resolve_stash(&vit_dir, stash_ref) {
+        Ok(stash) => stash,
+        Err(message) => return eprintln!("{}", message),
+    };
 
-    if index >= lines.len() {
-        return eprintln!("Invalid stash index!");
+    // Keep the entry around when it could not be applied
+    if let Err(message) = apply_stash(&vit_dir, &stash_hash, restore_index) {
+        return eprintln!("{}", message);
     }
 
-    let stash_log = lines[index];
-    let parts: Vec<&str> = stash_log.splitn(8, ' ').collect();
-    let current_commit_hash = parts[1].to_string();
-    let current_commit_path = objects_path
-        .join(&current_commit_hash[..2])
-        .join(&current_commit_hash[2..]);
+    drop_stash(&vit_dir, index);
+}
 
-    let stash_entry = read_commit_file(&current_commit_path).unwrap();
-    let stash_tree_hash = hex::encode(&stash_entry.tree);
-    let current_tree_hash = objects_path
-        .join(&stash_tree_hash[..2])
-        .join(&stash_tree_hash[2..]);
+pub fn stash_drop(stash_ref: Option<&str>) {
+    let current_dir = env::current_dir().unwrap();
+    let vit_dir = current_dir.join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    match resolve_stash(&vit_dir, stash_ref) {
+        Ok((index, _)) => drop_stash(&vit_dir, index),
+        Err(message) => eprintln!("{}", message),
+    }
+}
+
+pub fn stash_show(stash_ref: Option<&str>, patch: bool) {
+    let current_dir = env::current_dir().unwrap();
+    let vit_dir = current_dir.join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
 
-    // Restore stashed files
-    let tree_entries = parse_tree_entries(&current_tree_hash).unwrap();
-    for entry in tree_entries {
-        let name = current_dir.join(&entry.name);
-        let hash_str = hex::encode(&entry.sha256);
+    let objects_path = vit_dir.join("objects");
+    let stash_hash = match resolve_stash(&vit_dir, stash_ref) {
+        Ok((_, stash_hash)) => stash_hash,
+        Err(message) => return eprintln!("{}", message),
+    };
 
-        let blob_path = objects_path.join(&hash_str[..2]).join(&hash_str[2..]);
-        let decompressed = decompress_file_content(&blob_path).unwrap();
-        let blob_data = &decompressed[decompressed.iter().position(|&b| b == 0).unwrap() + 1..];
+    let stash_entry = read_commit(&objects_path, &stash_hash);
+    let Some(base_hash) = stash_entry.parents.first() else {
+        return eprintln!("{} is not a valid stash commit", hex::encode(stash_hash));
+    };
+    let base_entry = read_commit(&objects_path, base_hash);
 
-        // Make sure all parent directories are present
-        fs::create_dir_all(name.parent().unwrap()).unwrap();
+    let changes = diff_trees(
+        &objects_path,
+        Some(&base_entry.tree),
+        Some(&stash_entry.tree),
+    );
 
-        // Write file data
-        fs::write(&name, blob_data).unwrap();
+    utils::setup_pager();
+    if patch {
+        print!("{}", format_tree_patch(&objects_path, &changes));
+    } else {
+        print!("{}", format_tree_stat(&objects_path, &changes));
     }
 }
 
-pub fn pop() {
+/// Check out the commit a stash was made on as a new branch, then apply
+/// the stash there with its index and drop it.
+pub fn stash_branch(name: &str, stash_ref: Option<&str>) {
     let current_dir = env::current_dir().unwrap();
     let vit_dir = current_dir.join(".vit");
     if !vit_dir.exists() {
@@ -251,74 +324,263 @@ pub fn pop() {
     }
 
     let objects_path = vit_dir.join("objects");
-    let stash_ref = vit_dir.join("refs/stash");
-    let stash_path = vit_dir.join("logs/refs/stash");
+    let refs = RefStore::new(&vit_dir);
+    let branch_ref = format!("refs/heads/{}", name);
+    if !is_valid_ref_short_name(name) {
+        return eprintln!("'{}' is not a valid branch name", name);
+    }
+    if refs.read(&branch_ref).is_some() {
+        return eprintln!("A branch named '{}' already exists", name);
+    }
+    if let Some(existing) = refs.conflicting(&branch_ref) {
+        return eprintln!("Cannot create '{}', '{}' exists", branch_ref, existing);
+    }
 
-    if !stash_path.exists() {
-        return;
+    let (index, stash_hash) = match resolve_stash(&vit_dir, stash_ref) {
+        Ok(stash) => stash,
+        Err(message) => return eprintln!("{}", message),
+    };
+
+    let stash_entry = read_commit(&objects_path, &stash_hash);
+    let Some(base_hash) = stash_entry.parents.first() else {
+        return eprintln!("{} is not a valid stash commit", hex::encode(stash_hash));
+    };
+
+    let index_entries: Vec<IndexEntry> = utils::read_index().unwrap();
+    if has_local_changes(&index_entries) {
+        return eprintln!("You have local changes, commit or stash them before creating a branch");
+    }
+
+    // Move HEAD, the work tree and the index over to the stash base
+    let Some(head_hash) = refs.read("HEAD") else {
+        return eprintln!("You do not have the initial commit yet");
+    };
+
+    let head_tree = flatten_tree(&objects_path, &read_commit(&objects_path, &head_hash).tree);
+    let base_tree = flatten_tree(&objects_path, &read_commit(&objects_path, base_hash).tree);
+
+    let from = refs.current_branch().unwrap_or_else(|| "HEAD".to_string());
+    refs.update(
+        &branch_ref,
+        base_hash,
+        Some([0u8; 32]),
+        &format!("branch: Created from stash@{{{}}}", index),
+    )
+    .unwrap();
+    refs.set_symbolic(
+        "HEAD",
+        &branch_ref,
+        &format!("checkout: moving from {} to {}", from, name),
+    )
+    .unwrap();
+
+    update_work_tree(&objects_path, &head_tree, &base_tree).unwrap();
+    let base_index = index_from_tree(&base_tree);
+    utils::write_index(&base_index, vit_dir.join("index").to_str().unwrap()).unwrap();
+    println!("Switched to a new branch '{}'", name);
+    run_post_checkout(&vit_dir, &head_hash, base_hash);
+
+    if let Err(message) = apply_stash(&vit_dir, &stash_hash, true) {
+        return eprintln!("{}", message);
     }
 
-    let logs_data = fs::read_to_string(&stash_path).unwrap();
-    let mut lines: Vec<&str> = logs_data.lines().collect();
+    drop_stash(&vit_dir, index);
+}
+
+/// Replay a stash onto the work tree, and onto the index with `restore_index`.
+///
+/// Every file is merged three ways between the commit the stash was made
+/// on, the stashed content and what is in the work tree now, so edits made
+/// since the stash are kept. Overlapping edits are left with conflict
+/// markers and reported as an error so `pop` keeps the entry. Untracked
+/// files from the stash never overwrite existing files.
+fn apply_stash(vit_dir: &Path, stash_hash: &[u8; 32], restore_index: bool) -> Result<(), String> {
+    let objects_path = vit_dir.join("objects");
+    let stash_entry = read_commit_file(&loose_object_path(&objects_path, stash_hash)).unwrap();
+
+    if stash_entry.parents.len() < 2 {
+        return Err(format!(
+            "{} is not a valid stash commit",
+            hex::encode(stash_hash)
+        ));
+    }
+
+    let base_entry = read_commit(&objects_path, &stash_entry.parents[0]);
+    let index_entry = read_commit(&objects_path, &stash_entry.parents[1]);
+
+    let base_tree = flatten_tree(&objects_path, &base_entry.tree);
+    let stash_tree = flatten_tree(&objects_path, &stash_entry.tree);
+    let staged_tree = flatten_tree(&objects_path, &index_entry.tree);
+    let untracked_tree = match stash_entry.parents.get(2) {
+        Some(untracked_hash) => flatten_tree(
+            &objects_path,
+            &read_commit(&objects_path, untracked_hash).tree,
+        ),
+        None => BTreeMap::new(),
+    };
 
-    let current_stash = lines.pop().unwrap();
-    let parts: Vec<&str> = current_stash.splitn(8, ' ').collect();
-    let current_commit_hash = parts[1].to_string();
-    let current_commit_path = objects_path
-        .join(&current_commit_hash[..2])
-        .join(&current_commit_hash[2..]);
+    for path in untracked_tree.keys() {
+        if Path::new(path).exists() {
+            return Err(format!("{} already exists, no checkout", path));
+        }
+    }
 
-    let stash_entry = read_commit_file(&current_commit_path).unwrap();
-    let stash_tree_hash = hex::encode(&stash_entry.tree);
-    let current_tree_hash = objects_path
-        .join(&stash_tree_hash[..2])
-        .join(&stash_tree_hash[2..]);
+    // Merge the stashed changes into the work tree
+    let mut touched: Vec<String> = Vec::new();
+    let mut conflicts: Vec<String> = Vec::new();
 
-    // Restore stashed files
-    let tree_entries = parse_tree_entries(&current_tree_hash).unwrap();
-    for entry in tree_entries {
-        let name = current_dir.join(&entry.name);
-        let hash_str = hex::encode(&entry.sha256);
+    let mut paths: Vec<&String> = base_tree.keys().chain(stash_tree.keys()).collect();
+    paths.sort();
+    paths.dedup();
 
-        let blob_path = objects_path.join(&hash_str[..2]).join(&hash_str[2..]);
-        let decompressed = decompress_file_content(&blob_path).unwrap();
-        let blob_data = &decompressed[decompressed.iter().position(|&b| b == 0).unwrap() + 1..];
+    for path in paths {
+        let base_hash = base_tree.get(path).map(|e| e.sha256);
+        let stash_hash = stash_tree.get(path).map(|e| e.sha256);
+        if base_hash == stash_hash {
+            continue;
+        }
 
-        // Make sure all parent directories are present
-        fs::create_dir_all(name.parent().unwrap()).unwrap();
+        let current_hash = utils::hash_file_streaming(path).ok();
+        if current_hash == stash_hash {
+            continue;
+        }
+
+        touched.push(path.clone());
+        if current_hash == base_hash {
+            match stash_hash {
+                Some(hash) => write_work_tree_file(&objects_path, path, &hash),
+                None => remove_work_tree_file(path),
+            }
+            .map_err(|err| err.to_string())?;
+            continue;
+        }
+
+        match (current_hash, stash_hash) {
+            (Some(_), Some(hash)) => {
+                let base_content = match base_hash {
+                    Some(hash) => read_object_by_hash(&objects_path, &hash).unwrap().1,
+                    None => Vec::new(),
+                };
+                let current_content = fs::read(path).map_err(|err| err.to_string())?;
+                let stash_content = read_object_by_hash(&objects_path, &hash).unwrap().1;
+
+                let merged = merge_three_way(
+                    &base_content,
+                    &current_content,
+                    &stash_content,
+                    "Updated upstream",
+                    "Stashed changes",
+                );
+                fs::write(path, &merged.content).map_err(|err| err.to_string())?;
+
+                if merged.conflicts > 0 {
+                    println!("CONFLICT (content): Merge conflict in {}", path);
+                    conflicts.push(path.clone());
+                } else {
+                    println!("Auto-merging {}", path);
+                }
+            }
+            (Some(_), None) => {
+                println!(
+                    "CONFLICT (modify/delete): {} deleted in stash and modified in work tree",
+                    path
+                );
+                conflicts.push(path.clone());
+            }
+            (None, Some(hash)) => {
+                write_work_tree_file(&objects_path, path, &hash).map_err(|err| err.to_string())?;
+                println!(
+                    "CONFLICT (modify/delete): {} deleted in work tree and modified in stash",
+                    path
+                );
+                conflicts.push(path.clone());
+            }
+            (None, None) => {}
+        }
+    }
 
-        // Write file data
-        fs::write(&name, blob_data).unwrap();
-        fs::remove_file(blob_path).unwrap();
+    for (path, entry) in &untracked_tree {
+        write_work_tree_file(&objects_path, path, &entry.sha256).map_err(|err| err.to_string())?;
     }
 
-    // Remove tree entry
-    fs::remove_file(current_tree_hash).unwrap();
+    // Restore the index, files added in the stash are always staged again
+    let mut index_entries: Vec<IndexEntry> = utils::read_index().unwrap();
+    let target_index = if restore_index {
+        &staged_tree
+    } else {
+        &stash_tree
+    };
+
+    for (path, entry) in target_index {
+        let is_added = !base_tree.contains_key(path);
+        let is_changed = base_tree.get(path).map(|e| e.sha256) != Some(entry.sha256);
+        if !(is_added || restore_index && is_changed) || conflicts.contains(path) {
+            continue;
+        }
 
-    // Remove stash commit entry
-    fs::remove_file(current_commit_path).unwrap();
+        stage_stashed_file(&mut index_entries, path, entry, is_added);
+    }
 
-    if lines.is_empty() {
-        // Remove stash head reference
-        if stash_ref.exists() {
-            fs::remove_file(&stash_ref).unwrap();
+    if restore_index {
+        for path in base_tree.keys() {
+            if !staged_tree.contains_key(path)
+                && !conflicts.contains(path)
+                && let Some(entry) = index_entries.iter_mut().find(|e| &e.path == path)
+            {
+                entry.status = FileStatus::Deleted;
+            }
         }
+    }
 
-        // Remove stash head reference
-        if stash_path.exists() {
-            fs::remove_file(&stash_path).unwrap();
+    // Files may be rewritten within the same second, force a rehash where
+    // the work tree was touched or holds something other than the staged blob
+    for entry in index_entries.iter_mut() {
+        if touched.contains(&entry.path)
+            || stash_tree.get(&entry.path).map(|e| e.sha256) != Some(entry.sha256)
+        {
+            entry.mtime_secs = 0;
+            entry.mtime_nsecs = 0;
         }
+    }
+
+    index_entries.sort_by(|a, b| a.path.cmp(&b.path));
+    utils::write_index(&index_entries, vit_dir.join("index").to_str().unwrap()).unwrap();
+
+    if !conflicts.is_empty() {
+        return Err("The stash entry is kept in case you need it again.".to_string());
+    }
+
+    Ok(())
+}
+
+fn stage_stashed_file(
+    index_entries: &mut Vec<IndexEntry>,
+    path: &str,
+    entry: &TreeEntry,
+    is_added: bool,
+) {
+    let status = if is_added {
+        FileStatus::New
     } else {
-        // Update stash head reference
-        let next_stash = lines.pop().unwrap();
-        let next_parts: Vec<&str> = next_stash.splitn(8, ' ').collect();
-        let current_commit_hash = next_parts[1].to_string();
+        FileStatus::Modified
+    };
+
+    let mut new_entry = match fs::metadata(path) {
+        Ok(metadata) => IndexEntry::from_metadata(path, &metadata, entry.sha256),
+        Err(_) => return,
+    };
+    new_entry.status = status;
 
-        fs::write(&stash_ref, current_commit_hash).unwrap();
-        delete_last_line(&stash_path).unwrap();
+    match index_entries.iter_mut().find(|e| e.path == path) {
+        Some(existing) => *existing = new_entry,
+        None => index_entries.push(new_entry),
     }
 }
 
+fn read_commit(objects_path: &Path, hash: &[u8; 32]) -> CommitEntry {
+    read_commit_file(&loose_object_path(objects_path, hash)).unwrap()
+}
+
 pub fn list() {
     let current_dir = env::current_dir().unwrap();
     let vit_dir = current_dir.join(".vit");
@@ -326,24 +588,9 @@ pub fn list() {
         return eprintln!("vit repository not initialized!");
     }
 
-    let stash_path = vit_dir.join("logs/refs/stash");
-
-    if !stash_path.exists() {
-        return;
-    }
-
-    let logs_data = fs::read_to_string(stash_path).unwrap();
-    let lines: Vec<&str> = logs_data.lines().collect();
-
-    for (i, line) in lines.iter().rev().enumerate() {
-        let parts: Vec<&str> = line.splitn(8, ' ').collect();
-        if parts.len() < 8 {
-            continue;
-        }
-
-        let message = parts[7].trim();
-        let hash = parts[1].to_string();
-        println!("stash@{{{}}}: {}: {}", i, &hash[..8], message);
+    for (i, entry) in read_reflog(&vit_dir, "refs/stash").iter().rev().enumerate() {
+        let hash = hex::encode(entry.new);
+        println!("stash@{{{}}}: {}: {}", i, &hash[..8], entry.message.trim());
     }
 }
 
@@ -355,79 +602,156 @@ pub fn clear() {
     }
 
     let objects_path = vit_dir.join("objects");
-    let stash_ref = vit_dir.join("refs/stash");
+    let refs = RefStore::new(&vit_dir);
     let stash_path = vit_dir.join("logs/refs/stash");
 
     if !stash_path.exists() {
         return;
     }
 
-    let logs_data = fs::read_to_string(&stash_path).unwrap();
-    let lines: Vec<&str> = logs_data.lines().collect();
+    let mut stashed_objects: Vec<[u8; 32]> = Vec::new();
+    for entry in read_reflog(&vit_dir, "refs/stash") {
+        stashed_objects.extend(stash_objects(&objects_path, &entry.new));
+    }
 
-    for current_stash in lines {
-        let parts: Vec<&str> = current_stash.splitn(8, ' ').collect();
-        let current_commit_hash = parts[1].to_string();
-        let current_commit_path = objects_path
-            .join(&current_commit_hash[..2])
-            .join(&current_commit_hash[2..]);
+    // Remove stash head reference
+    if refs.read("refs/stash").is_some() {
+        refs.delete("refs/stash", None, "stash: cleared").unwrap();
+    }
 
-        let stash_entry = read_commit_file(&current_commit_path).unwrap();
-        let stash_tree_hash = hex::encode(&stash_entry.tree);
-        let current_tree_hash = objects_path
-            .join(&stash_tree_hash[..2])
-            .join(&stash_tree_hash[2..]);
+    // Remove stash log
+    fs::remove_file(&stash_path).unwrap();
 
-        // Restore stashed files
-        let tree_entries = parse_tree_entries(&current_tree_hash).unwrap();
-        for entry in tree_entries {
-            let hash_str = hex::encode(&entry.sha256);
-            let blob_path = objects_path.join(&hash_str[..2]).join(&hash_str[2..]);
+    // Blobs may be shared with history or the index, only drop what is now unused
+    remove_unreferenced_objects(&vit_dir, &stashed_objects);
+}
+
+/// The stash commit, its index and untracked commits and every tree and
+/// blob below them.
+fn stash_objects(objects_path: &Path, stash_hash: &[u8; 32]) -> Vec<[u8; 32]> {
+    let mut objects = vec![*stash_hash];
+
+    let stash_entry = read_commit(objects_path, stash_hash);
+    let mut pending_trees = vec![stash_entry.tree];
+
+    // The first parent is the commit the stash was made on, not part of the stash
+    for parent in stash_entry.parents.iter().skip(1) {
+        objects.push(*parent);
+        pending_trees.push(read_commit(objects_path, parent).tree);
+    }
+
+    while let Some(tree_hash) = pending_trees.pop() {
+        objects.push(tree_hash);
 
-            if blob_path.exists() {
-                fs::remove_file(blob_path).unwrap();
+        for entry in parse_tree_entries(&loose_object_path(objects_path, &tree_hash)).unwrap() {
+            if entry.mode == "040000" {
+                pending_trees.push(entry.sha256);
+            } else {
+                objects.push(entry.sha256);
             }
         }
+    }
 
-        // Remove tree entry
-        if current_tree_hash.exists() {
-            fs::remove_file(current_tree_hash).unwrap();
-        }
+    objects
+}
 
-        // Remove stash commit entry
-        if current_commit_path.exists() {
-            fs::remove_file(current_commit_path).unwrap();
-        }
+/// Delete the given loose objects, skipping any that are still reachable
+/// from a ref, a reflog, another stash entry or the index.
+fn remove_unreferenced_objects(vit_dir: &Path, objects: &[[u8; 32]]) {
+    let objects_path = vit_dir.join("objects");
+    let reachable = match reachable_objects(vit_dir) {
+        Ok(reachable) => reachable,
+        Err(err) => return eprintln!("Unable to compute reachable objects: {}", err),
+    };
 
-        // Remove stash head reference
-        if stash_ref.exists() {
-            fs::remove_file(&stash_ref).unwrap();
+    for hash in objects {
+        let object_path = loose_object_path(&objects_path, hash);
+        if !reachable.contains(hash) && object_path.exists() {
+            fs::remove_file(object_path).unwrap();
         }
+    }
+}
 
-        // Remove stash head reference
-        if stash_path.exists() {
-            fs::remove_file(&stash_path).unwrap();
-        }
+/// Turn `stash@{n}` or `n` into the entry position and its commit hash,
+/// the latest entry is used when no reference is given.
+fn resolve_stash(vit_dir: &Path, stash_ref: Option<&str>) -> Result<(usize, [u8; 32]), String> {
+    let entries = read_reflog(vit_dir, "refs/stash");
+    if entries.is_empty() {
+        return Err("No stash entries found.".to_string());
+    }
+
+    let index = match stash_ref {
+        None => 0,
+        Some(input) => input
+            .strip_prefix("stash@{")
+            .and_then(|rest| rest.strip_suffix('}'))
+            .unwrap_or(input)
+            .parse::<usize>()
+            .map_err(|_| format!("{} is not a valid reference", input))?,
+    };
+
+    match entries.iter().rev().nth(index) {
+        Some(entry) => Ok((index, entry.new)),
+        None => Err(format!("stash@{{{}}} does not exist", index)),
     }
 }
 
-fn delete_last_line(path: &Path) -> std::io::Result<()> {
-    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
-    let mut content = Vec::new();
-    file.read_to_end(&mut content)?;
-
-    // Find the position of the second-last newline
-    if let Some(pos) = content.iter().rposition(|&b| b == b'\n') {
-        let truncate_pos = if pos == 0 {
-            0 // file had only one line
-        } else {
-            content[..pos]
-                .iter()
-                .rposition(|&b| b == b'\n')
-                .map_or(0, |p| p + 1)
-        };
-        file.set_len(truncate_pos as u64)?;
+/// Remove a single entry from the stash reflog and delete the objects only
+/// it was holding on to.
+///
+/// The old hash of the entry following the dropped one is rewritten so the
+/// reflog stays a chain, and `refs/stash` is moved to the newest remaining
+/// entry.
+fn drop_stash(vit_dir: &Path, index: usize) {
+    let objects_path = vit_dir.join("objects");
+    let refs = RefStore::new(vit_dir);
+    let stash_path = vit_dir.join("logs/refs/stash");
+
+    let mut entries = read_reflog(vit_dir, "refs/stash");
+    let removed = entries.remove(entries.len() - 1 - index);
+    let dropped_objects = stash_objects(&objects_path, &removed.new);
+
+    if entries.is_empty() {
+        // Remove stash head reference, then the log it leaves behind
+        if refs.read("refs/stash").is_some() {
+            refs.delete("refs/stash", None, "stash: dropped").unwrap();
+        }
+        fs::remove_file(&stash_path).unwrap();
+    } else {
+        let mut previous = [0u8; 32];
+        for entry in &mut entries {
+            entry.old = previous;
+            previous = entry.new;
+        }
+
+        // Moving the ref logs the move, the rewritten chain replaces that
+        refs.update("refs/stash", &previous, None, "stash: dropped")
+            .unwrap();
+        write_reflog(vit_dir, "refs/stash", &entries).unwrap();
     }
 
-    Ok(())
+    // Blobs may be shared with history or the index, only drop what is now unused
+    remove_unreferenced_objects(vit_dir, &dropped_objects);
+    println!("Dropped stash@{{{}}} ({})", index, hex::encode(removed.new));
+}
+
+/// Whether the index or any tracked file differs from HEAD.
+fn has_local_changes(index_entries: &[IndexEntry]) -> bool {
+    index_entries.iter().any(|entry| {
+        if entry.status != FileStatus::Unchanged {
+            return true;
+        }
+
+        let Ok(metadata) = fs::metadata(&entry.path) else {
+            return true;
+        };
+        let mtime = metadata
+            .modified()
+            .unwrap_or(SystemTime::now())
+            .duration_since(UNIX_EPOCH)
+            .unwrap();
+
+        entry.mtime_secs != mtime.as_secs() as u32
+            && utils::hash_file_streaming(&entry.path).unwrap() != entry.sha256
+    })
 }
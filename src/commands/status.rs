@@ -1,5 +1,5 @@
 use crate::utils;
-use clap::Command;
+use clap::{Arg, Command};
 use colored::*;
 use std::{
     env,
@@ -8,22 +8,98 @@ use std::{
 };
 
 pub fn get_status_command() -> Command {
-    Command::new("status").about("Check the status of changes")
+    Command::new("status")
+        .about("Check the status of changes")
+        .arg(
+            Arg::new("paths")
+                .num_args(0..)
+                .help("Only show files matching these pathspecs"),
+        )
 }
 
-pub fn status() {
+pub fn status(paths: &[String]) {
     let current_dir: PathBuf = env::current_dir().expect("Directory not found!");
     let vit_dir: PathBuf = current_dir.join(".vit");
     if !vit_dir.exists() {
         return eprintln!("vit repository not initialized!");
     }
 
-    let files_to_add: Vec<String> = utils::expand_paths(&[String::from(".")]);
+    let refs = utils::RefStore::new(&vit_dir);
+    let branch = refs.current_branch().unwrap_or_else(|| "HEAD".to_string());
+    let unborn = refs.read("HEAD").is_none();
+
+    let pathspec = match utils::Pathspec::parse(paths) {
+        Ok(pathspec) => pathspec,
+        Err(err) => return eprintln!("fatal: {}", err),
+    };
+
     let index_entries: Vec<utils::IndexEntry> = utils::read_index().unwrap();
+    let mut lists = collect_status(&index_entries);
+    lists.staged.retain(|(_, path)| pathspec.matches(path));
+    lists.unstaged.retain(|(_, path)| pathspec.matches(path));
+    lists.untracked.retain(|path| pathspec.matches(path));
+
+    let added_files: Vec<String> = lists
+        .staged
+        .iter()
+        .map(|(label, path)| format!("  {} {}", label.green(), path.green()))
+        .collect();
+    let changed_files: Vec<String> = lists
+        .unstaged
+        .iter()
+        .map(|(label, path)| format!("  {} {}", label.red(), path.red()))
+        .collect();
+    let untracked_files = lists.untracked;
+
+    if unborn {
+        println!("No commits yet on {}", branch);
+        println!();
+    }
+
+    if added_files.is_empty() && changed_files.is_empty() && untracked_files.is_empty() {
+        if unborn {
+            println!("Nothing to commit (create files and use \"vit add\" to track)");
+        } else {
+            println!("Everything is up to date on {} branch", branch);
+        }
+    }
+
+    if !added_files.is_empty() {
+        println!("Changes to be committed:");
+        for file in added_files {
+            println!("{}", file)
+        }
+        println!();
+    }
+    if !changed_files.is_empty() {
+        println!("Changes not staged for commit:");
+        for file in changed_files {
+            println!("{}", file)
+        }
+        println!();
+    }
+    if !untracked_files.is_empty() {
+        println!("Untracked files:");
+        for file in untracked_files {
+            println!("  {}", file.red())
+        }
+    }
+}
 
-    let mut untracked_files: Vec<String> = Vec::new();
-    let mut added_files: Vec<String> = Vec::new();
-    let mut changed_files: Vec<String> = Vec::new();
+/// Files grouped the way `status` shows them, with the label of each.
+#[derive(Debug, Default)]
+pub struct StatusLists {
+    pub staged: Vec<(&'static str, String)>,
+    pub unstaged: Vec<(&'static str, String)>,
+    pub untracked: Vec<String>,
+}
+
+/// Compare the work tree with the index, paths are relative to the top of
+/// the work tree.
+pub fn collect_status(index_entries: &[utils::IndexEntry]) -> StatusLists {
+    let files_to_add: Vec<String> = utils::expand_paths(&[String::from(".")]);
+
+    let mut lists = StatusLists::default();
 
     for file_path in files_to_add {
         // Step 3a: Get file metadata (timestamp, size, etc.)
@@ -35,44 +111,35 @@ pub fn status() {
             .duration_since(UNIX_EPOCH)
             .unwrap();
 
-        match index_entries
-            .iter()
-            .find(|entry| entry.path == file_path)
-        {
+        match index_entries.iter().find(|entry| entry.path == file_path) {
             Some(existing_entry) => {
+                // Staged and unstaged changes are independent, a staged file
+                // may have been edited again since
+                let status = existing_entry.status;
+                let status_message = if status == utils::FileStatus::New {
+                    "new file:"
+                } else if status == utils::FileStatus::Modified {
+                    "modified:"
+                } else if status == utils::FileStatus::Deleted {
+                    "deleted: "
+                } else {
+                    "Unchanged"
+                };
+
+                if status_message != "Unchanged" {
+                    lists.staged.push((status_message, file_path.clone()));
+                }
+
                 if existing_entry.mtime_secs != mtime.as_secs() as u32 {
-                    let (file_hash, _object) = utils::hash_file(&file_path);
+                    let file_hash = utils::hash_file_streaming(&file_path).unwrap();
 
                     if existing_entry.sha256 != file_hash {
-                        changed_files.push(format!(
-                            "  {} {}",
-                            "modified:".red(),
-                            file_path.clone().red()
-                        ));
-                    }
-                } else {
-                    let status = existing_entry.status;
-                    let status_message = if status == utils::FileStatus::New {
-                        "new file:"
-                    } else if status == utils::FileStatus::Modified {
-                        "modified:"
-                    } else if status == utils::FileStatus::Deleted {
-                        "deleted: "
-                    } else {
-                        "Unchanged"
-                    };
-
-                    if status_message != "Unchanged" {
-                        added_files.push(format!(
-                            "  {} {}",
-                            status_message.green(),
-                            file_path.clone().green()
-                        ));
+                        lists.unstaged.push(("modified:", file_path.clone()));
                     }
                 }
             }
             None => {
-                untracked_files.push(file_path.clone());
+                lists.untracked.push(file_path.clone());
             }
         }
     }
@@ -80,44 +147,12 @@ pub fn status() {
     for entry in index_entries.iter() {
         if !Path::new(entry.path.as_str()).exists() {
             if entry.status != utils::FileStatus::Deleted {
-                changed_files.push(format!(
-                    "  {} {}",
-                    "deleted: ".red(),
-                    entry.path.clone().red()
-                ));
+                lists.unstaged.push(("deleted: ", entry.path.clone()));
             } else {
-                added_files.push(format!(
-                    "  {} {}",
-                    "deleted: ".green(),
-                    entry.path.clone().green()
-                ));
+                lists.staged.push(("deleted: ", entry.path.clone()));
             }
         }
     }
 
-    if added_files.is_empty() && changed_files.is_empty() && untracked_files.is_empty() {
-        println!("Everything is up to date on main branch");
-    }
-
-    if !added_files.is_empty() {
-        println!("Changes to be committed:");
-        for file in added_files {
-            println!("{}", file)
-        }
-        println!();
-    }
-    if !changed_files.is_empty() {
-        println!("Changes not staged for commit:");
-        for file in changed_files {
-            println!("{}", file)
-        }
-        println!();
-    }
-    if !untracked_files.is_empty() {
-        println!("Untracked files:");
-        for file in untracked_files {
-            println!("  {}", file.red())
-        }
-    }
-
+    lists
 }
@@ -0,0 +1,80 @@
+use crate::utils::RefStore;
+use clap::{Arg, ArgAction, Command};
+use std::env;
+
+pub fn get_symbolic_ref_command() -> Command {
+    Command::new("symbolic-ref")
+        .about("Read, change or delete a symbolic ref such as HEAD")
+        .arg(
+            Arg::new("name")
+                .required(true)
+                .help("Symbolic ref to read or change, e.g. HEAD"),
+        )
+        .arg(
+            Arg::new("ref")
+                .required(false)
+                .help("Ref the symbolic ref should point at, e.g. refs/heads/main"),
+        )
+        .arg(
+            Arg::new("delete")
+                .short('d')
+                .long("delete")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("ref")
+                .help("Delete the symbolic ref"),
+        )
+        .arg(
+            Arg::new("short")
+                .long("short")
+                .action(ArgAction::SetTrue)
+                .help("Print main instead of refs/heads/main"),
+        )
+        .arg(
+            Arg::new("message")
+                .short('m')
+                .value_name("REASON")
+                .help("Reason recorded in the reflog of HEAD"),
+        )
+}
+
+/// Print the target of `name`, or point it at `target` when given.
+pub fn symbolic_ref(name: &str, target: Option<&str>, short: bool, message: Option<&str>) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    let refs = RefStore::new(&vit_dir);
+
+    if let Some(target) = target {
+        let message = message.unwrap_or("symbolic-ref");
+        if let Err(err) = refs.set_symbolic(name, target, message) {
+            eprintln!("fatal: {}", err);
+        }
+        return;
+    }
+
+    match refs.read_symbolic(name) {
+        Some(target) if short => {
+            let short_name = target
+                .strip_prefix("refs/heads/")
+                .or_else(|| target.strip_prefix("refs/tags/"))
+                .or_else(|| target.strip_prefix("refs/"))
+                .unwrap_or(&target);
+            println!("{}", short_name);
+        }
+        Some(target) => println!("{}", target),
+        None => eprintln!("fatal: ref {} is not a symbolic ref", name),
+    }
+}
+
+pub fn symbolic_ref_delete(name: &str) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    if let Err(err) = RefStore::new(&vit_dir).delete_symbolic(name) {
+        eprintln!("fatal: {}", err);
+    }
+}
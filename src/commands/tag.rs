@@ -0,0 +1,179 @@
+use crate::utils::{
+    RefStore, TagEntry, get_identity, is_valid_ref_short_name, local_timezone, read_object_by_hash,
+    resolve_revision, write_loose_object,
+};
+use clap::{Arg, ArgAction, Command};
+use std::env;
+
+pub fn get_tag_command() -> Command {
+    Command::new("tag")
+        .about("Create, list or delete tags")
+        .arg(
+            Arg::new("name")
+                .required(false)
+                .help("Name of the tag, or a pattern such as 'v1.*' with -l"),
+        )
+        .arg(
+            Arg::new("revision")
+                .required(false)
+                .help("Commit to tag, defaults to HEAD"),
+        )
+        .arg(
+            Arg::new("annotate")
+                .short('a')
+                .long("annotate")
+                .action(ArgAction::SetTrue)
+                .help("Create an annotated tag object"),
+        )
+        .arg(
+            Arg::new("message")
+                .short('m')
+                .long("message")
+                .help("Message of an annotated tag, implies -a"),
+        )
+        .arg(
+            Arg::new("list")
+                .short('l')
+                .long("list")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["delete", "annotate", "message", "revision"])
+                .help("List tags, optionally matching a pattern"),
+        )
+        .arg(
+            Arg::new("delete")
+                .short('d')
+                .long("delete")
+                .action(ArgAction::SetTrue)
+                .requires("name")
+                .conflicts_with_all(["annotate", "message", "revision"])
+                .help("Delete the specified tag"),
+        )
+        .arg(
+            Arg::new("force")
+                .short('f')
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Replace an existing tag"),
+        )
+}
+
+/// Point `refs/tags/<name>` at a commit, through a new tag object when a
+/// message is given.
+pub fn tag(name: &str, revision: Option<&str>, message: Option<&str>, annotate: bool, force: bool) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    if !is_valid_ref_short_name(name) {
+        return eprintln!("fatal: '{}' is not a valid tag name.", name);
+    }
+
+    let refs = RefStore::new(&vit_dir);
+    let ref_name = format!("refs/tags/{}", name);
+    let previous = refs.read(&ref_name);
+    if previous.is_some() && !force {
+        return eprintln!("fatal: tag '{}' already exists", name);
+    }
+    if let Some(existing) = refs.conflicting(&ref_name) {
+        return eprintln!("fatal: cannot create '{}', '{}' exists", ref_name, existing);
+    }
+
+    let target = match resolve_revision(&vit_dir, revision.unwrap_or("HEAD")) {
+        Ok(hash) => hash,
+        Err(_) => {
+            return eprintln!(
+                "fatal: Failed to resolve '{}' as a valid ref.",
+                revision.unwrap_or("HEAD")
+            );
+        }
+    };
+
+    let tag_hash = match message {
+        Some(message) => {
+            let tagger = match get_identity() {
+                Ok(identity) => identity,
+                Err(err) => return eprintln!("{}", err),
+            };
+            let objects_dir = vit_dir.join("objects");
+            let (object_type, _) = read_object_by_hash(&objects_dir, &target).unwrap();
+
+            let tag_entry = TagEntry {
+                object: target,
+                object_type,
+                tag: name.to_string(),
+                tagger,
+                timestamp: chrono::Utc::now().timestamp(),
+                timezone: local_timezone(),
+                message: format!("{}\n", message.trim_end()),
+            };
+            write_loose_object(&objects_dir, "tag", &tag_entry.to_bytes()).unwrap()
+        }
+        None if annotate => {
+            return eprintln!("fatal: no tag message given, use -m <message>");
+        }
+        None => target,
+    };
+
+    let expected = previous.unwrap_or([0u8; 32]);
+    if let Err(err) = refs.update(
+        &ref_name,
+        &tag_hash,
+        Some(expected),
+        &format!("tag: {}", name),
+    ) {
+        return eprintln!("fatal: {}", err);
+    }
+
+    if let Some(previous) = previous.filter(|previous| *previous != tag_hash) {
+        println!(
+            "Updated tag '{}' (was {})",
+            name,
+            &hex::encode(previous)[..7]
+        );
+    }
+}
+
+/// Print tag names in order, only those matching `pattern` when given.
+pub fn tag_list(pattern: Option<&str>) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    for (ref_name, _) in RefStore::new(&vit_dir).list("refs/tags/") {
+        let name = ref_name.trim_start_matches("refs/tags/");
+        if pattern.is_none_or(|pattern| wildmatch(pattern.as_bytes(), name.as_bytes())) {
+            println!("{}", name);
+        }
+    }
+}
+
+pub fn tag_delete(name: &str) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    let refs = RefStore::new(&vit_dir);
+    let ref_name = format!("refs/tags/{}", name);
+    let Some(hash) = refs.read(&ref_name) else {
+        return eprintln!("error: tag '{}' not found.", name);
+    };
+
+    if let Err(err) = refs.delete(&ref_name, Some(hash), "tag: deleted") {
+        return eprintln!("error: {}", err);
+    }
+
+    println!("Deleted tag '{}' (was {})", name, &hex::encode(hash)[..7]);
+}
+
+/// Shell style matching of `*` and `?`, as used by `tag -l`.
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| wildmatch(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && wildmatch(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && wildmatch(rest, &text[1..]),
+    }
+}
@@ -0,0 +1,119 @@
+use crate::utils::{RefStore, loose_object_path, open_object, resolve_revision};
+use clap::{Arg, ArgAction, Command};
+use std::{env, path::Path};
+
+pub fn get_update_ref_command() -> Command {
+    Command::new("update-ref")
+        .about("Safely update the hash a ref points at")
+        .arg(
+            Arg::new("ref")
+                .required(true)
+                .help("Full name of the ref, e.g. refs/heads/main or HEAD"),
+        )
+        .arg(
+            Arg::new("new")
+                .required_unless_present("delete")
+                .help("Revision the ref should point at"),
+        )
+        .arg(Arg::new("old").help(
+            "Only update while the ref still points here, an empty value or zero hash requires the ref not to exist",
+        ))
+        .arg(
+            Arg::new("delete")
+                .short('d')
+                .action(ArgAction::SetTrue)
+                .help("Delete the ref, the second argument is then the expected old value"),
+        )
+        .arg(
+            Arg::new("message")
+                .short('m')
+                .value_name("REASON")
+                .help("Reason recorded in the reflog"),
+        )
+}
+
+/// Point `ref_name` at `new`, or delete it with `delete`, as long as it
+/// still holds `old` when that is given.
+pub fn update_ref(
+    ref_name: &str,
+    new: Option<&str>,
+    old: Option<&str>,
+    delete: bool,
+    message: Option<&str>,
+) {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    if !vit_dir.exists() {
+        return eprintln!("vit repository not initialized!");
+    }
+
+    let refs = RefStore::new(&vit_dir);
+    let message = message.unwrap_or("update-ref");
+
+    if delete {
+        // `update-ref -d <ref> <old>` takes the old value second
+        let expected = match new.map(|old| parse_value(&vit_dir, old)) {
+            Some(Ok(hash)) => Some(hash),
+            Some(Err(message)) => return eprintln!("fatal: {}", message),
+            None => None,
+        };
+        if let Err(err) = refs.delete(ref_name, expected, message) {
+            eprintln!("fatal: {}", err);
+        }
+        return;
+    }
+
+    let new_hash = match parse_value(&vit_dir, new.unwrap()) {
+        Ok(hash) => hash,
+        Err(message) => return eprintln!("fatal: {}", message),
+    };
+    let expected = match old.map(|old| parse_value(&vit_dir, old)) {
+        Some(Ok(hash)) => Some(hash),
+        Some(Err(message)) => return eprintln!("fatal: {}", message),
+        None => None,
+    };
+    if let Err(message) = check_target(&vit_dir, ref_name, &new_hash) {
+        return eprintln!("fatal: {}", message);
+    }
+
+    if let Err(err) = refs.update(ref_name, &new_hash, expected, message) {
+        eprintln!("fatal: {}", err);
+    }
+}
+
+/// Full hashes are taken as they are, so tag objects are not peeled,
+/// anything else is resolved as a revision. Empty stands for no ref.
+fn parse_value(vit_dir: &Path, value: &str) -> Result<[u8; 32], String> {
+    if value.is_empty() {
+        return Ok([0u8; 32]);
+    }
+    if let Some(hash) = hex::decode(value)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+    {
+        return Ok(hash);
+    }
+
+    resolve_revision(vit_dir, value).map_err(|_| format!("{}: not a valid SHA256", value))
+}
+
+/// Refs may only point at objects that exist, branches and HEAD only at
+/// commits.
+fn check_target(vit_dir: &Path, ref_name: &str, hash: &[u8; 32]) -> Result<(), String> {
+    let object = open_object(&loose_object_path(&vit_dir.join("objects"), hash)).map_err(|_| {
+        format!(
+            "trying to write ref '{}' with nonexistent object {}",
+            ref_name,
+            hex::encode(hash)
+        )
+    })?;
+
+    if (ref_name == "HEAD" || ref_name.starts_with("refs/heads/")) && object.object_type != "commit"
+    {
+        return Err(format!(
+            "trying to write non-commit object {} to branch '{}'",
+            hex::encode(hash),
+            ref_name
+        ));
+    }
+    Ok(())
+}
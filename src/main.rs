@@ -1,43 +1,280 @@
+use std::{env, process};
 use version_it::cli;
 use version_it::commands;
+use version_it::utils;
 
 fn main() {
-    let matches = cli().get_matches();
+    // Aliases may only name commands vit does not have itself
+    let command = cli();
+    let builtins: Vec<&str> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name())
+        .chain(["help"])
+        .collect();
+
+    let args = match utils::expand_aliases(env::args().collect(), &builtins) {
+        Ok(utils::AliasExpansion::Args(args)) => args,
+        Ok(utils::AliasExpansion::Shell { command, args }) => {
+            match utils::run_shell_alias(&command, &args) {
+                Ok(status) => process::exit(status.code().unwrap_or(1)),
+                Err(err) => return eprintln!("fatal: failed to run '{}': {}", command, err),
+            }
+        }
+        Err(message) => return eprintln!("fatal: {}", message),
+    };
+    let matches = command.get_matches_from(args);
+
+    // Global flags are propagated to the subcommand matches, external
+    // commands have none
+    if let Some((_, sub_matches)) = matches.subcommand() {
+        let no_pager = sub_matches
+            .try_get_one::<bool>("no-pager")
+            .ok()
+            .flatten()
+            .copied()
+            .unwrap_or(false);
+        let color = sub_matches
+            .try_get_one::<String>("color")
+            .ok()
+            .flatten()
+            .and_then(|value| utils::ColorMode::parse(value));
+
+        utils::configure_output(no_pager, color);
+    }
 
     match matches.subcommand() {
-        Some(("init", _)) => {
-            match commands::init() {
-                Err(err) => {
-                    println!("{}", err.to_string())
-                }
-                Ok(()) => {}
+        Some(("init", sub_matches)) => {
+            let initial_branch = sub_matches.get_one::<String>("initial-branch");
+
+            if let Err(err) = commands::init(initial_branch.map(String::as_str)) {
+                println!("{}", err)
             };
         }
-        Some(("status", _)) => {
-            commands::status();
+        Some(("status", sub_matches)) => {
+            let paths: Vec<String> = sub_matches
+                .get_many::<String>("paths")
+                .map(|paths| paths.cloned().collect())
+                .unwrap_or_default();
+
+            commands::status(&paths);
         }
-        Some(("log", _)) => {
-            commands::log();
+        Some(("log", sub_matches)) => {
+            commands::log(commands::LogOptions::from_matches(sub_matches));
         }
+        Some(("tag", sub_matches)) => {
+            let name = sub_matches.get_one::<String>("name").map(String::as_str);
+
+            if sub_matches.get_flag("delete") {
+                commands::tag_delete(name.unwrap());
+            } else if let Some(name) = name.filter(|_| !sub_matches.get_flag("list")) {
+                let revision = sub_matches.get_one::<String>("revision");
+                let message = sub_matches.get_one::<String>("message");
+                let annotate = sub_matches.get_flag("annotate");
+                let force = sub_matches.get_flag("force");
+
+                commands::tag(
+                    name,
+                    revision.map(String::as_str),
+                    message.map(String::as_str),
+                    annotate,
+                    force,
+                );
+            } else {
+                commands::tag_list(name);
+            }
+        }
+        Some(("reflog", sub_matches)) => match sub_matches.subcommand() {
+            Some(("show", show_matches)) => {
+                let ref_name = show_matches.get_one::<String>("ref");
+
+                commands::reflog_show(ref_name.map(String::as_str));
+            }
+            Some(("expire", expire_matches)) => {
+                let expire = expire_matches.get_one::<String>("expire").cloned();
+                let all = expire_matches.get_flag("all");
+                let refs: Vec<String> = expire_matches
+                    .get_many::<String>("refs")
+                    .map(|refs| refs.cloned().collect())
+                    .unwrap_or_default();
+
+                commands::reflog_expire(expire, all, &refs);
+            }
+            Some(("delete", delete_matches)) => {
+                let entries: Vec<String> = delete_matches
+                    .get_many::<String>("entries")
+                    .unwrap()
+                    .cloned()
+                    .collect();
+
+                commands::reflog_delete(&entries);
+            }
+            _ => {
+                let ref_name = sub_matches.get_one::<String>("ref");
+
+                commands::reflog_show(ref_name.map(String::as_str));
+            }
+        },
+        Some(("show-ref", sub_matches)) => {
+            let options = commands::ShowRefOptions {
+                patterns: sub_matches
+                    .get_many::<String>("patterns")
+                    .map(|patterns| patterns.cloned().collect())
+                    .unwrap_or_default(),
+                heads: sub_matches.get_flag("heads"),
+                tags: sub_matches.get_flag("tags"),
+                hash_only: sub_matches.get_flag("hash"),
+                dereference: sub_matches.get_flag("dereference"),
+                verify: sub_matches.get_flag("verify"),
+            };
+
+            commands::show_ref(&options);
+        }
+        Some(("update-ref", sub_matches)) => {
+            let ref_name = sub_matches.get_one::<String>("ref").unwrap();
+            let new = sub_matches.get_one::<String>("new");
+            let old = sub_matches.get_one::<String>("old");
+            let delete = sub_matches.get_flag("delete");
+            let message = sub_matches.get_one::<String>("message");
+
+            commands::update_ref(
+                ref_name,
+                new.map(String::as_str),
+                old.map(String::as_str),
+                delete,
+                message.map(String::as_str),
+            );
+        }
+        Some(("symbolic-ref", sub_matches)) => {
+            let name = sub_matches.get_one::<String>("name").unwrap();
+
+            if sub_matches.get_flag("delete") {
+                commands::symbolic_ref_delete(name);
+            } else {
+                let target = sub_matches.get_one::<String>("ref");
+                let short = sub_matches.get_flag("short");
+                let message = sub_matches.get_one::<String>("message");
+
+                commands::symbolic_ref(
+                    name,
+                    target.map(String::as_str),
+                    short,
+                    message.map(String::as_str),
+                );
+            }
+        }
+        Some(("config", sub_matches)) => match sub_matches.subcommand() {
+            Some(("get", get_matches)) => {
+                let key = get_matches.get_one::<String>("key").unwrap();
+                let scope = commands::config_scope_from_matches(get_matches);
+                let all = get_matches.get_flag("all");
+                let value_type = get_matches.get_one::<String>("type");
+
+                commands::config_get(key, scope, all, value_type.map(String::as_str));
+            }
+            Some(("set", set_matches)) => {
+                let key = set_matches.get_one::<String>("key").unwrap();
+                let value = set_matches.get_one::<String>("value").unwrap();
+                let scope = commands::config_scope_from_matches(set_matches);
+                let add = set_matches.get_flag("add");
+
+                commands::config_set(key, value, scope, add);
+            }
+            Some(("unset", unset_matches)) => {
+                let key = unset_matches.get_one::<String>("key").unwrap();
+                let scope = commands::config_scope_from_matches(unset_matches);
+
+                commands::config_unset(key, scope);
+            }
+            Some(("list", list_matches)) => {
+                let scope = commands::config_scope_from_matches(list_matches);
+                let show_scope = list_matches.get_flag("show-scope");
+
+                commands::config_list(scope, show_scope);
+            }
+            _ => unreachable!("config requires a subcommand"),
+        },
         Some(("add", sub_matches)) => {
             let paths: Vec<String> = sub_matches
                 .get_many::<String>("paths")
-                .unwrap()
-                .cloned()
-                .collect();
+                .map(|paths| paths.cloned().collect())
+                .unwrap_or_default();
 
-            commands::add(&paths);
+            if sub_matches.get_flag("patch") {
+                commands::add_patch(&paths);
+            } else {
+                commands::add(&paths);
+            }
         }
-        Some(("commit", sub_matches)) => {
-            let message = sub_matches.get_one::<String>("message").cloned();
+        Some(("reset", sub_matches)) => {
+            let paths: Vec<String> = sub_matches
+                .get_many::<String>("paths")
+                .map(|paths| paths.cloned().collect())
+                .unwrap_or_default();
+
+            commands::reset(&paths, sub_matches.get_flag("patch"));
+        }
+        Some(("restore", sub_matches)) => {
+            let paths: Vec<String> = sub_matches
+                .get_many::<String>("paths")
+                .map(|paths| paths.cloned().collect())
+                .unwrap_or_default();
 
-            commands::commit(message);
+            commands::restore(&paths, sub_matches.get_flag("patch"));
+        }
+        Some(("commit", sub_matches)) => {
+            commands::commit(commands::CommitOptions::from_matches(sub_matches));
         }
         Some(("branch", sub_matches)) => {
-            let brnach_name = sub_matches.get_one::<String>("name").cloned();
-            let is_deleting = sub_matches.get_flag("delete");
+            let names: Vec<&str> = sub_matches
+                .get_many::<String>("names")
+                .map(|names| names.map(String::as_str).collect())
+                .unwrap_or_default();
+            let filter = commands::BranchFilter {
+                merged: sub_matches.get_one::<String>("merged").cloned(),
+                no_merged: sub_matches.get_one::<String>("no-merged").cloned(),
+                contains: sub_matches.get_one::<String>("contains").cloned(),
+            };
+
+            // `-m new` renames the current branch, `-m old new` any branch
+            let (old, new) = match names.as_slice() {
+                [new] => (None, Some(*new)),
+                [old, new] => (Some(*old), Some(*new)),
+                _ => (None, None),
+            };
+
+            let delete = sub_matches.get_flag("delete");
+            let force_delete = sub_matches.get_flag("force-delete");
+            let rename = sub_matches.get_flag("move");
+            let force_rename = sub_matches.get_flag("force-move");
+            let copy = sub_matches.get_flag("copy");
+            let force_copy = sub_matches.get_flag("force-copy");
+
+            if delete || force_delete {
+                match names.as_slice() {
+                    [] => eprintln!("branch name required"),
+                    names => {
+                        for name in names {
+                            commands::branch_delete(name, force_delete);
+                        }
+                    }
+                }
+            } else if rename || force_rename {
+                match new {
+                    Some(new) => commands::branch_rename(old, new, force_rename),
+                    None => eprintln!("branch name required"),
+                }
+            } else if copy || force_copy {
+                match new {
+                    Some(new) => commands::branch_copy(old, new, force_copy),
+                    None => eprintln!("branch name required"),
+                }
+            } else if let Some(name) = names.first() {
+                commands::branch(name, names.get(1).copied());
+            } else {
+                let verbose = sub_matches.get_flag("verbose");
 
-            commands::branch(brnach_name, is_deleting);
+                commands::branch_list(verbose, &filter);
+            }
         }
         Some(("checkout", sub_matches)) => {
             let branch_name = sub_matches.get_one::<String>("name").cloned().unwrap();
@@ -47,22 +284,39 @@ fn main() {
         Some(("stash", sub_matches)) => match sub_matches.subcommand() {
             Some(("save", save_matches)) => {
                 let message = save_matches.get_one::<String>("message").cloned();
+                let include_untracked = save_matches.get_flag("include-untracked");
+                let keep_index = save_matches.get_flag("keep-index");
 
-                commands::stash(message);
+                commands::stash(message, include_untracked, keep_index);
             }
-            Some(("pop", _)) => {
-                commands::pop();
+            Some(("pop", pop_matches)) => {
+                let stash_ref = pop_matches.get_one::<String>("stash");
+                let restore_index = pop_matches.get_flag("restore-index");
+
+                commands::pop(stash_ref.map(String::as_str), restore_index);
             }
             Some(("apply", apply_matches)) => {
-                let input = apply_matches.get_one::<String>("index").cloned().unwrap();
-                match input
-                    .trim()
-                    .parse::<usize>()
-                    .map_err(|_| format!("Invalid index: '{}'", input))
-                {
-                    Ok(index) => commands::apply(index),
-                    Err(e) => eprint!("{}", e),
-                }
+                let stash_ref = apply_matches.get_one::<String>("stash");
+                let restore_index = apply_matches.get_flag("restore-index");
+
+                commands::apply(stash_ref.map(String::as_str), restore_index);
+            }
+            Some(("drop", drop_matches)) => {
+                let stash_ref = drop_matches.get_one::<String>("stash");
+
+                commands::stash_drop(stash_ref.map(String::as_str));
+            }
+            Some(("show", show_matches)) => {
+                let stash_ref = show_matches.get_one::<String>("stash");
+                let patch = show_matches.get_flag("patch");
+
+                commands::stash_show(stash_ref.map(String::as_str), patch);
+            }
+            Some(("branch", branch_matches)) => {
+                let name = branch_matches.get_one::<String>("name").unwrap();
+                let stash_ref = branch_matches.get_one::<String>("stash");
+
+                commands::stash_branch(name, stash_ref.map(String::as_str));
             }
             Some(("list", _)) => {
                 commands::list();
@@ -71,9 +325,40 @@ fn main() {
                 commands::clear();
             }
             _ => {
-                commands::stash(Option::None);
+                let include_untracked = sub_matches.get_flag("include-untracked");
+                let keep_index = sub_matches.get_flag("keep-index");
+
+                commands::stash(Option::None, include_untracked, keep_index);
             }
         },
-        _ => unreachable!("Unknown subcommand!"),
+        Some(("repack", sub_matches)) => {
+            let all = sub_matches.get_flag("all");
+
+            commands::repack(all);
+        }
+        Some(("gc", _)) => {
+            commands::gc();
+        }
+        Some(("prune", sub_matches)) => {
+            let expire = sub_matches.get_one::<String>("expire").cloned();
+            let dry_run = sub_matches.get_flag("dry-run");
+
+            commands::prune(expire, dry_run);
+        }
+        Some((name, sub_matches)) => {
+            let args: Vec<&String> = sub_matches
+                .get_many::<String>("")
+                .map(|args| args.collect())
+                .unwrap_or_default();
+
+            let Some(path) = utils::find_external_command(name) else {
+                return eprintln!("vit: '{}' is not a vit command. See 'vit --help'.", name);
+            };
+            match process::Command::new(&path).args(args).status() {
+                Ok(status) => process::exit(status.code().unwrap_or(1)),
+                Err(err) => eprintln!("fatal: failed to run '{}': {}", path.display(), err),
+            }
+        }
+        None => unreachable!("A subcommand is required"),
     }
 }
@@ -0,0 +1,129 @@
+use crate::utils::Config;
+use std::{
+    collections::HashSet,
+    env,
+    io::Result,
+    path::PathBuf,
+    process::{Command, ExitStatus},
+};
+
+/// What the command line turned into once aliases are expanded.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AliasExpansion {
+    /// Arguments for clap, the program name first
+    Args(Vec<String>),
+    /// A `!` alias, run by the shell with the remaining arguments
+    Shell { command: String, args: Vec<String> },
+}
+
+/// Replace an `alias.<name>` subcommand by its value, repeatedly as aliases
+/// may refer to other aliases. Built in commands cannot be overridden.
+///
+/// `vit --no-pager lg -5` with `alias.lg = log --oneline` becomes
+/// `vit --no-pager log --oneline -5`. Values starting with `!` are shell
+/// commands and end the expansion.
+pub fn expand_aliases(
+    args: Vec<String>,
+    builtins: &[&str],
+) -> std::result::Result<AliasExpansion, String> {
+    let config = Config::load();
+    let mut args = args;
+    let mut seen = HashSet::new();
+
+    // Global options such as `--no-pager` come before the subcommand
+    while let Some(position) = args.iter().skip(1).position(|arg| !arg.starts_with('-')) {
+        let position = position + 1;
+        let name = args[position].clone();
+        if builtins.contains(&name.as_str()) {
+            break;
+        }
+        let Some(value) = config.get(&format!("alias.{}", name)) else {
+            break;
+        };
+
+        if !seen.insert(name.clone()) {
+            return Err(format!(
+                "alias loop detected: expansion of '{}' does not terminate",
+                name
+            ));
+        }
+
+        if let Some(command) = value.strip_prefix('!') {
+            return Ok(AliasExpansion::Shell {
+                command: command.to_string(),
+                args: args.split_off(position + 1),
+            });
+        }
+
+        let words = split_command_line(value)?;
+        if words.is_empty() {
+            return Err(format!("empty alias for {}", name));
+        }
+        args.splice(position..=position, words);
+    }
+
+    Ok(AliasExpansion::Args(args))
+}
+
+/// Split an alias value into words, honouring single and double quotes
+/// and backslash escapes.
+pub fn split_command_line(line: &str) -> std::result::Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('\'')) => word.push(c),
+            ('\\', _) => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => word.push(c),
+            ('\'' | '"', None) => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (c, None) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (c, None) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err(format!("unclosed quote in alias: {}", line));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Run a `!` alias through `sh`, the arguments are available as `$@` and
+/// appended to the command like git does.
+pub fn run_shell_alias(command: &str, args: &[String]) -> Result<ExitStatus> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", command))
+        .arg(command)
+        .args(args)
+        .status()
+}
+
+/// `vit-<name>` on `PATH`, run for subcommands vit does not know.
+pub fn find_external_command(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(format!("vit-{}", name)))
+        .find(|candidate| candidate.is_file())
+}
@@ -1,14 +1,16 @@
-use crate::utils::{CommitEntry, IndexEntry, TreeEntry, decompress_file_content};
-use chrono::Local;
+use crate::utils::{
+    CommitEntry, FileStatus, IndexEntry, TreeEntry, decompress_file_content, get_config,
+    local_timezone, loose_compression, loose_object_path,
+};
 use core::str;
-use flate2::{Compression, write::ZlibEncoder};
+use flate2::write::ZlibEncoder;
 use sha2::{Digest, Sha256};
 use std::{
     collections::BTreeMap,
     env,
     ffi::OsStr,
-    fs::{self, File, OpenOptions},
-    io::{Error, ErrorKind, Read, Write},
+    fs,
+    io::{Error, ErrorKind, Write},
     path::Path,
     process::Command,
 };
@@ -16,8 +18,12 @@ use std::{
 pub fn build_tree(index_entries: &[IndexEntry]) -> [u8; 32] {
     let mut tree_map: BTreeMap<String, Vec<&IndexEntry>> = BTreeMap::new();
 
-    // Group files by their parent directory
+    // Group files by their parent directory, staged deletions are left out
     for entry in index_entries {
+        if entry.status == FileStatus::Deleted {
+            continue;
+        }
+
         let parent_dir = std::path::Path::new(&entry.path)
             .parent()
             .unwrap_or(std::path::Path::new(""))
@@ -25,10 +31,16 @@ pub fn build_tree(index_entries: &[IndexEntry]) -> [u8; 32] {
             .unwrap()
             .to_string();
 
-        tree_map
-            .entry(parent_dir)
-            .or_insert_with(Vec::new)
-            .push(entry);
+        // Directories holding only sub directories still need a tree
+        let mut ancestor = Path::new(&parent_dir).parent();
+        while let Some(dir) = ancestor {
+            tree_map
+                .entry(dir.to_str().unwrap().to_string())
+                .or_default();
+            ancestor = dir.parent();
+        }
+
+        tree_map.entry(parent_dir).or_default().push(entry);
     }
 
     // Now recursively build trees
@@ -76,15 +88,18 @@ fn build_tree_recursive(path: &str, tree_map: &BTreeMap<String, Vec<&IndexEntry>
     }
 
     // Finally, hash this tree and store it as an object
-    let tree_hash = save_tree_object(&tree_content).unwrap();
-    tree_hash
+
+    save_tree_object(&tree_content).unwrap()
 }
 
 pub fn save_tree_object(content: &[u8]) -> Result<[u8; 32], Error> {
     let current_dir = env::current_dir().unwrap();
     let vit_dir = current_dir.join(".vit");
     if !vit_dir.exists() {
-        return Err(Error::new(ErrorKind::NotFound, "vit repository not initialized!"));
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            "vit repository not initialized!",
+        ));
     }
 
     let mut full_data: Vec<u8> = Vec::new();
@@ -104,7 +119,7 @@ pub fn save_tree_object(content: &[u8]) -> Result<[u8; 32], Error> {
     let object_dir = vit_dir.join(format!("objects/{}", &tree_hash_str[..2]));
     let object_file = object_dir.join(&tree_hash_str[2..]);
 
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    let mut encoder = ZlibEncoder::new(Vec::new(), loose_compression());
     encoder.write_all(&full_data).unwrap();
     let compressed = encoder.finish().unwrap();
 
@@ -117,6 +132,12 @@ pub fn save_tree_object(content: &[u8]) -> Result<[u8; 32], Error> {
 pub fn parse_tree_entries(path: &Path) -> Result<Vec<TreeEntry>, Error> {
     let decompressed = decompress_file_content(path).unwrap();
     let data = &decompressed[decompressed.iter().position(|&b| b == 0).unwrap() + 1..];
+
+    parse_tree_data(data)
+}
+
+/// Parse the content of a tree object, without its header.
+pub fn parse_tree_data(data: &[u8]) -> Result<Vec<TreeEntry>, Error> {
     let mut tree_entries: Vec<TreeEntry> = Vec::new();
     let mut cursor = 0;
 
@@ -143,14 +164,47 @@ pub fn parse_tree_entries(path: &Path) -> Result<Vec<TreeEntry>, Error> {
     Ok(tree_entries)
 }
 
-pub fn build_commit(tree_hash: [u8; 32], parent_hash: &[u8], message: &str) -> [u8; 32] {
+/// A commit by `identity`, see [`get_identity`](crate::utils::get_identity).
+pub fn build_commit(
+    tree_hash: [u8; 32],
+    parents: &[[u8; 32]],
+    message: &str,
+    identity: &str,
+) -> [u8; 32] {
+    let timestamp = chrono::Utc::now().timestamp();
     let commit_entry = CommitEntry {
         tree: tree_hash,
-        parent: parent_hash.try_into().unwrap(),
-        author: "Vivek <vivek@example.com>".to_string(),
-        committer: "Vivek <vivek@example.com>".to_string(),
-        timestamp: chrono::Utc::now().timestamp(),
-        timezone: "".to_string(),
+        parents: parents.to_vec(),
+        author: identity.to_string(),
+        committer: identity.to_string(),
+        timestamp,
+        timezone: local_timezone(),
+        committer_timestamp: timestamp,
+        committer_timezone: local_timezone(),
+        message: message.to_string(),
+    };
+
+    save_commit_object(&commit_entry.to_bytes())
+}
+
+/// Like [`build_commit`] for `commit --amend`, the author and author date
+/// are kept from `original` and only the committer is `identity`.
+pub fn build_amended_commit(
+    tree_hash: [u8; 32],
+    parents: &[[u8; 32]],
+    message: &str,
+    original: &CommitEntry,
+    identity: &str,
+) -> [u8; 32] {
+    let commit_entry = CommitEntry {
+        tree: tree_hash,
+        parents: parents.to_vec(),
+        author: original.author.clone(),
+        committer: identity.to_string(),
+        timestamp: original.timestamp,
+        timezone: original.timezone.clone(),
+        committer_timestamp: chrono::Utc::now().timestamp(),
+        committer_timezone: local_timezone(),
         message: message.to_string(),
     };
 
@@ -173,7 +227,7 @@ fn save_commit_object(content: &[u8]) -> [u8; 32] {
     let object_dir = format!(".vit/objects/{}", &commit_hash_str[..2]);
     let object_file = format!("{}/{}", object_dir, &commit_hash_str[2..]);
 
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    let mut encoder = ZlibEncoder::new(Vec::new(), loose_compression());
     encoder.write_all(&full_data).unwrap();
     let compressed = encoder.finish().unwrap();
 
@@ -183,123 +237,154 @@ fn save_commit_object(content: &[u8]) -> [u8; 32] {
     commit_hash
 }
 
-pub fn update_head(commit_hash: [u8; 32], commit_ref: &Path) {
-    let current_dir = env::current_dir().unwrap();
-    let vit_dir = current_dir.join(".vit");
-    if !vit_dir.exists() {
-        panic!("vit repository not initialized!");
-    }
+/// Line of a commit message template below which everything is dropped,
+/// `commit -v` puts the diff under it.
+pub const SCISSORS_LINE: &str = "# ------------------------ >8 ------------------------";
+
+/// Editor used when neither `VIT_EDITOR`, `core.editor`, `VISUAL` nor
+/// `EDITOR` is set.
+const DEFAULT_EDITOR: &str = "vi";
+
+/// How a commit message is tidied up before it is recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanupMode {
+    /// Drop `#` comment lines, trailing whitespace and extra blank lines
+    Strip,
+    /// Like `Strip` but keep comment lines
+    Whitespace,
+    /// Keep the message exactly as written
+    Verbatim,
+    /// Like `Whitespace` but drop everything from the scissors line on
+    Scissors,
+}
 
-    fs::write(commit_ref, hex::encode(commit_hash)).expect("Failed to update reference!");
+impl CleanupMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "strip" => Some(CleanupMode::Strip),
+            "whitespace" => Some(CleanupMode::Whitespace),
+            "verbatim" => Some(CleanupMode::Verbatim),
+            "scissors" => Some(CleanupMode::Scissors),
+            _ => None,
+        }
+    }
 }
 
-pub fn write_log_entry(
-    old_commit: &[u8],
-    new_commit: &[u8],
-    author_name: &str,
-    author_email: &str,
-    message: &str,
-    current_branch_ref: &str,
-) {
-    let current_dir = env::current_dir().unwrap();
-    let vit_dir = current_dir.join(".vit");
-    if !vit_dir.exists() {
-        return eprintln!("vit repository not initialized!");
+/// Tidy up `message` according to `mode`. `cut_scissors` drops everything
+/// from the scissors line on whatever the mode, for messages written in
+/// the editor below a verbose diff.
+pub fn cleanup_commit_message(message: &str, mode: CleanupMode, cut_scissors: bool) -> String {
+    if mode == CleanupMode::Verbatim {
+        return message.to_string();
     }
 
-    let now = Local::now(); // Local time
-    let timestamp = now.timestamp();
-    let offset = now.offset().local_minus_utc();
-    let hours = offset / 3600;
-    let minutes = (offset % 3600) / 60;
-    let timezone = format!("{:+03}{}", hours, format!("{:02}", minutes.abs()));
-
-    let log_entry: String;
-    if current_branch_ref.contains("/stash") {
-        log_entry = format!(
-            "{} {} {} <{}> {} {} stash: {}\n",
-            hex::encode(old_commit),
-            hex::encode(new_commit),
-            author_name,
-            author_email,
-            timestamp,
-            timezone,
-            message,
-        );
-    } else {
-        log_entry = format!(
-            "{} {} {} <{}> {} {} commit: {}\n",
-            hex::encode(old_commit),
-            hex::encode(new_commit),
-            author_name,
-            author_email,
-            timestamp,
-            timezone,
-            message,
-        );
+    let cut_scissors = cut_scissors || mode == CleanupMode::Scissors;
+    let mut lines: Vec<&str> = Vec::new();
+    for line in message.lines() {
+        if cut_scissors && line == SCISSORS_LINE {
+            break;
+        }
+        if mode == CleanupMode::Strip && line.starts_with('#') {
+            continue;
+        }
+        let line = line.trim_end();
+        // Runs of blank lines become one, leading ones go away
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
     }
 
-    let log_path = vit_dir.join("logs").join(current_branch_ref);
-    std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(vit_dir.join(log_path))
-        .unwrap()
-        .write_all(log_entry.as_bytes())
-        .unwrap();
+    lines.join("\n")
 }
 
-pub fn get_commit_message_from_editor(status: &str) -> String {
-    let vit_dir = env::current_dir().unwrap().join(".vit");
+/// The editor for commit messages, from `VIT_EDITOR`, `core.editor`,
+/// `VISUAL` or `EDITOR` in that order.
+pub fn get_commit_editor() -> String {
+    env::var("VIT_EDITOR")
+        .ok()
+        .or_else(|| get_config("core.editor"))
+        .or_else(|| env::var("VISUAL").ok())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
 
-    // 1. Create/open temporary file
-    let temp_path = vit_dir.join("COMMIT_EDITMSG");
-    let temp_path = temp_path.as_path(); // similar to Git
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(temp_path)
-        .expect("Cannot open COMMIT_EDITMSG");
-
-    // 2. Write status info with '#' into file
-    writeln!(
-        file,
-        "\n# Please enter the commit message above.\n#\n# Changes to be committed:\n# {}\n",
-        status
-    )
-    .expect("Cannot write commit message template");
-
-    // 3. Open user's default editor
-    let editor = env::var("EDITOR").unwrap_or_else(|_| "vim".to_string()); // fallback to vim
-    let status = Command::new(editor)
-        .arg(temp_path)
-        .status()
-        .expect("Failed to open editor");
-
-    if !status.success() {
-        panic!("Editor failed");
+/// Open `path` in the editor from `get_commit_editor` and wait for it.
+///
+/// The editor is run by the shell so settings like `code --wait` work.
+/// Failing to start it or a non-zero exit is an error.
+pub fn run_editor(path: &Path) -> Result<(), String> {
+    let editor = get_commit_editor();
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(path)
+        .status();
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        _ => Err(format!("There was a problem with the editor '{}'.", editor)),
     }
+}
 
-    // 4. Read the file back
-    let mut contents = String::new();
-    File::open(temp_path)
-        .expect("Cannot open COMMIT_EDITMSG for reading")
-        .read_to_string(&mut contents)
-        .expect("Cannot read COMMIT_EDITMSG");
-
-    // 5. Filter out commented lines
-    let final_message: String = contents
-        .lines()
-        .filter(|line| !line.trim_start().starts_with('#'))
-        .collect::<Vec<_>>()
-        .join("\n")
-        .trim()
-        .to_string();
-
-    if final_message.is_empty() {
-        panic!("Aborting commit due to empty commit message.");
-    }
+/// Write `template` to `.vit/COMMIT_EDITMSG`, let the user edit it and
+/// return what they saved, still to be cleaned up.
+pub fn get_commit_message_from_editor(template: &str) -> Result<String, String> {
+    let vit_dir = env::current_dir().unwrap().join(".vit");
+    let message_path = vit_dir.join("COMMIT_EDITMSG"); // similar to Git
+
+    fs::write(&message_path, template)
+        .map_err(|err| format!("could not write {}: {}", message_path.display(), err))?;
+
+    run_editor(&message_path).map_err(|message| {
+        format!(
+            "{}\nPlease supply the message using either -m or -F option.",
+            message
+        )
+    })?;
+
+    fs::read_to_string(&message_path)
+        .map_err(|err| format!("could not read {}: {}", message_path.display(), err))
+}
+
+/// Every blob below a tree keyed by its full path, e.g. `src/main.rs`.
+pub fn flatten_tree(objects_dir: &Path, tree_hash: &[u8; 32]) -> BTreeMap<String, TreeEntry> {
+    let mut files = BTreeMap::new();
+    flatten_tree_recursive(objects_dir, tree_hash, "", &mut files);
+    files
+}
 
-    final_message
+fn flatten_tree_recursive(
+    objects_dir: &Path,
+    tree_hash: &[u8; 32],
+    prefix: &str,
+    files: &mut BTreeMap<String, TreeEntry>,
+) {
+    let tree_path = loose_object_path(objects_dir, tree_hash);
+
+    for entry in parse_tree_entries(&tree_path).unwrap() {
+        let path = if prefix.is_empty() {
+            entry.name.clone()
+        } else {
+            format!("{}/{}", prefix, entry.name)
+        };
+
+        if entry.mode == "040000" {
+            flatten_tree_recursive(objects_dir, &entry.sha256, &path, files);
+        } else {
+            files.insert(
+                path.clone(),
+                TreeEntry {
+                    mode: entry.mode,
+                    name: path,
+                    sha256: entry.sha256,
+                },
+            );
+        }
+    }
 }
@@ -1,14 +1,16 @@
-use std::{io, path::Path};
 use crate::utils::decompress_file_content;
+use std::{io, path::Path};
 
 #[derive(Debug)]
 pub struct CommitEntry {
     pub tree: [u8; 32],    // SHA-256 of the tree object
-    pub parent: [u8; 32],  // Optional for the first commit
+    pub parents: Vec<[u8; 32]>, // Empty for the first commit, several for merges
     pub author: String,    // "Name <email>"
     pub committer: String, // "Name <email>"
-    pub timestamp: i64,    // UNIX timestamp
+    pub timestamp: i64,    // UNIX timestamp of the author date
     pub timezone: String,  // e.g., "+0530"
+    pub committer_timestamp: i64, // Later than the author date once amended
+    pub committer_timezone: String,
     pub message: String,   // Commit message
 }
 
@@ -20,9 +22,11 @@ impl CommitEntry {
         content.extend_from_slice(hex::encode(self.tree).as_bytes());
         content.push(b'\n');
 
-        content.extend_from_slice(b"parent ");
-        content.extend_from_slice(hex::encode(self.parent).as_bytes());
-        content.push(b'\n');
+        for parent in &self.parents {
+            content.extend_from_slice(b"parent ");
+            content.extend_from_slice(hex::encode(parent).as_bytes());
+            content.push(b'\n');
+        }
 
         content.extend_from_slice(b"author ");
         content.extend_from_slice(self.author.as_bytes());
@@ -35,9 +39,9 @@ impl CommitEntry {
         content.extend_from_slice(b"committer ");
         content.extend_from_slice(self.committer.as_bytes());
         content.push(b' ');
-        content.extend_from_slice(self.timestamp.to_string().as_bytes());
+        content.extend_from_slice(self.committer_timestamp.to_string().as_bytes());
         content.push(b' ');
-        content.extend_from_slice(self.timezone.as_bytes());
+        content.extend_from_slice(self.committer_timezone.as_bytes());
         content.push(b'\n');
 
         content.push(b'\n');
@@ -50,43 +54,49 @@ impl CommitEntry {
 pub fn read_commit_file(path: &Path) -> io::Result<CommitEntry> {
     let decompressed = decompress_file_content(path).unwrap();
     let content = &decompressed[decompressed.iter().position(|&b| b == 0).unwrap() + 1..];
+
+    parse_commit_data(content)
+}
+
+/// Parse the content of a commit object, without its header.
+pub fn parse_commit_data(content: &[u8]) -> io::Result<CommitEntry> {
     let content_str = String::from_utf8_lossy(content).into_owned();
     let mut lines = content_str.lines();
 
     let mut tree = [0u8; 32];
-    let mut parent = [0u8; 32];
+    let mut parents: Vec<[u8; 32]> = Vec::new();
     let mut author = String::new();
     let mut committer = String::new();
     let mut timestamp = 0;
     let mut timezone = String::new();
+    let mut committer_timestamp = 0;
+    let mut committer_timezone = String::new();
 
-    while let Some(line) = lines.next() {
-        if line.starts_with("tree ") {
-            let hash = &line[5..];
+    for line in lines.by_ref() {
+        if let Some(hash) = line.strip_prefix("tree ") {
             let hash_bytes = hex::decode(hash).expect("Invalid tree hash");
             tree.copy_from_slice(&hash_bytes);
-        } else if line.starts_with("parent ") {
-            let hash = &line[7..];
+        } else if let Some(hash) = line.strip_prefix("parent ") {
             let hash_bytes = hex::decode(hash).expect("Invalid parent hash");
+            let mut parent = [0u8; 32];
             parent.copy_from_slice(&hash_bytes);
-        } else if line.starts_with("author ") {
-            let author_info = &line[7..];
-            let info: Vec<&str> = author_info.split(" ").collect();
-
-            let author_name = info.get(0).cloned().unwrap_or("");
-            let author_email = info.get(1).cloned().unwrap_or("");
-            author = author_name.to_string() + " " + &author_email.to_string();
-
-            timestamp = info.get(2).cloned().unwrap_or("0").parse().unwrap();
-            timezone = info.get(3).cloned().unwrap_or("").to_string();
-        } else if line.starts_with("committer ") {
-            let committer_info = &line[10..];
-            let info: Vec<&str> = committer_info.split(" ").collect();
-
-            let committer_name = info.get(0).cloned().unwrap_or("");
-            let committer_email = info.get(1).cloned().unwrap_or("");
-            committer = committer_name.to_string() + " " + &committer_email.to_string();
 
+            // Older root commits recorded an all-zero parent
+            if parent != [0u8; 32] {
+                parents.push(parent);
+            }
+        } else if let Some(author_info) = line.strip_prefix("author ") {
+            let (ident, time, zone) = split_signature(author_info);
+
+            author = ident.to_string();
+            timestamp = time.parse().unwrap_or(0);
+            timezone = zone.to_string();
+        } else if let Some(committer_info) = line.strip_prefix("committer ") {
+            let (ident, time, zone) = split_signature(committer_info);
+
+            committer = ident.to_string();
+            committer_timestamp = time.parse().unwrap_or(0);
+            committer_timezone = zone.to_string();
         } else if line.is_empty() {
             break; // message follows after this
         }
@@ -96,11 +106,26 @@ pub fn read_commit_file(path: &Path) -> io::Result<CommitEntry> {
 
     Ok(CommitEntry {
         tree,
-        parent,
+        parents,
         author,
         committer,
         timestamp,
         timezone,
+        committer_timestamp,
+        committer_timezone,
         message,
     })
 }
+
+/// Split `Name <email> timestamp timezone` into its three parts, names may
+/// contain spaces and older commits have an empty timezone.
+fn split_signature(signature: &str) -> (&str, &str, &str) {
+    let ident_end = signature.rfind('>').map_or(0, |end| end + 1);
+    let (ident, rest) = signature.split_at(ident_end);
+
+    let mut fields = rest.split_whitespace();
+    let timestamp = fields.next().unwrap_or("0");
+    let timezone = fields.next().unwrap_or("");
+
+    (ident.trim(), timestamp, timezone)
+}
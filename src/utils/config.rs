@@ -0,0 +1,483 @@
+use flate2::Compression;
+use std::{
+    env, fs,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Included files including further files are followed this deep at most.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Where a config value comes from, later scopes override earlier ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigScope {
+    /// `/etc/vitconfig`, or `VIT_CONFIG_SYSTEM`
+    System,
+    /// `~/.vitconfig`, or `VIT_CONFIG_GLOBAL`
+    Global,
+    /// `.vit/config` of the repository
+    Local,
+}
+
+impl ConfigScope {
+    pub const ALL: [ConfigScope; 3] =
+        [ConfigScope::System, ConfigScope::Global, ConfigScope::Local];
+
+    /// The file of this scope, `None` when there is no home directory or
+    /// `VIT_CONFIG_NOSYSTEM` turns the system file off.
+    pub fn path(self) -> Option<PathBuf> {
+        match self {
+            ConfigScope::System => {
+                if env::var_os("VIT_CONFIG_NOSYSTEM").is_some() {
+                    return None;
+                }
+                Some(
+                    env::var_os("VIT_CONFIG_SYSTEM")
+                        .map(PathBuf::from)
+                        .unwrap_or_else(|| PathBuf::from("/etc/vitconfig")),
+                )
+            }
+            ConfigScope::Global => env::var_os("VIT_CONFIG_GLOBAL")
+                .map(PathBuf::from)
+                .or_else(|| home_dir().map(|home| home.join(".vitconfig"))),
+            ConfigScope::Local => env::current_dir()
+                .ok()
+                .map(|dir| dir.join(".vit").join("config")),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigScope::System => "system",
+            ConfigScope::Global => "global",
+            ConfigScope::Local => "local",
+        }
+    }
+}
+
+/// One `key = value` line, with the key normalised to `section.name` or
+/// `section.subsection.name`, section and name lower case.
+#[derive(Debug, Clone)]
+pub struct ConfigEntry {
+    pub key: String,
+    pub value: String,
+    pub scope: ConfigScope,
+    pub origin: PathBuf, // File the line is in, an included file for include.path
+}
+
+/// Every value of the system, global and repository config files.
+///
+/// The files use git's format: `[section]` or `[section "subsection"]`
+/// headers followed by `key = value` lines, `#` and `;` start comments and
+/// a key without a value is a boolean set to true. `include.path` pulls in
+/// another file at that point, relative paths are relative to the file
+/// holding the include.
+#[derive(Debug, Default)]
+pub struct Config {
+    entries: Vec<ConfigEntry>,
+}
+
+impl Config {
+    /// Read all scopes, a missing file counts as empty.
+    pub fn load() -> Self {
+        let mut config = Config::default();
+        for scope in ConfigScope::ALL {
+            config.read_scope(scope);
+        }
+        config
+    }
+
+    /// Read a single scope, as for `vit config --global list`.
+    pub fn load_scope(scope: ConfigScope) -> Self {
+        let mut config = Config::default();
+        config.read_scope(scope);
+        config
+    }
+
+    /// Read a single file.
+    pub fn load_file(path: &Path, scope: ConfigScope) -> Self {
+        let mut config = Config::default();
+        config.read_file(path, scope, 0);
+        config
+    }
+
+    pub fn entries(&self) -> &[ConfigEntry] {
+        &self.entries
+    }
+
+    /// The value of `key`, the last occurrence wins.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).pop()
+    }
+
+    /// Every value of a multi-valued key, in the order they were read.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let Some(key) = normalize_key(key) else {
+            return Vec::new();
+        };
+
+        self.entries
+            .iter()
+            .filter(|entry| entry.key == key)
+            .map(|entry| entry.value.as_str())
+            .collect()
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key).and_then(parse_config_bool)
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        self.get(key).and_then(parse_config_int)
+    }
+
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        self.get(key).map(expand_config_path)
+    }
+
+    fn read_scope(&mut self, scope: ConfigScope) {
+        if let Some(path) = scope.path() {
+            self.read_file(&path, scope, 0);
+        }
+    }
+
+    fn read_file(&mut self, path: &Path, scope: ConfigScope, depth: usize) {
+        let Ok(content) = fs::read_to_string(path) else {
+            return;
+        };
+
+        let mut section = String::new();
+        for line in content.lines() {
+            let Some((next_section, entry)) = parse_line(line, &section) else {
+                continue;
+            };
+            section = next_section;
+            let Some((name, value)) = entry else {
+                continue;
+            };
+
+            let key = format!("{}.{}", section, name.to_lowercase());
+            if key == "include.path" && depth < MAX_INCLUDE_DEPTH {
+                let included = expand_config_path(&value);
+                let included = match path.parent() {
+                    Some(dir) if included.is_relative() => dir.join(included),
+                    _ => included,
+                };
+                self.read_file(&included, scope, depth + 1);
+            }
+
+            self.entries.push(ConfigEntry {
+                key,
+                value,
+                scope,
+                origin: path.to_path_buf(),
+            });
+        }
+    }
+}
+
+/// Look up a `section.key` or `section.subsection.key` value across the
+/// system, global and repository config.
+pub fn get_config(key: &str) -> Option<String> {
+    Config::load().get(key).map(str::to_string)
+}
+
+/// Look up a value in a single config file, the last occurrence wins.
+pub fn read_config_value(path: &Path, key: &str) -> Option<String> {
+    Config::load_file(path, ConfigScope::Local)
+        .get(key)
+        .map(str::to_string)
+}
+
+/// Interpret a config value as a boolean the way git does.
+pub fn parse_config_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Integers may carry a `k`, `m` or `g` suffix, e.g. `512k`.
+pub fn parse_config_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (digits, factor) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1 << 10),
+        'm' => (&value[..value.len() - 1], 1 << 20),
+        'g' => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+
+    digits.parse::<i64>().ok()?.checked_mul(factor)
+}
+
+/// Expand a leading `~/` to the home directory.
+pub fn expand_config_path(value: &str) -> PathBuf {
+    match (value.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(value),
+    }
+}
+
+/// Set `key` in a config file, replacing its last value or, with `add`,
+/// adding another one. The section is created when missing and the rest of
+/// the file, comments included, is left as it is.
+pub fn set_config_value(path: &Path, key: &str, value: &str, add: bool) -> Result<()> {
+    let (section, name) = split_key(key).ok_or_else(|| invalid_key(key))?;
+    let normalized = normalize_key(key).ok_or_else(|| invalid_key(key))?;
+
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let new_line = format!("\t{} = {}", name, quote_value(value));
+
+    let mut current = String::new();
+    let mut last_match = None;
+    let mut section_end = None;
+    for (i, line) in lines.iter().enumerate() {
+        let Some((next_section, entry)) = parse_line(line, &current) else {
+            continue;
+        };
+        current = next_section;
+        if current == normalized_section(&normalized) {
+            section_end = Some(i);
+            if let Some((entry_name, _)) = entry
+                && entry_name.eq_ignore_ascii_case(&name)
+            {
+                last_match = Some(i);
+            }
+        }
+    }
+
+    match (last_match, section_end) {
+        (Some(i), _) if !add => lines[i] = new_line,
+        (_, Some(i)) => lines.insert(i + 1, new_line),
+        (_, None) => {
+            lines.push(section_header(&section));
+            lines.push(new_line);
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, lines.join("\n") + "\n")
+}
+
+/// Remove every value of `key` from a config file and return how many
+/// there were.
+pub fn unset_config_value(path: &Path, key: &str) -> Result<usize> {
+    let normalized = normalize_key(key).ok_or_else(|| invalid_key(key))?;
+    let Ok(content) = fs::read_to_string(path) else {
+        return Ok(0);
+    };
+
+    let mut current = String::new();
+    let mut kept = Vec::new();
+    let mut removed = 0;
+    for line in content.lines() {
+        if let Some((next_section, entry)) = parse_line(line, &current) {
+            current = next_section;
+            if let Some((entry_name, _)) = entry
+                && format!("{}.{}", current, entry_name.to_lowercase()) == normalized
+            {
+                removed += 1;
+                continue;
+            }
+        }
+        kept.push(line);
+    }
+
+    if removed > 0 {
+        fs::write(path, kept.join("\n") + "\n")?;
+    }
+    Ok(removed)
+}
+
+/// `Name <email>` from `user.name` and `user.email`, recorded in commits
+/// and tags. Fails with the advice to set them when either is missing.
+pub fn get_identity() -> Result<String> {
+    let config = Config::load();
+    match (config.get("user.name"), config.get("user.email")) {
+        (Some(name), Some(email)) => Ok(format!("{} <{}>", name, email)),
+        _ => Err(Error::new(
+            ErrorKind::NotFound,
+            "Author identity unknown
+
+*** Please tell me who you are.
+
+Run
+
+  vit config set --global user.email \"you@example.com\"
+  vit config set --global user.name \"Your Name\"
+
+to set your account's default identity.
+Omit --global to set the identity only in this repository.",
+        )),
+    }
+}
+
+/// Like [`get_identity`] for reflogs, which are local and never refuse an
+/// update. Missing values are made up from `$USER` and the host name.
+pub fn reflog_identity() -> String {
+    let config = Config::load();
+    let user = env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    let name = config.get("user.name").unwrap_or(&user).to_string();
+    let email = config.get("user.email").map_or_else(
+        || {
+            let host = fs::read_to_string("/etc/hostname").unwrap_or_default();
+            let host = host.trim();
+            format!(
+                "{}@{}",
+                user,
+                if host.is_empty() { "localhost" } else { host }
+            )
+        },
+        str::to_string,
+    );
+    format!("{} <{}>", name, email)
+}
+
+/// zlib level for loose objects, `core.looseCompression` or
+/// `core.compression`, read once per process.
+pub fn loose_compression() -> Compression {
+    static LEVEL: OnceLock<Compression> = OnceLock::new();
+    *LEVEL.get_or_init(|| compression_level("core.looseCompression"))
+}
+
+/// zlib level for packs, `pack.compression` or `core.compression`.
+pub fn pack_compression() -> Compression {
+    static LEVEL: OnceLock<Compression> = OnceLock::new();
+    *LEVEL.get_or_init(|| compression_level("pack.compression"))
+}
+
+/// -1 is zlib's default, 0 to 9 trade speed for size.
+fn compression_level(key: &str) -> Compression {
+    let config = Config::load();
+    match config
+        .get_int(key)
+        .or_else(|| config.get_int("core.compression"))
+    {
+        Some(level @ 0..=9) => Compression::new(level as u32),
+        _ => Compression::default(),
+    }
+}
+
+/// Parse one line given the section it is in. Returns the section the
+/// line leaves us in and the `(name, value)` it holds, `None` for blank
+/// and comment lines.
+fn parse_line(line: &str, section: &str) -> Option<(String, Option<(String, String)>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+        return None;
+    }
+
+    if let Some(header) = line.strip_prefix('[').and_then(|h| h.split(']').next()) {
+        return Some((parse_section_header(header), None));
+    }
+
+    let (name, value) = match line.split_once('=') {
+        Some((name, value)) => (name.trim(), parse_value(value)),
+        // A key without a value is a boolean set to true
+        None => (line, "true".to_string()),
+    };
+
+    Some((section.to_string(), Some((name.to_string(), value))))
+}
+
+/// `core.pager` -> ("core", "pager"), `remote.origin.url` -> ("remote.origin", "url"),
+/// with the section name lowercased.
+fn split_key(key: &str) -> Option<(String, String)> {
+    let (section, name) = key.rsplit_once('.')?;
+    if section.is_empty() || name.is_empty() {
+        return None;
+    }
+
+    let section = match section.split_once('.') {
+        Some((section, subsection)) => format!("{}.{}", section.to_lowercase(), subsection),
+        None => section.to_lowercase(),
+    };
+
+    Some((section, name.to_string()))
+}
+
+/// `Core.Pager` -> `core.pager`, subsections keep their case.
+fn normalize_key(key: &str) -> Option<String> {
+    let (section, name) = split_key(key)?;
+    Some(format!("{}.{}", section, name.to_lowercase()))
+}
+
+fn normalized_section(normalized_key: &str) -> &str {
+    normalized_key
+        .rsplit_once('.')
+        .map_or("", |(section, _)| section)
+}
+
+fn parse_section_header(header: &str) -> String {
+    match header.split_once(char::is_whitespace) {
+        Some((section, subsection)) => format!(
+            "{}.{}",
+            section.to_lowercase(),
+            subsection.trim().trim_matches('"')
+        ),
+        None => header.trim().to_lowercase(),
+    }
+}
+
+fn section_header(section: &str) -> String {
+    match section.split_once('.') {
+        Some((section, subsection)) => format!("[{} \"{}\"]", section, subsection),
+        None => format!("[{}]", section),
+    }
+}
+
+/// Strip comments and quotes from the right hand side of `key = value`.
+fn parse_value(raw: &str) -> String {
+    let mut value = String::new();
+    let mut in_quotes = false;
+    let mut chars = raw.trim().chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' | ';' if !in_quotes => break,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(other) => value.push(other),
+                None => {}
+            },
+            _ => value.push(c),
+        }
+    }
+
+    value.trim_end().to_string()
+}
+
+/// The reverse of `parse_value`, quoting only when needed.
+fn quote_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+
+    if value.trim() != value || value.contains(['#', ';']) {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+fn invalid_key(key: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("key does not contain a section: {}", key),
+    )
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
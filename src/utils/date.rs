@@ -0,0 +1,186 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+/// How dates are rendered by `log --date`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateMode {
+    /// `Mon Jan 31 12:30:00 2024 +0530`, in the offset of the commit
+    #[default]
+    Default,
+    /// `3 hours ago`
+    Relative,
+    /// `2024-01-31 12:30:00 +0530`
+    Iso,
+    /// `2024-01-31`
+    Short,
+    /// Seconds since the epoch
+    Unix,
+    /// Like the default, converted to the local timezone
+    Local,
+}
+
+impl DateMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "default" => Some(DateMode::Default),
+            "relative" => Some(DateMode::Relative),
+            "iso" | "iso8601" => Some(DateMode::Iso),
+            "short" => Some(DateMode::Short),
+            "unix" => Some(DateMode::Unix),
+            "local" => Some(DateMode::Local),
+            _ => None,
+        }
+    }
+}
+
+/// Render a commit timestamp in the offset it was recorded with.
+///
+/// The timezone is the `+hhmm` string stored in the commit, commits that
+/// were written without one are shown in UTC.
+pub fn format_date(timestamp: i64, timezone: &str, mode: DateMode) -> String {
+    let offset = parse_timezone(timezone).unwrap_or(FixedOffset::east_opt(0).unwrap());
+    let Some(date_time) = DateTime::from_timestamp(timestamp, 0) else {
+        return timestamp.to_string();
+    };
+    let date_time = date_time.with_timezone(&offset);
+
+    match mode {
+        DateMode::Default => date_time.format("%a %b %-d %H:%M:%S %Y %z").to_string(),
+        DateMode::Relative => format_relative(Local::now().timestamp() - timestamp),
+        DateMode::Iso => date_time.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        DateMode::Short => date_time.format("%Y-%m-%d").to_string(),
+        DateMode::Unix => timestamp.to_string(),
+        DateMode::Local => date_time
+            .with_timezone(&Local)
+            .format("%a %b %-d %H:%M:%S %Y")
+            .to_string(),
+    }
+}
+
+/// The local UTC offset as stored in commits and reflogs, e.g. `+0530`.
+pub fn local_timezone() -> String {
+    let offset = Local::now().offset().local_minus_utc();
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+
+    format!("{}{:02}{:02}", sign, offset / 3600, (offset % 3600) / 60)
+}
+
+fn parse_timezone(timezone: &str) -> Option<FixedOffset> {
+    let (sign, digits) = match timezone.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if digits.len() != 4 {
+        return None;
+    }
+
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Approximate age the way git shows it, e.g. `5 minutes ago`.
+fn format_relative(seconds: i64) -> String {
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+
+    let plural = |amount: i64, unit: &str| {
+        format!(
+            "{} {}{} ago",
+            amount,
+            unit,
+            if amount == 1 { "" } else { "s" }
+        )
+    };
+
+    let minutes = (seconds + 30) / 60;
+    let hours = (minutes + 30) / 60;
+    let days = (hours + 12) / 24;
+
+    if seconds < 90 {
+        plural(seconds, "second")
+    } else if minutes < 90 {
+        plural(minutes, "minute")
+    } else if hours < 36 {
+        plural(hours, "hour")
+    } else if days < 14 {
+        plural(days, "day")
+    } else if days < 70 {
+        plural((days + 3) / 7, "week")
+    } else if days < 365 {
+        plural((days + 15) / 30, "month")
+    } else {
+        plural((days + 183) / 365, "year")
+    }
+}
+
+/// Parse a point in time given on the command line into a Unix timestamp.
+///
+/// Accepts `now`, raw Unix timestamps, relative forms such as `2.weeks.ago`
+/// or `3 days ago`, and absolute dates like `2024-01-31` or
+/// `2024-01-31 12:30:00` in local time.
+pub fn parse_date(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let now = Local::now().timestamp();
+
+    if value.eq_ignore_ascii_case("now") {
+        return Some(now);
+    }
+    if let Some(timestamp) = value.strip_prefix('@') {
+        return timestamp.parse().ok();
+    }
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return Some(timestamp);
+    }
+    if let Some(seconds) = parse_relative(value) {
+        return Some(now - seconds);
+    }
+    if let Ok(date_time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(date_time.timestamp());
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(value, format) {
+            return Local
+                .from_local_datetime(&date_time)
+                .earliest()
+                .map(|d| d.timestamp());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()
+            .map(|d| d.timestamp());
+    }
+
+    None
+}
+
+/// Parse `<n>.<unit>.ago` / `<n> <unit> ago` into a number of seconds.
+fn parse_relative(value: &str) -> Option<i64> {
+    let words: Vec<&str> = value
+        .split(['.', ' '])
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let (amount, unit) = match words.as_slice() {
+        [amount, unit, "ago"] => (amount.parse::<i64>().ok()?, *unit),
+        [unit, "ago"] => (1, *unit),
+        _ => return None,
+    };
+
+    let unit_seconds = match unit.trim_end_matches('s') {
+        "second" | "sec" => 1,
+        "minute" | "min" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        "year" => 365 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    Some(amount * unit_seconds)
+}
@@ -0,0 +1,302 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+};
+
+/// Size of the blocks of the base object that are indexed when looking for matches.
+const BLOCK_SIZE: usize = 16;
+/// Longest literal a single insert instruction can carry.
+const MAX_INSERT: usize = 0x7f;
+/// Longest range a single copy instruction can carry (3 size bytes).
+const MAX_COPY: usize = 0xff_ffff;
+
+/// Encode `target` as a list of copy/insert instructions against `base`.
+///
+/// The format follows git's delta encoding: the base and result sizes as
+/// varints, then instructions where a set high bit means "copy a range of
+/// the base" and a clear high bit means "insert the next n literal bytes".
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_varint(&mut delta, base.len() as u64);
+    write_varint(&mut delta, target.len() as u64);
+
+    // Remember the first occurrence of every aligned block of the base
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    for offset in (0..base.len().saturating_sub(BLOCK_SIZE - 1)).step_by(BLOCK_SIZE) {
+        blocks
+            .entry(&base[offset..offset + BLOCK_SIZE])
+            .or_insert(offset);
+    }
+
+    let mut literal_start = 0;
+    let mut cursor = 0;
+
+    while cursor + BLOCK_SIZE <= target.len() {
+        let Some(&base_offset) = blocks.get(&target[cursor..cursor + BLOCK_SIZE]) else {
+            cursor += 1;
+            continue;
+        };
+
+        // Grow the match forwards, then backwards into the pending literal
+        let mut length = BLOCK_SIZE;
+        while base_offset + length < base.len()
+            && cursor + length < target.len()
+            && base[base_offset + length] == target[cursor + length]
+        {
+            length += 1;
+        }
+
+        let mut back = 0;
+        while cursor - back > literal_start
+            && base_offset > back
+            && base[base_offset - back - 1] == target[cursor - back - 1]
+        {
+            back += 1;
+        }
+
+        write_insert(&mut delta, &target[literal_start..cursor - back]);
+        write_copy(&mut delta, base_offset - back, length + back);
+
+        cursor += length;
+        literal_start = cursor;
+    }
+
+    write_insert(&mut delta, &target[literal_start..]);
+    delta
+}
+
+/// Rebuild the target object from `base` and a delta produced by `create_delta`.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut cursor = 0;
+    let base_size = read_varint(delta, &mut cursor)? as usize;
+    let result_size = read_varint(delta, &mut cursor)? as usize;
+
+    if base_size != base.len() {
+        return Err(corrupt("Delta base size mismatch"));
+    }
+
+    let mut result = Vec::with_capacity(result_size);
+    while cursor < delta.len() {
+        let opcode = delta[cursor];
+        cursor += 1;
+
+        if opcode & 0x80 != 0 {
+            let mut offset = 0usize;
+            for i in 0..4 {
+                if opcode & (1 << i) != 0 {
+                    offset |= (*delta
+                        .get(cursor)
+                        .ok_or_else(|| corrupt("Truncated delta"))?
+                        as usize)
+                        << (8 * i);
+                    cursor += 1;
+                }
+            }
+
+            let mut size = 0usize;
+            for i in 0..3 {
+                if opcode & (0x10 << i) != 0 {
+                    size |= (*delta
+                        .get(cursor)
+                        .ok_or_else(|| corrupt("Truncated delta"))?
+                        as usize)
+                        << (8 * i);
+                    cursor += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+
+            let range = base
+                .get(offset..offset + size)
+                .ok_or_else(|| corrupt("Delta copy out of range"))?;
+            result.extend_from_slice(range);
+        } else if opcode != 0 {
+            let size = opcode as usize;
+            let literal = delta
+                .get(cursor..cursor + size)
+                .ok_or_else(|| corrupt("Truncated delta"))?;
+            result.extend_from_slice(literal);
+            cursor += size;
+        } else {
+            return Err(corrupt("Invalid delta opcode"));
+        }
+    }
+
+    if result.len() != result_size {
+        return Err(corrupt("Delta result size mismatch"));
+    }
+
+    Ok(result)
+}
+
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub fn read_varint(data: &[u8], cursor: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+
+    loop {
+        let byte = *data
+            .get(*cursor)
+            .ok_or_else(|| corrupt("Truncated varint"))?;
+        *cursor += 1;
+
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+
+        shift += 7;
+        if shift > 63 {
+            return Err(corrupt("Varint too long"));
+        }
+    }
+}
+
+fn write_insert(delta: &mut Vec<u8>, mut literal: &[u8]) {
+    while !literal.is_empty() {
+        let size = literal.len().min(MAX_INSERT);
+        delta.push(size as u8);
+        delta.extend_from_slice(&literal[..size]);
+        literal = &literal[size..];
+    }
+}
+
+fn write_copy(delta: &mut Vec<u8>, mut offset: usize, mut length: usize) {
+    while length > 0 {
+        let size = length.min(MAX_COPY);
+        let mut opcode = 0x80u8;
+        let mut operands = Vec::with_capacity(7);
+
+        for i in 0..4 {
+            let byte = ((offset >> (8 * i)) & 0xff) as u8;
+            if byte != 0 {
+                opcode |= 1 << i;
+                operands.push(byte);
+            }
+        }
+        for i in 0..3 {
+            let byte = ((size >> (8 * i)) & 0xff) as u8;
+            if byte != 0 {
+                opcode |= 0x10 << i;
+                operands.push(byte);
+            }
+        }
+
+        delta.push(opcode);
+        delta.extend_from_slice(&operands);
+
+        offset += size;
+        length -= size;
+    }
+}
+
+fn corrupt(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let delta = create_delta(base, target);
+        assert_eq!(apply_delta(base, &delta).unwrap(), target);
+        delta
+    }
+
+    fn sample(len: usize, seed: u8) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+            .collect()
+    }
+
+    #[test]
+    fn empty_inputs_round_trip() {
+        assert_eq!(round_trip(b"", b""), [0, 0]);
+        round_trip(b"", b"only new content");
+        round_trip(b"only old content", b"");
+    }
+
+    #[test]
+    fn identical_inputs_become_copies() {
+        let base = sample(4096, 1);
+        let delta = round_trip(&base, &base);
+        assert!(delta.len() < 16, "{} bytes", delta.len());
+
+        // Longer than a single copy instruction can carry
+        let large = sample(MAX_COPY + 4096, 2);
+        let delta = round_trip(&large, &large);
+        assert!(delta.len() < 32, "{} bytes", delta.len());
+    }
+
+    #[test]
+    fn appended_content_is_inserted_after_a_copy() {
+        let base = sample(4096, 3);
+        let mut target = base.clone();
+        target.extend_from_slice(b"appended at the end\n");
+
+        let delta = round_trip(&base, &target);
+        assert!(delta.len() < 48, "{} bytes", delta.len());
+    }
+
+    #[test]
+    fn rewritten_content_is_inserted_whole() {
+        let base = vec![b'a'; 1000];
+        let target = vec![b'b'; 1000];
+
+        let delta = round_trip(&base, &target);
+        // Every byte is a literal, plus one opcode per MAX_INSERT bytes
+        assert_eq!(delta.len(), 2 + 2 + 1000 + 1000usize.div_ceil(MAX_INSERT));
+    }
+
+    #[test]
+    fn deltas_against_the_wrong_base_are_rejected() {
+        let delta = create_delta(b"the original base", b"the original target");
+        assert!(apply_delta(b"another base", &delta).is_err());
+        assert!(apply_delta(b"the original base", &delta[..delta.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn varints_round_trip_at_byte_boundaries() {
+        let cases: [(u64, usize); 8] = [
+            (0, 1),
+            (0x7f, 1),
+            (0x80, 2),
+            (0x3fff, 2),
+            (0x4000, 3),
+            (u32::MAX as u64, 5),
+            (1 << 63, 10),
+            (u64::MAX, 10),
+        ];
+
+        for (value, len) in cases {
+            let mut encoded = Vec::new();
+            write_varint(&mut encoded, value);
+            assert_eq!(encoded.len(), len, "{:#x}", value);
+
+            let mut cursor = 0;
+            assert_eq!(read_varint(&encoded, &mut cursor).unwrap(), value);
+            assert_eq!(cursor, len);
+        }
+    }
+
+    #[test]
+    fn truncated_and_overlong_varints_are_rejected() {
+        assert!(read_varint(&[0x80], &mut 0).is_err());
+        assert!(read_varint(&[], &mut 0).is_err());
+        assert!(read_varint(&[0xff; 11], &mut 0).is_err());
+    }
+}
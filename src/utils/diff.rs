@@ -0,0 +1,482 @@
+use crate::utils::{flatten_tree, read_object_by_hash};
+use colored::Colorize;
+use std::{fmt::Write, path::Path};
+
+/// Lines of context shown around every change in a patch.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// A single step of a line diff, indices point into the old and new lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal { old: usize, new: usize },
+    Delete { old: usize },
+    Insert { new: usize },
+}
+
+/// A group of edits shown together under one `@@` header.
+#[derive(Debug)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_count: usize,
+    pub new_start: usize,
+    pub new_count: usize,
+    pub edits: Vec<Edit>,
+}
+
+/// Number of added and removed lines between two versions of a file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiffStat {
+    pub insertions: usize,
+    pub deletions: usize,
+    pub binary: bool,
+}
+
+/// A file that differs between two trees, `None` where it is missing.
+#[derive(Debug)]
+pub struct TreeChange {
+    pub path: String,
+    pub old: Option<[u8; 32]>,
+    pub new: Option<[u8; 32]>,
+}
+
+/// Files that differ between two trees, sorted by path. A missing tree is
+/// treated as empty.
+pub fn diff_trees(
+    objects_dir: &Path,
+    old_tree: Option<&[u8; 32]>,
+    new_tree: Option<&[u8; 32]>,
+) -> Vec<TreeChange> {
+    let old_files = old_tree
+        .map(|tree| flatten_tree(objects_dir, tree))
+        .unwrap_or_default();
+    let new_files = new_tree
+        .map(|tree| flatten_tree(objects_dir, tree))
+        .unwrap_or_default();
+
+    let mut paths: Vec<&String> = old_files.keys().chain(new_files.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let old = old_files.get(path).map(|entry| entry.sha256);
+            let new = new_files.get(path).map(|entry| entry.sha256);
+            (old != new).then(|| TreeChange {
+                path: path.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+/// Largest edit distance searched before falling back to replacing the
+/// whole changed region, keeps the memory of the search bounded.
+const MAX_EDIT_DISTANCE: usize = 4096;
+
+/// Shortest edit script turning `old` into `new` (Myers' algorithm).
+pub fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    // Common prefix and suffix never need searching
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut edits: Vec<Edit> = (0..prefix)
+        .map(|i| Edit::Equal { old: i, new: i })
+        .collect();
+
+    let middle = shortest_edit(old_mid, new_mid).unwrap_or_else(|| {
+        let deletes = (0..old_mid.len()).map(|old| Edit::Delete { old });
+        deletes
+            .chain((0..new_mid.len()).map(|new| Edit::Insert { new }))
+            .collect()
+    });
+    edits.extend(middle.into_iter().map(|edit| match edit {
+        Edit::Equal { old, new } => Edit::Equal {
+            old: old + prefix,
+            new: new + prefix,
+        },
+        Edit::Delete { old } => Edit::Delete { old: old + prefix },
+        Edit::Insert { new } => Edit::Insert { new: new + prefix },
+    }));
+
+    let (old_tail, new_tail) = (old.len() - suffix, new.len() - suffix);
+    edits.extend((0..suffix).map(|i| Edit::Equal {
+        old: old_tail + i,
+        new: new_tail + i,
+    }));
+
+    edits
+}
+
+fn shortest_edit<T: PartialEq>(old: &[T], new: &[T]) -> Option<Vec<Edit>> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+
+    // Furthest x reached on every diagonal k, plus the diagonals -d-1..=d+1
+    // of it before every round so the path can be walked back
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        if d as usize > MAX_EDIT_DISTANCE {
+            return None;
+        }
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut edits = Vec::with_capacity(max);
+    let (mut x, mut y) = (n, m);
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal {
+                old: x as usize,
+                new: y as usize,
+            });
+        }
+
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert {
+                    new: prev_y as usize,
+                });
+            } else {
+                edits.push(Edit::Delete {
+                    old: prev_x as usize,
+                });
+            }
+        }
+
+        x = prev_x;
+        y = prev_y;
+    }
+
+    edits.reverse();
+    Some(edits)
+}
+
+/// Split an edit script into hunks with `context` unchanged lines around
+/// every change, merging changes whose context would overlap.
+pub fn build_hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal { .. }))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut hunks = Vec::new();
+    let mut i = 0;
+
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(context);
+        let mut last = changes[i];
+        while i + 1 < changes.len() && changes[i + 1] - last <= 2 * context + 1 {
+            i += 1;
+            last = changes[i];
+        }
+        let end = (last + context + 1).min(edits.len());
+        i += 1;
+
+        // Position of the hunk in both files, counted in lines before it
+        let old_start = edits[..start]
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert { .. }))
+            .count();
+        let new_start = edits[..start]
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Delete { .. }))
+            .count();
+
+        let hunk_edits = edits[start..end].to_vec();
+        let old_count = hunk_edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert { .. }))
+            .count();
+        let new_count = hunk_edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Delete { .. }))
+            .count();
+
+        hunks.push(Hunk {
+            old_start,
+            old_count,
+            new_start,
+            new_count,
+            edits: hunk_edits,
+        });
+    }
+
+    hunks
+}
+
+/// Unified diff of one file, `None` stands for a missing side (added or
+/// deleted file). Returns an empty string when both sides are equal.
+pub fn unified_diff(path: &str, old: Option<&[u8]>, new: Option<&[u8]>, context: usize) -> String {
+    if old == new {
+        return String::new();
+    }
+
+    let mut out = format!("diff --vit a/{} b/{}\n", path, path);
+    match (old, new) {
+        (None, _) => out.push_str("new file\n"),
+        (_, None) => out.push_str("deleted file\n"),
+        _ => {}
+    }
+
+    if is_binary(old) || is_binary(new) {
+        out.push_str(&format!("Binary files a/{} and b/{} differ\n", path, path));
+        return out;
+    }
+
+    let old_text = String::from_utf8_lossy(old.unwrap_or_default());
+    let new_text = String::from_utf8_lossy(new.unwrap_or_default());
+    let old_lines: Vec<&str> = old_text.lines().collect();
+    let new_lines: Vec<&str> = new_text.lines().collect();
+
+    let old_name = old.map_or("/dev/null".to_string(), |_| format!("a/{}", path));
+    let new_name = new.map_or("/dev/null".to_string(), |_| format!("b/{}", path));
+    out.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+
+    let edits = diff_lines(&old_lines, &new_lines);
+    for hunk in build_hunks(&edits, context) {
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(hunk.old_start, hunk.old_count),
+            hunk_range(hunk.new_start, hunk.new_count)
+        ));
+
+        for edit in &hunk.edits {
+            match *edit {
+                Edit::Equal { old, .. } => out.push_str(&format!(" {}\n", old_lines[old])),
+                Edit::Delete { old } => out.push_str(&format!("-{}\n", old_lines[old])),
+                Edit::Insert { new } => out.push_str(&format!("+{}\n", new_lines[new])),
+            }
+        }
+    }
+
+    out
+}
+
+/// Count added and removed lines between two versions of a file.
+pub fn diff_stat(old: Option<&[u8]>, new: Option<&[u8]>) -> DiffStat {
+    if is_binary(old) || is_binary(new) {
+        return DiffStat {
+            binary: true,
+            ..DiffStat::default()
+        };
+    }
+
+    let old_text = String::from_utf8_lossy(old.unwrap_or_default());
+    let new_text = String::from_utf8_lossy(new.unwrap_or_default());
+    let old_lines: Vec<&str> = old_text.lines().collect();
+    let new_lines: Vec<&str> = new_text.lines().collect();
+
+    let mut stat = DiffStat::default();
+    for edit in diff_lines(&old_lines, &new_lines) {
+        match edit {
+            Edit::Delete { .. } => stat.deletions += 1,
+            Edit::Insert { .. } => stat.insertions += 1,
+            Edit::Equal { .. } => {}
+        }
+    }
+
+    stat
+}
+
+/// The `path | N ++--` lines and the summary line of a diffstat.
+pub fn format_diff_stat(stats: &[(String, DiffStat)]) -> String {
+    let name_width = stats.iter().map(|(path, _)| path.len()).max().unwrap_or(0);
+    let max_changes = stats
+        .iter()
+        .map(|(_, stat)| stat.insertions + stat.deletions)
+        .max()
+        .unwrap_or(0);
+    let count_width = max_changes.to_string().len();
+
+    // Scale the bars down so the widest one fits in 50 columns
+    let scale = |count: usize| {
+        if max_changes <= 50 {
+            count
+        } else {
+            (count * 50).div_ceil(max_changes)
+        }
+    };
+
+    let mut out = String::new();
+    let (mut insertions, mut deletions) = (0, 0);
+    for (path, stat) in stats {
+        if stat.binary {
+            writeln!(out, " {:<name_width$} | Bin", path).unwrap();
+            continue;
+        }
+
+        insertions += stat.insertions;
+        deletions += stat.deletions;
+        writeln!(
+            out,
+            " {:<name_width$} | {:>count_width$} {}{}",
+            path,
+            stat.insertions + stat.deletions,
+            "+".repeat(scale(stat.insertions)).green(),
+            "-".repeat(scale(stat.deletions)).red()
+        )
+        .unwrap();
+    }
+
+    write!(
+        out,
+        " {} file{} changed",
+        stats.len(),
+        if stats.len() == 1 { "" } else { "s" }
+    )
+    .unwrap();
+    if insertions > 0 || deletions == 0 {
+        write!(
+            out,
+            ", {} insertion{}(+)",
+            insertions,
+            if insertions == 1 { "" } else { "s" }
+        )
+        .unwrap();
+    }
+    if deletions > 0 {
+        write!(
+            out,
+            ", {} deletion{}(-)",
+            deletions,
+            if deletions == 1 { "" } else { "s" }
+        )
+        .unwrap();
+    }
+    out.push('\n');
+    out
+}
+
+/// Diffstat of a list of tree changes, empty when nothing changed.
+pub fn format_tree_stat(objects_dir: &Path, changes: &[TreeChange]) -> String {
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let stats: Vec<(String, DiffStat)> = changes
+        .iter()
+        .map(|change| {
+            let old = read_blob(objects_dir, change.old);
+            let new = read_blob(objects_dir, change.new);
+            (
+                change.path.clone(),
+                diff_stat(old.as_deref(), new.as_deref()),
+            )
+        })
+        .collect();
+
+    format_diff_stat(&stats)
+}
+
+/// Coloured unified diff of every file in a list of tree changes.
+pub fn format_tree_patch(objects_dir: &Path, changes: &[TreeChange]) -> String {
+    let mut out = String::new();
+
+    for change in changes {
+        let old = read_blob(objects_dir, change.old);
+        let new = read_blob(objects_dir, change.new);
+        out.push_str(&color_patch(&unified_diff(
+            &change.path,
+            old.as_deref(),
+            new.as_deref(),
+            DEFAULT_CONTEXT,
+        )));
+    }
+
+    out
+}
+
+fn read_blob(objects_dir: &Path, hash: Option<[u8; 32]>) -> Option<Vec<u8>> {
+    hash.map(|hash| read_object_by_hash(objects_dir, &hash).unwrap().1)
+}
+
+/// Colour a patch produced by `unified_diff` line by line.
+pub fn color_patch(patch: &str) -> String {
+    let mut out = String::new();
+
+    for line in patch.lines() {
+        if line.starts_with("diff --vit")
+            || line.starts_with("--- ")
+            || line.starts_with("+++ ")
+            || line == "new file"
+            || line == "deleted file"
+        {
+            writeln!(out, "{}", line.bold()).unwrap();
+        } else if line.starts_with("@@") {
+            writeln!(out, "{}", line.cyan()).unwrap();
+        } else if line.starts_with('+') {
+            writeln!(out, "{}", line.green()).unwrap();
+        } else if line.starts_with('-') {
+            writeln!(out, "{}", line.red()).unwrap();
+        } else {
+            writeln!(out, "{}", line).unwrap();
+        }
+    }
+
+    out
+}
+
+/// Line range of a hunk header, `start` counts the lines before the hunk.
+pub fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+/// Files with a NUL byte in the first 8000 bytes are treated as binary.
+fn is_binary(content: Option<&[u8]>) -> bool {
+    content.is_some_and(|data| data[..data.len().min(8000)].contains(&0))
+}
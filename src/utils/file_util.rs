@@ -1,17 +1,30 @@
-use crate::utils::IndexEntry;
-use flate2::{Compression, bufread::ZlibDecoder, write::ZlibEncoder};
+use crate::utils::{
+    IndexEntry, hash_reader, loose_compression, read_packed_object, split_loose_object_path,
+    store_blob_from_reader,
+};
+use flate2::{bufread::ZlibDecoder, write::ZlibEncoder};
 use hex;
 use ignore::WalkBuilder;
+use ignore::WalkState;
 use sha2::{Digest, Sha256};
 use std::{
     env, fs,
     fs::File,
-    io::{Cursor, Read, Result, Write},
+    io::{self, BufReader, Cursor, Read, Result, Write},
     path::{Path, PathBuf},
+    process,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 use super::parse_tree_entries;
 
+/// Files larger than this are hashed and compressed in a streaming fashion.
+pub const STREAMING_THRESHOLD: u64 = 8 * 1024 * 1024;
+const CHUNK_SIZE: usize = 64 * 1024;
+
 pub fn write_index(entries: &[IndexEntry], path: &str) -> Result<()> {
     let mut file = File::create(path)?;
 
@@ -56,35 +69,43 @@ pub fn expand_paths(paths: &[String]) -> Vec<String> {
 }
 
 pub fn list_files_recursively(root: &Path) -> Vec<String> {
-    let mut files = Vec::new();
+    let files: Mutex<Vec<String>> = Mutex::new(Vec::new());
 
-    for result in WalkBuilder::new(root)
+    WalkBuilder::new(root)
         .standard_filters(true)
         .add_custom_ignore_filename(".vitignore")
-        .build()
-    {
-        let dir_entry = match result {
-            Ok(entry) => entry,
-            Err(_) => continue,
-        };
+        .build_parallel()
+        .run(|| {
+            let files = &files;
+            Box::new(move |result| {
+                let dir_entry = match result {
+                    Ok(entry) => entry,
+                    Err(_) => return WalkState::Continue,
+                };
+
+                if dir_entry
+                    .file_type()
+                    .map(|ft| ft.is_file())
+                    .unwrap_or(false)
+                    && let Some(path_str) = dir_entry.path().to_str()
+                {
+                    let path = path_str.trim_start_matches("./").to_string();
+                    files.lock().unwrap().push(path);
+                }
 
-        if dir_entry
-            .file_type()
-            .map(|ft| ft.is_file())
-            .unwrap_or(false)
-        {
-            if let Some(path_str) = dir_entry.path().to_str() {
-                files.push(path_str.trim_start_matches("./").to_string());
-            }
-        }
-    }
+                WalkState::Continue
+            })
+        });
 
+    // The parallel walker yields entries in no particular order
+    let mut files = files.into_inner().unwrap();
+    files.sort();
     files
 }
 
-pub fn hash_file(path: &str) -> ([u8; 32], Vec<u8>) {
+pub fn hash_file(path: &str) -> Result<([u8; 32], Vec<u8>)> {
     let mut hasher = Sha256::new();
-    let content = std::fs::read(path).expect("Unable to read file");
+    let content = std::fs::read(path)?;
 
     // Create header
     let header = format!("blob {}\0", content.len());
@@ -95,7 +116,35 @@ pub fn hash_file(path: &str) -> ([u8; 32], Vec<u8>) {
     let mut sha256 = [0u8; 32];
     sha256.copy_from_slice(&sha256_result[..]);
 
-    (sha256, content) // Returning hash AND raw content
+    Ok((sha256, content)) // Returning hash AND raw content
+}
+
+/// Hash a file in fixed size chunks so large files never have to be held in memory.
+pub fn hash_file_streaming(path: &str) -> Result<[u8; 32]> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+
+    hash_reader(
+        "blob",
+        file_size,
+        &mut BufReader::with_capacity(CHUNK_SIZE, file),
+    )
+}
+
+/// Hash a work tree file and make sure its blob exists in the object store.
+///
+/// Small files are read once and hashed and compressed from memory, files
+/// above `STREAMING_THRESHOLD` are hashed and compressed straight from disk.
+pub fn hash_and_store_file(vit_dir: &Path, path: &str) -> Result<[u8; 32]> {
+    let file_size = fs::metadata(path)?.len();
+
+    if file_size <= STREAMING_THRESHOLD {
+        let (sha256, content) = hash_file(path)?;
+        return store_object(vit_dir, sha256, content);
+    }
+
+    let sha256 = hash_file_streaming(path)?;
+    store_file_object(vit_dir, sha256, path)
 }
 
 pub fn store_object(git_dir: &Path, sha256: [u8; 32], content: Vec<u8>) -> Result<[u8; 32]> {
@@ -115,17 +164,95 @@ pub fn store_object(git_dir: &Path, sha256: [u8; 32], content: Vec<u8>) -> Resul
     full_data.extend_from_slice(&content);
 
     // Compress
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    let mut encoder = ZlibEncoder::new(Vec::new(), loose_compression());
     encoder.write_all(&full_data)?;
     let compressed = encoder.finish()?;
 
     fs::create_dir_all(&object_dir)?;
-    fs::write(object_path, compressed)?;
+    let temp_path = temp_object_path(&object_dir);
+    fs::write(&temp_path, compressed)?;
+    fs::rename(temp_path, object_path)?;
+
+    Ok(sha256)
+}
+
+/// Compress a file from disk into the object store as a blob without
+/// buffering its content.
+pub fn store_file_object(git_dir: &Path, sha256: [u8; 32], path: &str) -> Result<[u8; 32]> {
+    let sha256_hex = hex::encode(sha256);
+    let object_path = git_dir
+        .join("objects")
+        .join(&sha256_hex[..2])
+        .join(&sha256_hex[2..]);
+
+    if object_path.exists() {
+        return Ok(sha256);
+    }
+
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let stored = store_blob_from_reader(
+        git_dir,
+        file_size,
+        &mut BufReader::with_capacity(CHUNK_SIZE, file),
+    )?;
+    if stored != sha256 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} changed while it was being added", path),
+        ));
+    }
 
     Ok(sha256)
 }
 
+/// Objects are written under a unique temporary name and renamed into place,
+/// so concurrent writers of the same object never observe a partial file.
+pub(crate) fn temp_object_path(object_dir: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+    object_dir.join(format!("tmp_obj_{}_{}", process::id(), id))
+}
+
+/// Temporary objects left behind by writers that died before renaming them,
+/// in `objects_dir` itself or one of its fan-out directories.
+pub fn list_temp_objects(objects_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    if !objects_dir.exists() {
+        return Ok(paths);
+    }
+
+    for entry in fs::read_dir(objects_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("tmp_obj_") {
+            paths.push(entry.path());
+        } else if name.len() == 2 && entry.file_type()?.is_dir() {
+            for file in fs::read_dir(entry.path())? {
+                let file = file?;
+                if file.file_name().to_string_lossy().starts_with("tmp_obj_") {
+                    paths.push(file.path());
+                }
+            }
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
 pub fn decompress_file_content(file_path: &Path) -> Result<Vec<u8>> {
+    // Objects that were moved into a pack are served from there
+    if !file_path.exists()
+        && let Some((objects_dir, hash)) = split_loose_object_path(file_path)
+        && let Some((object_type, content)) = read_packed_object(&objects_dir, &hash)?
+    {
+        let mut decompressed = format!("{} {}\0", object_type, content.len()).into_bytes();
+        decompressed.extend_from_slice(&content);
+        return Ok(decompressed);
+    }
+
     let compressed_data = std::fs::read(file_path)?;
 
     let mut decoder = ZlibDecoder::new(Cursor::new(compressed_data));
@@ -147,10 +274,10 @@ pub fn clear_current_tree(root: &Path) {
 
         if dir_entry.file_type().unwrap().is_dir() {
             if dir_entry.path().to_str() != root.to_str() {
-                fs::remove_dir_all(&dir_entry.path()).unwrap();
+                fs::remove_dir_all(dir_entry.path()).unwrap();
             }
         } else {
-            fs::remove_file(&dir_entry.path()).unwrap();
+            fs::remove_file(dir_entry.path()).unwrap();
         }
     }
 }
@@ -175,7 +302,7 @@ pub struct FileChange {
     pub path: String,
     pub file_type: FileType,
     pub action: Action,
-    pub sha256: [u8; 32]
+    pub sha256: [u8; 32],
 }
 
 pub fn calculate_diff(
@@ -206,10 +333,10 @@ pub fn calculate_diff(
             .trim_start_matches("./")
             .to_string();
 
-        if relative_name == "" {
+        if relative_name.is_empty() {
             continue;
         }
-        let hash_str = hex::encode(&tt_entry.sha256);
+        let hash_str = hex::encode(tt_entry.sha256);
         let file_type = if &tt_entry.mode == "040000" {
             FileType::Tree
         } else {
@@ -226,7 +353,7 @@ pub fn calculate_diff(
                             path: relative_name,
                             file_type,
                             action: Action::Restore,
-                            sha256: tt_entry.sha256
+                            sha256: tt_entry.sha256,
                         });
                     } else {
                         calculate_diff(
@@ -244,7 +371,7 @@ pub fn calculate_diff(
                     path: relative_name,
                     file_type,
                     action: Action::Create,
-                    sha256: tt_entry.sha256
+                    sha256: tt_entry.sha256,
                 });
             }
         };
@@ -258,7 +385,7 @@ pub fn calculate_diff(
             .trim_start_matches("./")
             .to_string();
 
-        if relative_name == "" {
+        if relative_name.is_empty() {
             continue;
         }
         let file_type = if &ct_entry.mode == "040000" {
@@ -267,15 +394,16 @@ pub fn calculate_diff(
             FileType::Blob
         };
 
-        if let None = &target_tree
+        if target_tree
             .iter()
             .find(|entry| entry.name == ct_entry.name)
+            .is_none()
         {
             list_of_changes.push(FileChange {
                 path: relative_name,
                 file_type,
                 action: Action::Delete,
-                sha256: ct_entry.sha256
+                sha256: ct_entry.sha256,
             });
         }
     }
@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+/// The graph columns drawn next to one commit's output.
+#[derive(Debug)]
+pub struct GraphRows {
+    /// The row with the `*` marking the commit
+    pub commit: String,
+    /// Rows moving the lanes from this commit to the next one
+    pub transition: Vec<String>,
+    /// Row used next to any further output of the commit
+    pub padding: String,
+}
+
+/// Lays out lanes for a stream of commits given children first.
+///
+/// Every lane waits for one commit. A commit takes the lane that waits for
+/// it, or a new one on the right. Its first parent continues in the same
+/// lane, further parents branch off to the right, and lanes converge again
+/// when they wait for the same commit. Parents that are not going to be
+/// shown never get a lane.
+pub struct GraphRenderer {
+    lanes: Vec<[u8; 32]>,
+    visible: HashSet<[u8; 32]>,
+}
+
+impl GraphRenderer {
+    pub fn new(visible: HashSet<[u8; 32]>) -> Self {
+        GraphRenderer {
+            lanes: Vec::new(),
+            visible,
+        }
+    }
+
+    pub fn next_commit(&mut self, hash: &[u8; 32], parents: &[[u8; 32]]) -> GraphRows {
+        let column = match self.lanes.iter().position(|lane| lane == hash) {
+            Some(column) => column,
+            None => {
+                self.lanes.push(*hash);
+                self.lanes.len() - 1
+            }
+        };
+
+        let commit_row: String = (0..self.lanes.len())
+            .map(|i| if i == column { "*" } else { "|" })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        // Work out where every current lane goes on the next row
+        let parents: Vec<[u8; 32]> = parents
+            .iter()
+            .filter(|parent| self.visible.contains(*parent))
+            .copied()
+            .collect();
+
+        let mut next: Vec<[u8; 32]> = Vec::new();
+        let mut edges: Vec<(usize, usize)> = Vec::new();
+        let mut pending: Vec<(usize, [u8; 32])> = Vec::new();
+
+        for (i, lane) in self.lanes.iter().enumerate() {
+            if i == column {
+                for parent in &parents {
+                    if let Some(target) = next.iter().position(|lane| lane == parent) {
+                        edges.push((column, target));
+                    } else if self.lanes[i + 1..].contains(parent) {
+                        pending.push((column, *parent));
+                    } else {
+                        edges.push((column, next.len()));
+                        next.push(*parent);
+                    }
+                }
+            } else if lane == hash {
+                // Another child was waiting for this commit as well
+                if let Some(first_parent) = parents.first() {
+                    pending.push((i, *first_parent));
+                }
+            } else if let Some(target) = next.iter().position(|other| other == lane) {
+                edges.push((i, target));
+            } else {
+                edges.push((i, next.len()));
+                next.push(*lane);
+            }
+        }
+
+        for (from, target) in pending {
+            let to = next.iter().position(|lane| *lane == target).unwrap();
+            edges.push((from, to));
+        }
+
+        let transition = draw_transition(&mut edges);
+        self.lanes = next;
+
+        GraphRows {
+            commit: commit_row,
+            transition,
+            padding: vec!["|"; self.lanes.len()].join(" "),
+        }
+    }
+}
+
+/// Rows of `|`, `/` and `\` moving every edge one column per row until it
+/// reaches its target. Nothing is drawn when no lane moves.
+fn draw_transition(edges: &mut [(usize, usize)]) -> Vec<String> {
+    let mut rows = Vec::new();
+
+    while edges.iter().any(|(from, to)| from != to) {
+        let width = edges
+            .iter()
+            .map(|(from, to)| 2 * from.max(to) + 2)
+            .max()
+            .unwrap_or(0);
+        let mut row = vec![' '; width];
+
+        for (position, target) in edges.iter_mut() {
+            if position < target {
+                row[2 * *position + 1] = '\\';
+                *position += 1;
+            } else if position > target {
+                row[2 * *position - 1] = '/';
+                *position -= 1;
+            } else if row[2 * *position] == ' ' {
+                row[2 * *position] = '|';
+            }
+        }
+
+        rows.push(row.into_iter().collect::<String>().trim_end().to_string());
+    }
+
+    rows
+}
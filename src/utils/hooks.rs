@@ -0,0 +1,59 @@
+use crate::utils::Config;
+use std::{
+    fs,
+    io::Result,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Where hooks live, `core.hooksPath` or `.vit/hooks`. A relative
+/// `core.hooksPath` is relative to the top of the work tree, so a hooks
+/// directory checked into the repository can be shared.
+pub fn hooks_dir(vit_dir: &Path) -> PathBuf {
+    let work_tree = vit_dir.parent().unwrap_or(vit_dir);
+
+    match Config::load().get_path("core.hooksPath") {
+        Some(path) if path.is_relative() => work_tree.join(path),
+        Some(path) => path,
+        None => vit_dir.join("hooks"),
+    }
+}
+
+/// Run the hook called `name` with `args`. Returns whether the operation
+/// may go on, which it may when the hook does not exist or exits with 0.
+///
+/// Hooks run from the top of the work tree with `VIT_DIR` set, a hook
+/// file that is not executable is skipped with a hint like git does.
+pub fn run_hook(vit_dir: &Path, name: &str, args: &[&str]) -> Result<bool> {
+    let hook = hooks_dir(vit_dir).join(name);
+    if !hook.is_file() {
+        return Ok(true);
+    }
+    if !is_executable(&hook) {
+        eprintln!(
+            "hint: The '{}' hook was ignored because it's not set as executable.",
+            hook.display()
+        );
+        return Ok(true);
+    }
+
+    let status = Command::new(&hook)
+        .args(args)
+        .current_dir(vit_dir.parent().unwrap_or(vit_dir))
+        .env("VIT_DIR", vit_dir)
+        .stdin(Stdio::null())
+        .status()?;
+
+    Ok(status.success())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok()
+}
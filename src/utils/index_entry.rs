@@ -1,7 +1,8 @@
+use crate::utils::hash_file_streaming;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use sha2::{Digest, Sha256};
 use std::{
-    fs, io,
+    fs::{self, Metadata},
+    io,
     io::{Read, Seek, SeekFrom, Write},
     path::Path,
     time::{SystemTime, UNIX_EPOCH},
@@ -16,7 +17,7 @@ pub enum FileStatus {
     Deleted = 3,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IndexEntry {
     pub ctime_secs: u32,
     pub ctime_nsecs: u32,
@@ -38,18 +39,14 @@ impl IndexEntry {
         );
 
         let metadata = fs::metadata(file_path).expect("Unable to get metadata");
-        let content = fs::read(file_path).expect("Unable to read file");
-        let mut hasher = Sha256::new();
+        let sha256 = hash_file_streaming(file_path).expect("Unable to read file");
 
-        // Create header
-        let header = format!("blob {}\0", content.len());
-        hasher.update(header.as_bytes());
-        hasher.update(&content);
-
-        let sha256_result = hasher.finalize();
-        let mut sha256 = [0u8; 32];
-        sha256.copy_from_slice(&sha256_result[..]);
+        Self::from_metadata(file_path, &metadata, sha256)
+    }
 
+    /// Build an entry from already known metadata and blob hash, without
+    /// touching the file contents again.
+    pub fn from_metadata(file_path: &str, metadata: &Metadata, sha256: [u8; 32]) -> Self {
         let mtime = metadata
             .modified()
             .unwrap_or(SystemTime::now())
@@ -68,7 +65,7 @@ impl IndexEntry {
 
         let flags = (path.len() as u16) & 0xFFF; // 12 bits for path length in git
 
-        return IndexEntry {
+        IndexEntry {
             ctime_secs: ctime.as_secs() as u32,
             ctime_nsecs: ctime.subsec_nanos(),
             mtime_secs: mtime.as_secs() as u32,
@@ -79,7 +76,17 @@ impl IndexEntry {
             status: FileStatus::New,
             flags,
             path,
-        };
+        }
+    }
+
+    /// Take over timestamps and size from fresh metadata of the work tree file.
+    pub fn refresh_stat(&mut self, metadata: &Metadata) {
+        let refreshed = Self::from_metadata(&self.path, metadata, self.sha256);
+        self.ctime_secs = refreshed.ctime_secs;
+        self.ctime_nsecs = refreshed.ctime_nsecs;
+        self.mtime_secs = refreshed.mtime_secs;
+        self.mtime_nsecs = refreshed.mtime_nsecs;
+        self.file_size = refreshed.file_size;
     }
 
     pub fn write<W: Write>(&self, file: &mut W) -> io::Result<()> {
@@ -102,7 +109,7 @@ impl IndexEntry {
         let padding = (8 - (total_size % 8)) % 8;
         file.write_all(&vec![0u8; padding])?;
 
-        return Ok(());
+        Ok(())
     }
 
     pub fn read<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Self>> {
@@ -0,0 +1,171 @@
+use crate::utils::{Edit, diff_lines};
+
+/// Outcome of merging two versions of a file against their common base.
+#[derive(Debug)]
+pub struct MergeResult {
+    pub content: Vec<u8>,
+    pub conflicts: usize,
+}
+
+/// A run of base lines `[start, end)` replaced by other lines on one side.
+struct Chunk<'a> {
+    start: usize,
+    end: usize,
+    lines: Vec<&'a str>,
+    ours: bool,
+}
+
+/// Three-way merge of `ours` and `theirs` against `base`, line by line.
+///
+/// Changes made on only one side are taken as they are, identical changes
+/// are taken once. Overlapping or touching changes that differ become a
+/// conflict wrapped in `<<<<<<<`, `=======` and `>>>>>>>` markers followed
+/// by the given labels. Binary content is never merged, it is reported as a
+/// single conflict with `ours` kept.
+pub fn merge_three_way(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    ours_label: &str,
+    theirs_label: &str,
+) -> MergeResult {
+    if [base, ours, theirs]
+        .iter()
+        .any(|data| data[..data.len().min(8000)].contains(&0))
+    {
+        return MergeResult {
+            content: ours.to_vec(),
+            conflicts: 1,
+        };
+    }
+
+    let base_text = String::from_utf8_lossy(base);
+    let ours_text = String::from_utf8_lossy(ours);
+    let theirs_text = String::from_utf8_lossy(theirs);
+
+    // Keep the line endings so the result is byte for byte what was merged
+    let base_lines: Vec<&str> = base_text.split_inclusive('\n').collect();
+    let ours_lines: Vec<&str> = ours_text.split_inclusive('\n').collect();
+    let theirs_lines: Vec<&str> = theirs_text.split_inclusive('\n').collect();
+
+    let mut chunks = changed_chunks(&base_lines, &ours_lines, true);
+    chunks.extend(changed_chunks(&base_lines, &theirs_lines, false));
+    chunks.sort_by_key(|chunk| (chunk.start, chunk.end));
+
+    let mut out = String::new();
+    let mut conflicts = 0;
+    let mut position = 0;
+    let mut i = 0;
+
+    while i < chunks.len() {
+        // Gather every chunk overlapping or touching the current group
+        let group_start = chunks[i].start;
+        let mut group_end = chunks[i].end;
+        let mut j = i + 1;
+        while j < chunks.len() && chunks[j].start <= group_end {
+            group_end = group_end.max(chunks[j].end);
+            j += 1;
+        }
+        let group = &chunks[i..j];
+        i = j;
+
+        out.extend(base_lines[position..group_start].iter().copied());
+        position = group_end;
+
+        let ours_version = apply_chunks(&base_lines, group_start, group_end, group, true);
+        let theirs_version = apply_chunks(&base_lines, group_start, group_end, group, false);
+
+        if group.iter().all(|chunk| chunk.ours) || ours_version == theirs_version {
+            out.extend(ours_version);
+        } else if group.iter().all(|chunk| !chunk.ours) {
+            out.extend(theirs_version);
+        } else {
+            conflicts += 1;
+            out.push_str(&format!("<<<<<<< {}\n", ours_label));
+            push_lines(&mut out, &ours_version);
+            out.push_str("=======\n");
+            push_lines(&mut out, &theirs_version);
+            out.push_str(&format!(">>>>>>> {}\n", theirs_label));
+        }
+    }
+
+    out.extend(base_lines[position..].iter().copied());
+
+    MergeResult {
+        content: out.into_bytes(),
+        conflicts,
+    }
+}
+
+/// Group the edits between `base` and `other` into replaced base ranges.
+fn changed_chunks<'a>(base: &[&str], other: &[&'a str], ours: bool) -> Vec<Chunk<'a>> {
+    let mut chunks: Vec<Chunk<'a>> = Vec::new();
+    let mut base_position = 0;
+    let mut current: Option<Chunk<'a>> = None;
+
+    for edit in diff_lines(base, other) {
+        match edit {
+            Edit::Equal { old, .. } => {
+                chunks.extend(current.take());
+                base_position = old + 1;
+            }
+            Edit::Delete { old } => {
+                let chunk = current.get_or_insert(Chunk {
+                    start: old,
+                    end: old,
+                    lines: Vec::new(),
+                    ours,
+                });
+                chunk.end = old + 1;
+                base_position = old + 1;
+            }
+            Edit::Insert { new } => {
+                current
+                    .get_or_insert(Chunk {
+                        start: base_position,
+                        end: base_position,
+                        lines: Vec::new(),
+                        ours,
+                    })
+                    .lines
+                    .push(other[new]);
+            }
+        }
+    }
+
+    chunks.extend(current);
+    chunks
+}
+
+/// One side's version of the base lines `[start, end)`.
+fn apply_chunks<'a>(
+    base: &[&'a str],
+    start: usize,
+    end: usize,
+    group: &[Chunk<'a>],
+    ours: bool,
+) -> Vec<&'a str> {
+    let mut lines = Vec::new();
+    let mut position = start;
+
+    for chunk in group.iter().filter(|chunk| chunk.ours == ours) {
+        lines.extend_from_slice(&base[position..chunk.start]);
+        lines.extend_from_slice(&chunk.lines);
+        position = chunk.end;
+    }
+
+    lines.extend_from_slice(&base[position..end]);
+    lines
+}
+
+/// Append lines inside a conflict, making sure the marker after them starts
+/// on a line of its own.
+fn push_lines(out: &mut String, lines: &[&str]) {
+    for line in lines {
+        out.push_str(line);
+    }
+
+    if lines.last().is_some_and(|line| !line.ends_with('\n')) {
+        out.push('\n');
+    }
+}
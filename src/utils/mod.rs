@@ -1,12 +1,53 @@
-pub mod index_entry;
-pub mod tree_entry;
+pub mod alias;
+pub mod commit;
 pub mod commit_entry;
+pub mod date;
+pub mod delta;
+pub mod diff;
 pub mod file_util;
-pub mod commit;
+pub mod index_entry;
+pub mod merge;
+pub mod object;
+pub mod pack;
+pub mod patch;
+pub mod pathspec;
+pub mod parallel;
+pub mod reachability;
+pub mod reflog;
+pub mod refs;
+pub mod revision;
+pub mod tag_entry;
+pub mod tree_entry;
+pub mod walk;
+pub mod graph;
+pub mod hooks;
+pub mod config;
+pub mod output;
+pub mod worktree;
 
-pub use index_entry::*;
-pub use tree_entry::*;
-pub use file_util::*;
+pub use alias::*;
 pub use commit::*;
 pub use commit_entry::*;
-
+pub use date::*;
+pub use delta::*;
+pub use diff::*;
+pub use file_util::*;
+pub use index_entry::*;
+pub use merge::*;
+pub use object::*;
+pub use pack::*;
+pub use patch::*;
+pub use pathspec::*;
+pub use parallel::*;
+pub use reachability::*;
+pub use reflog::*;
+pub use refs::*;
+pub use revision::*;
+pub use tag_entry::*;
+pub use tree_entry::*;
+pub use walk::*;
+pub use graph::*;
+pub use hooks::*;
+pub use config::*;
+pub use output::*;
+pub use worktree::*;
@@ -0,0 +1,229 @@
+use flate2::{bufread::ZlibDecoder, write::ZlibEncoder};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Result, Write},
+    path::Path,
+};
+
+use super::{loose_compression, open_packed_object, split_loose_object_path, temp_object_path};
+
+/// Streaming view over a loose object: the header is parsed up front and
+/// the content is inflated lazily as it is read.
+pub struct ObjectReader {
+    pub object_type: String,
+    pub size: u64,
+    decoder: Box<dyn Read>,
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.decoder.read(buf)
+    }
+}
+
+/// Open the object stored at a loose object path.
+///
+/// When no loose file exists the object is looked up in the packs of the
+/// same object directory, so callers never need to know where it lives.
+pub fn open_object(path: &Path) -> Result<ObjectReader> {
+    if !path.exists()
+        && let Some((objects_dir, hash)) = split_loose_object_path(path)
+        && let Some((object_type, size, decoder)) = open_packed_object(&objects_dir, &hash)?
+    {
+        return Ok(ObjectReader {
+            object_type,
+            size,
+            decoder,
+        });
+    }
+
+    let mut decoder = ZlibDecoder::new(BufReader::new(File::open(path)?));
+
+    // Header is "<type> <size>\0", read it one byte at a time
+    let mut header = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        decoder.read_exact(&mut byte)?;
+        if byte[0] == 0 {
+            break;
+        }
+        header.push(byte[0]);
+    }
+
+    let header = String::from_utf8_lossy(&header);
+    let (object_type, size) = header
+        .split_once(' ')
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid object header"))?;
+    let size = size
+        .parse::<u64>()
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid object size"))?;
+
+    Ok(ObjectReader {
+        object_type: object_type.to_string(),
+        size,
+        decoder: Box::new(decoder),
+    })
+}
+
+/// Hash `size` bytes of `reader` as an object of the given type.
+pub fn hash_reader<R: Read>(object_type: &str, size: u64, reader: &mut R) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{} {}\0", object_type, size).as_bytes());
+
+    let mut hashing = HashingWriter {
+        hasher: &mut hasher,
+    };
+    let copied = io::copy(&mut reader.take(size), &mut hashing)?;
+    if copied != size {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "Content shorter than expected",
+        ));
+    }
+
+    let mut sha256 = [0u8; 32];
+    sha256.copy_from_slice(&hasher.finalize()[..]);
+    Ok(sha256)
+}
+
+/// Hash and compress a blob in a single pass over `reader`.
+///
+/// The compressed object goes to a temporary file that is renamed into
+/// place once its hash is known, so memory use does not depend on `size`.
+pub fn store_blob_from_reader<R: Read>(
+    vit_dir: &Path,
+    size: u64,
+    reader: &mut R,
+) -> Result<[u8; 32]> {
+    let objects_dir = vit_dir.join("objects");
+    fs::create_dir_all(&objects_dir)?;
+
+    let header = format!("blob {}\0", size);
+    let mut hasher = Sha256::new();
+    hasher.update(header.as_bytes());
+
+    // Whatever fails after the temporary file exists removes it again
+    let temp_path = temp_object_path(&objects_dir);
+    let stored = (|| {
+        let mut encoder = ZlibEncoder::new(
+            BufWriter::new(File::create(&temp_path)?),
+            loose_compression(),
+        );
+        encoder.write_all(header.as_bytes())?;
+
+        let mut tee = TeeWriter {
+            hasher: &mut hasher,
+            inner: &mut encoder,
+        };
+        let copied = io::copy(&mut reader.take(size), &mut tee)?;
+        encoder.finish()?.flush()?;
+
+        if copied != size {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Content shorter than expected",
+            ));
+        }
+
+        let mut sha256 = [0u8; 32];
+        sha256.copy_from_slice(&hasher.finalize()[..]);
+
+        let sha256_hex = hex::encode(sha256);
+        let object_dir = objects_dir.join(&sha256_hex[..2]);
+        let object_path = object_dir.join(&sha256_hex[2..]);
+        if object_path.exists() {
+            fs::remove_file(&temp_path)?;
+        } else {
+            fs::create_dir_all(&object_dir)?;
+            fs::rename(&temp_path, &object_path)?;
+        }
+
+        Ok(sha256)
+    })();
+
+    if stored.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    stored
+}
+
+/// Inflate a blob straight into `destination` without buffering it.
+pub fn write_blob_to_file(blob_path: &Path, destination: &Path) -> Result<u64> {
+    let mut object = open_object(blob_path)?;
+    if object.object_type != "blob" {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Expected blob, found {}", object.object_type),
+        ));
+    }
+
+    let mut file = BufWriter::new(File::create(destination)?);
+    let written = io::copy(&mut object, &mut file)?;
+    file.flush()?;
+
+    Ok(written)
+}
+
+struct HashingWriter<'a> {
+    hasher: &'a mut Sha256,
+}
+
+impl Write for HashingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.hasher.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct TeeWriter<'a, W: Write> {
+    hasher: &'a mut Sha256,
+    inner: &'a mut W,
+}
+
+impl<W: Write> Write for TeeWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Write an object of any type as a loose object, unless it already exists.
+pub fn write_loose_object(
+    objects_dir: &Path,
+    object_type: &str,
+    content: &[u8],
+) -> Result<[u8; 32]> {
+    let mut full_data = format!("{} {}\0", object_type, content.len()).into_bytes();
+    full_data.extend_from_slice(content);
+
+    let mut sha256 = [0u8; 32];
+    sha256.copy_from_slice(&Sha256::digest(&full_data)[..]);
+
+    let sha256_hex = hex::encode(sha256);
+    let object_dir = objects_dir.join(&sha256_hex[..2]);
+    let object_path = object_dir.join(&sha256_hex[2..]);
+    if object_path.exists() {
+        return Ok(sha256);
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), loose_compression());
+    encoder.write_all(&full_data)?;
+    let compressed = encoder.finish()?;
+
+    fs::create_dir_all(&object_dir)?;
+    let temp_path = temp_object_path(&object_dir);
+    fs::write(&temp_path, compressed)?;
+    fs::rename(temp_path, object_path)?;
+
+    Ok(sha256)
+}
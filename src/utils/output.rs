@@ -0,0 +1,95 @@
+use crate::utils::{get_config, parse_config_bool};
+use colored::control::set_override;
+use pager::Pager;
+use std::{
+    env,
+    io::{IsTerminal, stdout},
+    sync::OnceLock,
+};
+
+/// Pager used when neither `VIT_PAGER`, `core.pager` nor `PAGER` is set.
+const DEFAULT_PAGER: &str = "less -R -F -X";
+
+/// When to colour output, from `--color` or `color.ui`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Only when writing to a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "auto" => Some(ColorMode::Auto),
+            "always" => Some(ColorMode::Always),
+            "never" => Some(ColorMode::Never),
+            other => parse_config_bool(other).map(|enabled| {
+                if enabled {
+                    ColorMode::Auto
+                } else {
+                    ColorMode::Never
+                }
+            }),
+        }
+    }
+}
+
+/// Output settings given on the command line before any command runs.
+#[derive(Debug, Default)]
+struct OutputSettings {
+    no_pager: bool,
+}
+
+static OUTPUT_SETTINGS: OnceLock<OutputSettings> = OnceLock::new();
+
+/// Apply the global `--no-pager` and `--color` flags.
+///
+/// Must run once before a command writes anything, the decision to colour
+/// is taken here while stdout is still the real terminal and not a pager.
+pub fn configure_output(no_pager: bool, color: Option<ColorMode>) {
+    let color = color
+        .or_else(|| get_config("color.ui").and_then(|value| ColorMode::parse(&value)))
+        .unwrap_or(ColorMode::Auto);
+
+    set_override(match color {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => stdout().is_terminal(),
+    });
+
+    let _ = OUTPUT_SETTINGS.set(OutputSettings { no_pager });
+
+    // Rust ignores SIGPIPE, so writing to a reader that went away, like
+    // `vit log | head`, fails and `print!` panics. Die quietly instead.
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+}
+
+/// Send the rest of stdout through the configured pager.
+///
+/// The pager is taken from `VIT_PAGER`, then `core.pager`, then `PAGER`.
+/// Nothing happens with `--no-pager`, when stdout is not a terminal, or
+/// when the pager is empty or `cat`.
+pub fn setup_pager() {
+    let settings = OUTPUT_SETTINGS.get_or_init(OutputSettings::default);
+    if settings.no_pager || !stdout().is_terminal() {
+        return;
+    }
+
+    let pager = env::var("VIT_PAGER")
+        .ok()
+        .or_else(|| get_config("core.pager"))
+        .or_else(|| env::var("PAGER").ok())
+        .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+
+    let pager = pager.trim();
+    if pager.is_empty() || pager == "cat" {
+        return;
+    }
+
+    Pager::with_pager(pager).setup();
+}
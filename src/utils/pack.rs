@@ -0,0 +1,727 @@
+use crate::utils::{
+    apply_delta, create_delta, open_object, pack_compression, parse_tree_data, read_varint,
+    write_loose_object, write_varint,
+};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use flate2::{bufread::ZlibDecoder, write::ZlibEncoder};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::{self, File},
+    io::{
+        self, BufReader, BufWriter, Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write,
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+const PACK_SIGNATURE: &[u8; 4] = b"PACK";
+const INDEX_SIGNATURE: &[u8; 4] = b"VIDX";
+const PACK_VERSION: u32 = 1;
+
+const OBJ_COMMIT: u8 = 1;
+const OBJ_TREE: u8 = 2;
+const OBJ_BLOB: u8 = 3;
+const OBJ_TAG: u8 = 4;
+const OBJ_REF_DELTA: u8 = 7;
+
+/// Number of preceding objects a new object is compared against for deltas.
+const DELTA_WINDOW: usize = 10;
+/// Longest chain of deltas allowed before an object is stored whole.
+const MAX_DELTA_DEPTH: usize = 50;
+/// Objects above this size are streamed into the pack without delta compression.
+const MAX_DELTA_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Lookup table for a single pack, loaded from its `.idx` file.
+///
+/// Hashes are sorted and the fanout table narrows the search to the hashes
+/// sharing the first byte, so a lookup is a binary search over a small range.
+pub struct PackIndex {
+    pub pack_path: PathBuf,
+    fanout: [u32; 256],
+    hashes: Vec<[u8; 32]>,
+    offsets: Vec<u64>,
+}
+
+impl PackIndex {
+    pub fn load(index_path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(index_path)?);
+
+        let mut signature = [0u8; 4];
+        reader.read_exact(&mut signature)?;
+        if &signature != INDEX_SIGNATURE || reader.read_u32::<BigEndian>()? != PACK_VERSION {
+            return Err(corrupt("Unsupported pack index"));
+        }
+
+        let mut fanout = [0u32; 256];
+        for slot in fanout.iter_mut() {
+            *slot = reader.read_u32::<BigEndian>()?;
+        }
+
+        let count = fanout[255] as usize;
+        let mut hashes = vec![[0u8; 32]; count];
+        for hash in hashes.iter_mut() {
+            reader.read_exact(hash)?;
+        }
+        let mut offsets = Vec::with_capacity(count);
+        for _ in 0..count {
+            offsets.push(reader.read_u64::<BigEndian>()?);
+        }
+
+        Ok(PackIndex {
+            pack_path: index_path.with_extension("pack"),
+            fanout,
+            hashes,
+            offsets,
+        })
+    }
+
+    pub fn find(&self, hash: &[u8; 32]) -> Option<u64> {
+        let first = hash[0] as usize;
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1] as usize
+        };
+        let end = self.fanout[first] as usize;
+
+        self.hashes[start..end]
+            .binary_search(hash)
+            .ok()
+            .map(|i| self.offsets[start + i])
+    }
+
+    pub fn hashes(&self) -> &[[u8; 32]] {
+        &self.hashes
+    }
+}
+
+/// Type, size and a reader over the content of a packed object.
+pub type PackedObject = (String, u64, Box<dyn Read>);
+/// Type code, size, delta base and a reader over the data of a pack entry.
+type PackEntry = (u8, u64, Option<[u8; 32]>, Box<dyn Read>);
+type PackCache = Mutex<HashMap<PathBuf, Arc<Vec<PackIndex>>>>;
+
+fn pack_cache() -> &'static PackCache {
+    static CACHE: std::sync::OnceLock<PackCache> = std::sync::OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// All packs of an object directory, loaded once per process.
+pub fn load_packs(objects_dir: &Path) -> Result<Arc<Vec<PackIndex>>> {
+    if let Some(packs) = pack_cache().lock().unwrap().get(objects_dir) {
+        return Ok(Arc::clone(packs));
+    }
+
+    let mut packs = Vec::new();
+    let pack_dir = objects_dir.join("pack");
+    if pack_dir.exists() {
+        let mut index_paths: Vec<PathBuf> = fs::read_dir(&pack_dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "idx"))
+            .collect();
+        index_paths.sort();
+
+        for index_path in index_paths {
+            packs.push(PackIndex::load(&index_path)?);
+        }
+    }
+
+    let packs = Arc::new(packs);
+    pack_cache()
+        .lock()
+        .unwrap()
+        .insert(objects_dir.to_path_buf(), Arc::clone(&packs));
+    Ok(packs)
+}
+
+/// Forget cached pack indexes, called after packs are added or removed.
+pub fn invalidate_pack_cache() {
+    pack_cache().lock().unwrap().clear();
+}
+
+pub fn find_packed_object(objects_dir: &Path, hash: &[u8; 32]) -> Result<Option<(PathBuf, u64)>> {
+    for pack in load_packs(objects_dir)?.iter() {
+        if let Some(offset) = pack.find(hash) {
+            return Ok(Some((pack.pack_path.clone(), offset)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Read a packed object fully into memory, resolving delta chains.
+pub fn read_packed_object(
+    objects_dir: &Path,
+    hash: &[u8; 32],
+) -> Result<Option<(String, Vec<u8>)>> {
+    let Some((pack_path, offset)) = find_packed_object(objects_dir, hash)? else {
+        return Ok(None);
+    };
+
+    let (object_type, _size, base, mut reader) = open_pack_entry(&pack_path, offset)?;
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+
+    match base {
+        None => Ok(Some((type_name(object_type)?.to_string(), content))),
+        Some(base_hash) => {
+            let (base_type, base_content) = read_object_by_hash(objects_dir, &base_hash)?;
+            Ok(Some((base_type, apply_delta(&base_content, &content)?)))
+        }
+    }
+}
+
+/// Open a packed object for reading.
+///
+/// Whole objects are inflated lazily straight from the pack, deltified
+/// objects are resolved in memory first.
+pub fn open_packed_object(objects_dir: &Path, hash: &[u8; 32]) -> Result<Option<PackedObject>> {
+    let Some((pack_path, offset)) = find_packed_object(objects_dir, hash)? else {
+        return Ok(None);
+    };
+
+    let (object_type, size, base, reader) = open_pack_entry(&pack_path, offset)?;
+    if base.is_none() {
+        return Ok(Some((type_name(object_type)?.to_string(), size, reader)));
+    }
+
+    match read_packed_object(objects_dir, hash)? {
+        Some((object_type, content)) => {
+            let size = content.len() as u64;
+            Ok(Some((object_type, size, Box::new(Cursor::new(content)))))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Read an object from wherever it lives, loose or packed.
+pub fn read_object_by_hash(objects_dir: &Path, hash: &[u8; 32]) -> Result<(String, Vec<u8>)> {
+    let mut object = open_object(&loose_object_path(objects_dir, hash))?;
+    let mut content = Vec::with_capacity(object.size as usize);
+    object.read_to_end(&mut content)?;
+    Ok((object.object_type.clone(), content))
+}
+
+pub fn loose_object_path(objects_dir: &Path, hash: &[u8; 32]) -> PathBuf {
+    let hash_str = hex::encode(hash);
+    objects_dir.join(&hash_str[..2]).join(&hash_str[2..])
+}
+
+/// Recover the object directory and hash from a loose object path such as
+/// `.vit/objects/ab/cdef...`.
+pub fn split_loose_object_path(path: &Path) -> Option<(PathBuf, [u8; 32])> {
+    let file_name = path.file_name()?.to_str()?;
+    let dir = path.parent()?;
+    let dir_name = dir.file_name()?.to_str()?;
+
+    let bytes = hex::decode(format!("{}{}", dir_name, file_name)).ok()?;
+    let hash: [u8; 32] = bytes.try_into().ok()?;
+
+    Some((dir.parent()?.to_path_buf(), hash))
+}
+
+/// Hashes of every loose object in the object directory.
+pub fn list_loose_objects(objects_dir: &Path) -> Result<Vec<[u8; 32]>> {
+    let mut hashes = Vec::new();
+    if !objects_dir.exists() {
+        return Ok(hashes);
+    }
+
+    for dir in fs::read_dir(objects_dir)? {
+        let dir = dir?;
+        let dir_name = dir.file_name().to_string_lossy().to_string();
+        if dir_name.len() != 2 || !dir.file_type()?.is_dir() {
+            continue;
+        }
+
+        for file in fs::read_dir(dir.path())? {
+            let file_name = file?.file_name().to_string_lossy().to_string();
+            if let Ok(bytes) = hex::decode(format!("{}{}", dir_name, file_name))
+                && let Ok(hash) = bytes.try_into()
+            {
+                hashes.push(hash);
+            }
+        }
+    }
+
+    hashes.sort();
+    Ok(hashes)
+}
+
+/// Hashes of every object stored in a pack.
+pub fn list_packed_objects(objects_dir: &Path) -> Result<Vec<[u8; 32]>> {
+    let mut hashes: Vec<[u8; 32]> = load_packs(objects_dir)?
+        .iter()
+        .flat_map(|pack| pack.hashes().iter().copied())
+        .collect();
+    hashes.sort();
+    hashes.dedup();
+    Ok(hashes)
+}
+
+/// Summary of a pack written by `write_pack`.
+pub struct PackStats {
+    pub pack_path: PathBuf,
+    pub objects: usize,
+    pub deltas: usize,
+}
+
+/// Write the given objects into a new pack and its index.
+///
+/// Objects are ordered by type, file name and descending size so similar
+/// objects end up next to each other, then each one is compared against a
+/// sliding window of its predecessors and stored as a delta when that is
+/// less than half of its full size.
+pub fn write_pack(objects_dir: &Path, hashes: &[[u8; 32]]) -> Result<PackStats> {
+    let pack_dir = objects_dir.join("pack");
+    fs::create_dir_all(&pack_dir)?;
+
+    // Collect type and size of every object, and file names from trees as hints
+    let mut candidates = Vec::with_capacity(hashes.len());
+    let mut name_hints: HashMap<[u8; 32], String> = HashMap::new();
+    for hash in hashes {
+        let object = open_object(&loose_object_path(objects_dir, hash))?;
+        let object_type = type_code(&object.object_type)?;
+
+        if object_type == OBJ_TREE {
+            let (_, content) = read_object_by_hash(objects_dir, hash)?;
+            for entry in parse_tree_data(&content)? {
+                name_hints.insert(entry.sha256, entry.name);
+            }
+        }
+
+        candidates.push((*hash, object_type, object.size));
+    }
+
+    candidates.sort_by(|a, b| {
+        let hint_a = name_hints.get(&a.0).map(String::as_str).unwrap_or("");
+        let hint_b = name_hints.get(&b.0).map(String::as_str).unwrap_or("");
+        a.1.cmp(&b.1)
+            .then_with(|| hint_a.cmp(hint_b))
+            .then_with(|| b.2.cmp(&a.2))
+    });
+
+    let temp_path = pack_dir.join(format!("tmp_pack_{}", std::process::id()));
+    let mut writer = PackWriter::new(File::create(&temp_path)?);
+    writer.write_all(PACK_SIGNATURE)?;
+    writer.write_u32::<BigEndian>(PACK_VERSION)?;
+    writer.write_u32::<BigEndian>(candidates.len() as u32)?;
+
+    let mut entries: Vec<([u8; 32], u64)> = Vec::with_capacity(candidates.len());
+    let mut window: VecDeque<([u8; 32], u8, Vec<u8>)> = VecDeque::with_capacity(DELTA_WINDOW);
+    let mut depths: HashMap<[u8; 32], usize> = HashMap::new();
+    let mut deltas = 0;
+
+    for (hash, object_type, size) in candidates {
+        entries.push((hash, writer.offset));
+
+        if size > MAX_DELTA_SIZE {
+            // Too large to hold in memory, stream it into the pack whole
+            let mut object = open_object(&loose_object_path(objects_dir, &hash))?;
+            write_entry_header(&mut writer, object_type, size, None)?;
+            let mut encoder = ZlibEncoder::new(&mut writer, pack_compression());
+            io::copy(&mut object, &mut encoder)?;
+            encoder.finish()?;
+            continue;
+        }
+
+        let (_, content) = read_object_by_hash(objects_dir, &hash)?;
+
+        let mut best: Option<([u8; 32], Vec<u8>)> = None;
+        for (base_hash, base_type, base_content) in window.iter() {
+            if *base_type != object_type
+                || depths.get(base_hash).copied().unwrap_or(0) >= MAX_DELTA_DEPTH
+            {
+                continue;
+            }
+
+            let delta = create_delta(base_content, &content);
+            let is_better = best
+                .as_ref()
+                .is_none_or(|(_, current)| delta.len() < current.len());
+            if delta.len() < content.len() / 2 && is_better {
+                best = Some((*base_hash, delta));
+            }
+        }
+
+        match best {
+            Some((base_hash, delta)) => {
+                write_entry_header(
+                    &mut writer,
+                    OBJ_REF_DELTA,
+                    delta.len() as u64,
+                    Some(&base_hash),
+                )?;
+                write_compressed(&mut writer, &delta)?;
+                depths.insert(hash, depths.get(&base_hash).copied().unwrap_or(0) + 1);
+                deltas += 1;
+            }
+            None => {
+                write_entry_header(&mut writer, object_type, content.len() as u64, None)?;
+                write_compressed(&mut writer, &content)?;
+            }
+        }
+
+        if window.len() == DELTA_WINDOW {
+            window.pop_front();
+        }
+        window.push_back((hash, object_type, content));
+    }
+
+    let checksum = writer.finish()?;
+    let pack_name = format!("pack-{}", hex::encode(checksum));
+    let pack_path = pack_dir.join(format!("{}.pack", pack_name));
+    fs::rename(&temp_path, &pack_path)?;
+
+    // The index is written last, readers only look for packs through it
+    entries.sort_by_key(|entry| entry.0);
+    write_pack_index(
+        &pack_dir.join(format!("{}.idx", pack_name)),
+        &entries,
+        &checksum,
+    )?;
+    invalidate_pack_cache();
+
+    Ok(PackStats {
+        pack_path,
+        objects: entries.len(),
+        deltas,
+    })
+}
+
+fn write_pack_index(
+    index_path: &Path,
+    entries: &[([u8; 32], u64)],
+    checksum: &[u8; 32],
+) -> Result<()> {
+    let temp_path = index_path.with_extension("idx.tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+
+    writer.write_all(INDEX_SIGNATURE)?;
+    writer.write_u32::<BigEndian>(PACK_VERSION)?;
+
+    let mut fanout = [0u32; 256];
+    for (hash, _) in entries {
+        fanout[hash[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in fanout.iter_mut() {
+        total += *count;
+        *count = total;
+    }
+    for count in fanout {
+        writer.write_u32::<BigEndian>(count)?;
+    }
+
+    for (hash, _) in entries {
+        writer.write_all(hash)?;
+    }
+    for (_, offset) in entries {
+        writer.write_u64::<BigEndian>(*offset)?;
+    }
+    writer.write_all(checksum)?;
+    writer.flush()?;
+
+    fs::rename(temp_path, index_path)
+}
+
+fn write_entry_header<W: Write>(
+    writer: &mut W,
+    object_type: u8,
+    size: u64,
+    base: Option<&[u8; 32]>,
+) -> Result<()> {
+    let mut header = vec![object_type];
+    write_varint(&mut header, size);
+    if let Some(base) = base {
+        header.extend_from_slice(base);
+    }
+    writer.write_all(&header)
+}
+
+fn write_compressed<W: Write>(writer: &mut W, data: &[u8]) -> Result<()> {
+    let mut encoder = ZlibEncoder::new(writer, pack_compression());
+    encoder.write_all(data)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Parse the entry header at `offset` and return a reader inflating its data.
+fn open_pack_entry(pack_path: &Path, offset: u64) -> Result<PackEntry> {
+    let mut file = File::open(pack_path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = BufReader::new(file);
+
+    let object_type = reader.read_u8()?;
+
+    // Entry sizes are varints, read them a byte at a time
+    let mut size_bytes = Vec::new();
+    loop {
+        let byte = reader.read_u8()?;
+        size_bytes.push(byte);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    let size = read_varint(&size_bytes, &mut 0)?;
+
+    let base = if object_type == OBJ_REF_DELTA {
+        let mut base = [0u8; 32];
+        reader.read_exact(&mut base)?;
+        Some(base)
+    } else {
+        type_name(object_type)?;
+        None
+    };
+
+    Ok((object_type, size, base, Box::new(ZlibDecoder::new(reader))))
+}
+
+fn type_code(object_type: &str) -> Result<u8> {
+    match object_type {
+        "commit" => Ok(OBJ_COMMIT),
+        "tree" => Ok(OBJ_TREE),
+        "blob" => Ok(OBJ_BLOB),
+        "tag" => Ok(OBJ_TAG),
+        _ => Err(corrupt("Unknown object type")),
+    }
+}
+
+fn type_name(object_type: u8) -> Result<&'static str> {
+    match object_type {
+        OBJ_COMMIT => Ok("commit"),
+        OBJ_TREE => Ok("tree"),
+        OBJ_BLOB => Ok("blob"),
+        OBJ_TAG => Ok("tag"),
+        _ => Err(corrupt("Unknown packed object type")),
+    }
+}
+
+fn corrupt(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Writer that tracks the current offset and checksums everything written.
+struct PackWriter {
+    inner: BufWriter<File>,
+    hasher: Sha256,
+    offset: u64,
+}
+
+impl PackWriter {
+    fn new(file: File) -> Self {
+        PackWriter {
+            inner: BufWriter::new(file),
+            hasher: Sha256::new(),
+            offset: 0,
+        }
+    }
+
+    /// Append the checksum trailer and return it.
+    fn finish(mut self) -> Result<[u8; 32]> {
+        let mut checksum = [0u8; 32];
+        checksum.copy_from_slice(&self.hasher.clone().finalize()[..]);
+        self.inner.write_all(&checksum)?;
+        self.inner.flush()?;
+        Ok(checksum)
+    }
+}
+
+impl Write for PackWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Move loose objects into a new pack and delete them afterwards.
+///
+/// With `all` the objects of existing packs are included as well and the
+/// old packs are removed, leaving a single pack behind. When `reachable` is
+/// given only those objects are packed: unreachable loose objects stay
+/// loose and unreachable packed objects are written back out as loose
+/// objects, so `prune` can expire them after its grace period.
+pub fn repack(
+    objects_dir: &Path,
+    all: bool,
+    reachable: Option<&HashSet<[u8; 32]>>,
+) -> Result<Option<PackStats>> {
+    let is_kept = |hash: &[u8; 32]| reachable.is_none_or(|set| set.contains(hash));
+
+    let loose: Vec<[u8; 32]> = list_loose_objects(objects_dir)?
+        .into_iter()
+        .filter(|hash| is_kept(hash))
+        .collect();
+    let packed = if all {
+        list_packed_objects(objects_dir)?
+    } else {
+        Vec::new()
+    };
+    let old_packs: Vec<PathBuf> = load_packs(objects_dir)?
+        .iter()
+        .map(|pack| pack.pack_path.clone())
+        .collect();
+
+    let mut hashes = loose.clone();
+    hashes.extend(packed.iter().filter(|hash| is_kept(hash)));
+    hashes.sort();
+    hashes.dedup();
+
+    let is_up_to_date = if all {
+        loose.is_empty() && old_packs.len() == 1 && hashes.len() == packed.len()
+    } else {
+        loose.is_empty()
+    };
+    if hashes.is_empty() || is_up_to_date {
+        return Ok(None);
+    }
+
+    if all {
+        for hash in packed.iter().filter(|hash| !is_kept(hash)) {
+            let (object_type, content) = read_object_by_hash(objects_dir, hash)?;
+            write_loose_object(objects_dir, &object_type, &content)?;
+        }
+    }
+
+    let stats = write_pack(objects_dir, &hashes)?;
+
+    for hash in &loose {
+        let path = loose_object_path(objects_dir, hash);
+        fs::remove_file(&path)?;
+        if let Some(dir) = path.parent() {
+            let _ = fs::remove_dir(dir); // Only succeeds once the directory is empty
+        }
+    }
+
+    if all {
+        for pack_path in old_packs {
+            if pack_path != stats.pack_path {
+                fs::remove_file(pack_path.with_extension("idx"))?;
+                fs::remove_file(&pack_path)?;
+            }
+        }
+        invalidate_pack_cache();
+    }
+
+    Ok(Some(stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_objects_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "vit-pack-test-{}-{}/objects",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Depth of the delta chain below the packed object `hash`.
+    fn delta_depth(objects_dir: &Path, hash: &[u8; 32]) -> usize {
+        let (pack_path, offset) = find_packed_object(objects_dir, hash).unwrap().unwrap();
+        match open_pack_entry(&pack_path, offset).unwrap().2 {
+            Some(base) => 1 + delta_depth(objects_dir, &base),
+            None => 0,
+        }
+    }
+
+    #[test]
+    fn packed_objects_are_found_through_the_index() {
+        let objects_dir = temp_objects_dir("index");
+
+        let mut objects = Vec::new();
+        for i in 0..64 {
+            let content = format!("object number {}\n", i).repeat(i + 1);
+            let hash = write_loose_object(&objects_dir, "blob", content.as_bytes()).unwrap();
+            objects.push((hash, content));
+        }
+        let hashes: Vec<[u8; 32]> = objects.iter().map(|(hash, _)| *hash).collect();
+
+        let stats = repack(&objects_dir, false, None).unwrap().unwrap();
+        assert_eq!(stats.objects, objects.len());
+        assert!(list_loose_objects(&objects_dir).unwrap().is_empty());
+
+        let index = PackIndex::load(&stats.pack_path.with_extension("idx")).unwrap();
+        let mut sorted = hashes.clone();
+        sorted.sort();
+        assert_eq!(index.hashes(), sorted.as_slice());
+        assert!(index.find(&[0u8; 32]).is_none());
+        assert!(index.find(&[0xffu8; 32]).is_none());
+
+        for (hash, content) in &objects {
+            assert!(index.find(hash).is_some());
+            let (object_type, data) = read_object_by_hash(&objects_dir, hash).unwrap();
+            assert_eq!(object_type, "blob");
+            assert_eq!(data, content.as_bytes());
+        }
+
+        fs::remove_dir_all(objects_dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn delta_chains_stop_at_the_depth_limit() {
+        let objects_dir = temp_objects_dir("depth");
+
+        // Every version edits one more line of the previous one, so recent
+        // versions make the best bases and the chain grows long
+        let lines: Vec<String> = (0..400)
+            .map(|i| format!("line {:03} of the original text\n", i))
+            .collect();
+        let versions: Vec<String> = (0..MAX_DELTA_DEPTH + 30)
+            .map(|edited| {
+                lines
+                    .iter()
+                    .enumerate()
+                    .map(|(i, line)| match i % 4 == 0 && i / 4 < edited {
+                        true => format!("line {:03} of the changed  text\n", i),
+                        false => line.clone(),
+                    })
+                    .collect()
+            })
+            .collect();
+        let hashes: Vec<[u8; 32]> = versions
+            .iter()
+            .map(|content| write_loose_object(&objects_dir, "blob", content.as_bytes()).unwrap())
+            .collect();
+
+        let stats = write_pack(&objects_dir, &hashes).unwrap();
+        for hash in &hashes {
+            fs::remove_file(loose_object_path(&objects_dir, hash)).unwrap();
+        }
+
+        let depths: Vec<usize> = hashes
+            .iter()
+            .map(|hash| delta_depth(&objects_dir, hash))
+            .collect();
+        assert_eq!(depths.iter().max(), Some(&MAX_DELTA_DEPTH));
+        assert_eq!(
+            stats.deltas,
+            depths.iter().filter(|depth| **depth > 0).count()
+        );
+
+        // Once every base in the window is at the limit a version is stored
+        // whole and starts a new chain
+        let restart = depths.iter().skip(1).position(|depth| *depth == 0).unwrap() + 1;
+        assert!(depths[..restart].contains(&MAX_DELTA_DEPTH));
+        assert_eq!(depths[restart + 1], 1);
+
+        for (hash, content) in hashes.iter().zip(&versions) {
+            let (_, data) = read_object_by_hash(&objects_dir, hash).unwrap();
+            assert_eq!(data, content.as_bytes());
+        }
+
+        fs::remove_dir_all(objects_dir.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,587 @@
+use crate::utils::{
+    DEFAULT_CONTEXT, Edit, Hunk, build_hunks, color_patch, diff_lines, hunk_range, run_editor,
+};
+use colored::Colorize;
+use std::{
+    env, fs,
+    io::{BufRead, Result, Write},
+};
+
+/// What came of going through the hunks of one file in patch mode.
+#[derive(Debug, Default)]
+pub struct PatchSelection {
+    /// The old content with the chosen hunks applied, `None` when nothing
+    /// was chosen. For a deletion any content means it was chosen.
+    pub content: Option<Vec<u8>>,
+    /// The user asked to stop, later files are not offered
+    pub quit: bool,
+}
+
+/// Lines of a file, each with its line ending.
+type Lines = Vec<Vec<u8>>;
+
+/// Lines `old_start..old_end` of the old file replaced by `new_lines`.
+#[derive(Debug)]
+struct Replacement {
+    old_start: usize,
+    old_end: usize,
+    new_lines: Lines,
+}
+
+const HUNK_HELP: &str = "y - apply this hunk
+n - do not apply this hunk
+q - quit; do not apply this hunk or any of the remaining ones
+a - apply this hunk and all later hunks in the file
+d - do not apply this hunk or any of the later hunks in the file
+s - split the current hunk into smaller hunks
+e - manually edit the current hunk
+? - print help";
+
+/// Offer every hunk between `old` and `new` and return `old` with the
+/// chosen ones applied, like `git add -p`. `verb` starts each question,
+/// e.g. "Stage".
+///
+/// Answers are read line by line from `input`, running out of input is
+/// the same as quitting.
+pub fn select_hunks<R: BufRead, W: Write>(
+    path: &str,
+    old: &[u8],
+    new: &[u8],
+    verb: &str,
+    input: &mut R,
+    out: &mut W,
+) -> Result<PatchSelection> {
+    let old_lines: Vec<&[u8]> = old.split_inclusive(|&b| b == b'\n').collect();
+    let new_lines: Vec<&[u8]> = new.split_inclusive(|&b| b == b'\n').collect();
+    let edits = diff_lines(&old_lines, &new_lines);
+    let mut hunks = build_hunks(&edits, DEFAULT_CONTEXT);
+
+    write!(
+        out,
+        "{}",
+        format!(
+            "diff --vit a/{} b/{}\n--- a/{}\n+++ b/{}\n",
+            path, path, path, path
+        )
+        .bold()
+    )?;
+
+    let mut replacements = Vec::new();
+    let mut quit = false;
+    let mut index = 0;
+
+    while index < hunks.len() {
+        let hunk = &hunks[index];
+        write!(
+            out,
+            "{}",
+            color_patch(&format_hunk(hunk, &old_lines, &new_lines))
+        )?;
+
+        let can_split = change_runs(&hunk.edits).len() > 1;
+        let options = if can_split {
+            "y,n,q,a,d,s,e,?"
+        } else {
+            "y,n,q,a,d,e,?"
+        };
+        let question = format!(
+            "({}/{}) {} this hunk [{}]? ",
+            index + 1,
+            hunks.len(),
+            verb,
+            options
+        );
+
+        match ask(input, out, &question)?.as_deref() {
+            Some("y") => {
+                replacements.extend(hunk_replacements(hunk, &new_lines));
+                index += 1;
+            }
+            Some("n") => index += 1,
+            Some("q") | None => {
+                quit = true;
+                break;
+            }
+            Some("a") => {
+                for hunk in &hunks[index..] {
+                    replacements.extend(hunk_replacements(hunk, &new_lines));
+                }
+                break;
+            }
+            Some("d") => break,
+            Some("s") if can_split => {
+                let parts = split_hunk(hunk);
+                writeln!(
+                    out,
+                    "{}",
+                    format!("Split into {} hunks.", parts.len()).cyan()
+                )?;
+                hunks.splice(index..=index, parts);
+            }
+            Some("e") => {
+                if let Some(replacement) = edit_hunk(hunk, &old_lines, &new_lines, input, out)? {
+                    replacements.push(replacement);
+                    index += 1;
+                }
+            }
+            _ => writeln!(out, "{}", HUNK_HELP.red())?,
+        }
+    }
+
+    let content = (!replacements.is_empty()).then(|| apply_replacements(&old_lines, replacements));
+    Ok(PatchSelection { content, quit })
+}
+
+/// Ask whether a deleted file should be taken over as a whole.
+pub fn select_deletion<R: BufRead, W: Write>(
+    path: &str,
+    verb: &str,
+    input: &mut R,
+    out: &mut W,
+) -> Result<PatchSelection> {
+    writeln!(
+        out,
+        "{}",
+        format!("diff --vit a/{} b/{}\ndeleted file", path, path).bold()
+    )?;
+
+    let question = format!("(1/1) {} deletion [y,n,q,a,d,?]? ", verb);
+    loop {
+        match ask(input, out, &question)?.as_deref() {
+            Some("y" | "a") => {
+                return Ok(PatchSelection {
+                    content: Some(Vec::new()),
+                    quit: false,
+                });
+            }
+            Some("n" | "d") => return Ok(PatchSelection::default()),
+            Some("q") | None => {
+                return Ok(PatchSelection {
+                    content: None,
+                    quit: true,
+                });
+            }
+            _ => writeln!(out, "{}", HUNK_HELP.red())?,
+        }
+    }
+}
+
+/// Print `question` and read the first letter of the answer, `None` once
+/// the input is exhausted.
+fn ask<R: BufRead, W: Write>(input: &mut R, out: &mut W, question: &str) -> Result<Option<String>> {
+    write!(out, "{}", question.blue().bold())?;
+    out.flush()?;
+
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        writeln!(out)?;
+        return Ok(None);
+    }
+    Ok(Some(
+        answer
+            .trim()
+            .chars()
+            .next()
+            .map(|c| c.to_ascii_lowercase().to_string())
+            .unwrap_or_default(),
+    ))
+}
+
+fn format_hunk(hunk: &Hunk, old_lines: &[&[u8]], new_lines: &[&[u8]]) -> String {
+    let mut text = format!(
+        "@@ -{} +{} @@\n",
+        hunk_range(hunk.old_start, hunk.old_count),
+        hunk_range(hunk.new_start, hunk.new_count)
+    );
+
+    for edit in &hunk.edits {
+        let (prefix, line) = match *edit {
+            Edit::Equal { old, .. } => (' ', old_lines[old]),
+            Edit::Delete { old } => ('-', old_lines[old]),
+            Edit::Insert { new } => ('+', new_lines[new]),
+        };
+        text.push(prefix);
+        text.push_str(&String::from_utf8_lossy(line));
+        if !line.ends_with(b"\n") {
+            text.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+
+    text
+}
+
+/// Index ranges of the runs of changed lines in `edits`.
+fn change_runs(edits: &[Edit]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+
+    for (i, edit) in edits.iter().enumerate() {
+        if matches!(edit, Edit::Equal { .. }) {
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run) if run.1 == i => run.1 = i + 1,
+            _ => runs.push((i, i + 1)),
+        }
+    }
+
+    runs
+}
+
+/// One hunk per run of changes, each keeping all context up to the
+/// neighbouring runs so consecutive parts share their context lines.
+fn split_hunk(hunk: &Hunk) -> Vec<Hunk> {
+    let runs = change_runs(&hunk.edits);
+
+    (0..runs.len())
+        .map(|i| {
+            let from = if i == 0 { 0 } else { runs[i - 1].1 };
+            let to = runs.get(i + 1).map_or(hunk.edits.len(), |run| run.0);
+            let before = &hunk.edits[..from];
+            let edits = hunk.edits[from..to].to_vec();
+
+            Hunk {
+                old_start: hunk.old_start + count_old(before),
+                old_count: count_old(&edits),
+                new_start: hunk.new_start + count_new(before),
+                new_count: count_new(&edits),
+                edits,
+            }
+        })
+        .collect()
+}
+
+fn count_old(edits: &[Edit]) -> usize {
+    edits
+        .iter()
+        .filter(|edit| !matches!(edit, Edit::Insert { .. }))
+        .count()
+}
+
+fn count_new(edits: &[Edit]) -> usize {
+    edits
+        .iter()
+        .filter(|edit| !matches!(edit, Edit::Delete { .. }))
+        .count()
+}
+
+/// The changes of a hunk, one replacement per run of changed lines.
+fn hunk_replacements(hunk: &Hunk, new_lines: &[&[u8]]) -> Vec<Replacement> {
+    change_runs(&hunk.edits)
+        .into_iter()
+        .map(|(from, to)| {
+            let old_start = hunk.old_start + count_old(&hunk.edits[..from]);
+            let run = &hunk.edits[from..to];
+            let new_lines = run
+                .iter()
+                .filter_map(|edit| match *edit {
+                    Edit::Insert { new } => Some(new_lines[new].to_vec()),
+                    _ => None,
+                })
+                .collect();
+
+            Replacement {
+                old_start,
+                old_end: old_start + count_old(run),
+                new_lines,
+            }
+        })
+        .collect()
+}
+
+/// Rebuild the file from the old lines with the replacements applied, one
+/// overlapping an earlier replacement is dropped.
+fn apply_replacements(old_lines: &[&[u8]], mut replacements: Vec<Replacement>) -> Vec<u8> {
+    replacements.sort_by_key(|replacement| (replacement.old_start, replacement.old_end));
+
+    let mut content = Vec::new();
+    let mut position = 0;
+    for replacement in replacements {
+        if replacement.old_start < position {
+            continue;
+        }
+        for line in &old_lines[position..replacement.old_start] {
+            content.extend_from_slice(line);
+        }
+        for line in &replacement.new_lines {
+            content.extend_from_slice(line);
+        }
+        position = replacement.old_end;
+    }
+    for line in &old_lines[position..] {
+        content.extend_from_slice(line);
+    }
+
+    content
+}
+
+const EDIT_HELP: &str = "# ---
+# To remove '-' lines, make them ' ' lines (context).
+# To remove '+' lines, delete them.
+# Lines starting with # will be removed.
+# If the patch applies cleanly, the edited hunk will immediately be
+# marked for applying. If it does not apply cleanly, you will be given
+# an opportunity to edit again. If all lines of the hunk are removed,
+# then the edit is aborted and the hunk is left unchanged.
+";
+
+/// Let the user edit `hunk` in their editor. Returns the edited change, or
+/// `None` when the edit was given up and the hunk should be offered again.
+fn edit_hunk<R: BufRead, W: Write>(
+    hunk: &Hunk,
+    old_lines: &[&[u8]],
+    new_lines: &[&[u8]],
+    input: &mut R,
+    out: &mut W,
+) -> Result<Option<Replacement>> {
+    let vit_dir = env::current_dir()?.join(".vit");
+    let patch_path = vit_dir.join("ADD_EDIT.patch");
+    let expected_old: Vec<&[u8]> =
+        old_lines[hunk.old_start..hunk.old_start + hunk.old_count].to_vec();
+
+    let mut text = String::from("# Manual hunk edit mode - see bottom for a quick guide.\n");
+    text.push_str(&format_hunk(hunk, old_lines, new_lines));
+    text.push_str(EDIT_HELP);
+
+    loop {
+        fs::write(&patch_path, &text)?;
+        if let Err(message) = run_editor(&patch_path) {
+            writeln!(out, "{}", message.red())?;
+            return Ok(None);
+        }
+        let edited = fs::read_to_string(&patch_path)?;
+        let _ = fs::remove_file(&patch_path);
+
+        match parse_edited_hunk(&edited) {
+            Some((old_side, new_side)) if old_side.is_empty() && new_side.is_empty() => {
+                return Ok(None);
+            }
+            Some((old_side, new_side))
+                if old_side
+                    .iter()
+                    .map(Vec::as_slice)
+                    .eq(expected_old.iter().copied()) =>
+            {
+                return Ok(Some(edited_replacement(
+                    hunk.old_start,
+                    &old_side,
+                    new_side,
+                )));
+            }
+            _ => {}
+        }
+
+        writeln!(out, "{}", "Your edited hunk does not apply.".red())?;
+        text = edited;
+        match ask(input, out, "Edit again (saying \"no\" discards!) [y/n]? ")?.as_deref() {
+            Some("y") => continue,
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// The change an edited hunk starting at line `old_start` makes, unchanged
+/// lines at either end are context and left out.
+fn edited_replacement(old_start: usize, old_side: &[Vec<u8>], mut new_side: Lines) -> Replacement {
+    let prefix = old_side
+        .iter()
+        .zip(&new_side)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let max_suffix = old_side.len().min(new_side.len()) - prefix;
+    let suffix = old_side
+        .iter()
+        .rev()
+        .zip(new_side.iter().rev())
+        .take(max_suffix)
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    new_side.truncate(new_side.len() - suffix);
+    Replacement {
+        old_start: old_start + prefix,
+        old_end: old_start + old_side.len() - suffix,
+        new_lines: new_side.split_off(prefix),
+    }
+}
+
+/// Split an edited hunk into the old and new lines it describes, `None`
+/// when a line does not start with ' ', '-', '+', '#', '@' or '\'.
+fn parse_edited_hunk(text: &str) -> Option<(Lines, Lines)> {
+    let mut old_side: Lines = Vec::new();
+    let mut new_side: Lines = Vec::new();
+    // Which sides the previous line went to, for "\ No newline"
+    let mut last = (false, false);
+
+    for line in text.lines() {
+        let (to_old, to_new, content) = match line.chars().next() {
+            None => (true, true, ""),
+            Some(' ') => (true, true, &line[1..]),
+            Some('-') => (true, false, &line[1..]),
+            Some('+') => (false, true, &line[1..]),
+            Some('#' | '@') => continue,
+            Some('\\') => {
+                if last.0 {
+                    old_side.last_mut()?.pop();
+                }
+                if last.1 {
+                    new_side.last_mut()?.pop();
+                }
+                continue;
+            }
+            Some(_) => return None,
+        };
+
+        let content = format!("{}\n", content).into_bytes();
+        if to_old {
+            old_side.push(content.clone());
+        }
+        if to_new {
+            new_side.push(content);
+        }
+        last = (to_old, to_new);
+    }
+
+    Some((old_side, new_side))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<&[u8]> {
+        text.as_bytes().split_inclusive(|&b| b == b'\n').collect()
+    }
+
+    fn owned(text: &str) -> Lines {
+        lines(text).into_iter().map(<[u8]>::to_vec).collect()
+    }
+
+    fn header(hunk: &Hunk, old: &[&[u8]], new: &[&[u8]]) -> String {
+        format_hunk(hunk, old, new)
+            .lines()
+            .next()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn split_hunks_are_recounted_and_share_context() {
+        let old = lines("1\n2\n3\n4\n5\n6\n7\n8\n");
+        let new = lines("1\n2\nx\n3\n4\n5\nsix\n7\n8\n");
+        let hunks = build_hunks(&diff_lines(&old, &new), DEFAULT_CONTEXT);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(header(&hunks[0], &old, &new), "@@ -1,8 +1,9 @@");
+        assert_eq!(change_runs(&hunks[0].edits), [(2, 3), (6, 8)]);
+
+        let parts = split_hunk(&hunks[0]);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(header(&parts[0], &old, &new), "@@ -1,5 +1,6 @@");
+        assert_eq!(header(&parts[1], &old, &new), "@@ -3,6 +4,6 @@");
+        assert_eq!(
+            format_hunk(&parts[1], &old, &new),
+            "@@ -3,6 +4,6 @@\n 3\n 4\n 5\n-6\n+six\n 7\n 8\n"
+        );
+
+        // Either part applies on its own, both give the new file
+        let apply = |parts: &[Hunk]| {
+            let replacements = parts
+                .iter()
+                .flat_map(|part| hunk_replacements(part, &new))
+                .collect();
+            String::from_utf8(apply_replacements(&old, replacements)).unwrap()
+        };
+        assert_eq!(apply(&parts[..1]), "1\n2\nx\n3\n4\n5\n6\n7\n8\n");
+        assert_eq!(apply(&parts[1..]), "1\n2\n3\n4\n5\nsix\n7\n8\n");
+        assert_eq!(apply(&parts), "1\n2\nx\n3\n4\n5\nsix\n7\n8\n");
+    }
+
+    #[test]
+    fn a_single_run_is_not_split() {
+        let old = lines("a\nb\nc\n");
+        let new = lines("a\nB\nC\n");
+        let hunks = build_hunks(&diff_lines(&old, &new), DEFAULT_CONTEXT);
+
+        assert_eq!(change_runs(&hunks[0].edits), [(1, 5)]);
+        let parts = split_hunk(&hunks[0]);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(header(&parts[0], &old, &new), "@@ -1,3 +1,3 @@");
+    }
+
+    #[test]
+    fn edited_hunks_are_read_without_trusting_the_header() {
+        // The counts in the header are stale after the edit and ignored
+        let edited = "# Manual hunk edit mode - see bottom for a quick guide.
+@@ -1,3 +1,3 @@
+ a
+-b
++B
++extra
+
+ c
+# ---
+# Lines starting with # will be removed.
+";
+        let (old_side, new_side) = parse_edited_hunk(edited).unwrap();
+        assert_eq!(old_side, owned("a\nb\n\nc\n"));
+        assert_eq!(new_side, owned("a\nB\nextra\n\nc\n"));
+
+        assert!(parse_edited_hunk(" a\noops\n").is_none());
+        assert_eq!(
+            parse_edited_hunk("# nothing left\n"),
+            Some((vec![], vec![]))
+        );
+    }
+
+    #[test]
+    fn missing_newlines_stick_to_the_side_of_the_line_before() {
+        let edited = " a\n-b\n\\ No newline at end of file\n+b\n+c\n\\ No newline at end of file\n";
+        let (old_side, new_side) = parse_edited_hunk(edited).unwrap();
+        assert_eq!(old_side, owned("a\nb"));
+        assert_eq!(new_side, owned("a\nb\nc"));
+
+        let edited = " a\n b\n\\ No newline at end of file\n";
+        let (old_side, new_side) = parse_edited_hunk(edited).unwrap();
+        assert_eq!(old_side, owned("a\nb"));
+        assert_eq!(new_side, owned("a\nb"));
+    }
+
+    #[test]
+    fn edited_replacements_leave_out_context() {
+        let replacement = edited_replacement(10, &owned("a\nb\nc\n"), owned("a\nB\nc\n"));
+        assert_eq!((replacement.old_start, replacement.old_end), (11, 12));
+        assert_eq!(replacement.new_lines, owned("B\n"));
+
+        // An insertion replaces an empty range, a deletion has no new lines
+        let replacement = edited_replacement(0, &owned("a\nc\n"), owned("a\nb\nc\n"));
+        assert_eq!((replacement.old_start, replacement.old_end), (1, 1));
+        assert_eq!(replacement.new_lines, owned("b\n"));
+        let replacement = edited_replacement(0, &owned("a\nb\nc\n"), owned("a\nc\n"));
+        assert_eq!((replacement.old_start, replacement.old_end), (1, 2));
+        assert!(replacement.new_lines.is_empty());
+
+        // Context matching at both ends is only counted once
+        let replacement = edited_replacement(0, &owned("a\na\n"), owned("a\na\na\n"));
+        assert_eq!((replacement.old_start, replacement.old_end), (2, 2));
+        assert_eq!(replacement.new_lines, owned("a\n"));
+    }
+
+    #[test]
+    fn overlapping_replacements_are_dropped() {
+        let old = lines("a\nb\nc\nd\n");
+        let replacement = |old_start, old_end, text: &str| Replacement {
+            old_start,
+            old_end,
+            new_lines: owned(text),
+        };
+
+        let content = apply_replacements(
+            &old,
+            vec![
+                replacement(2, 4, "C\n"),
+                replacement(0, 2, "A\nB\n"),
+                replacement(1, 3, "lost\n"),
+            ],
+        );
+        assert_eq!(content, b"A\nB\nC\n");
+    }
+}
@@ -0,0 +1,288 @@
+use crate::utils::list_files_recursively;
+use std::{collections::BTreeSet, path::Path};
+
+/// File patterns given on the command line, as understood by `add`,
+/// `status` and `log`.
+///
+/// Every argument is a path relative to the top of the work tree, a
+/// directory matches everything below it and `*`, `?` and `[...]` are
+/// wildcards where `*` also matches `/`. `**/` matches any number of
+/// directories, none included. Magic in front changes an argument:
+///
+/// - `:(exclude)pat`, `:!pat` or `:^pat` leaves out what `pat` matches
+/// - `:(icase)pat` ignores case
+/// - `:(literal)pat` has no wildcards
+/// - `:(top)pat` or `:/pat` is relative to the top of the work tree
+///
+/// vit always runs from the top of the work tree, so `top` only makes the
+/// intent explicit. An empty pathspec matches every path.
+#[derive(Debug, Clone, Default)]
+pub struct Pathspec {
+    items: Vec<PathspecItem>,
+}
+
+#[derive(Debug, Clone)]
+struct PathspecItem {
+    /// The argument as given, for messages
+    original: String,
+    /// Normalised pattern without magic, empty matches everything
+    pattern: String,
+    exclude: bool,
+    icase: bool,
+    literal: bool,
+}
+
+impl Pathspec {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let items = args
+            .iter()
+            .map(|arg| PathspecItem::parse(arg))
+            .collect::<Result<_, _>>()?;
+        Ok(Pathspec { items })
+    }
+
+    /// True without any argument, everything matches then.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Whether `path`, relative to the top of the work tree, is selected:
+    /// it matches an argument, or there are only exclusions, and no
+    /// exclusion matches it.
+    pub fn matches(&self, path: &str) -> bool {
+        let mut positives = self.items.iter().filter(|item| !item.exclude).peekable();
+        let included = positives.peek().is_none() || positives.any(|item| item.matches(path));
+
+        included
+            && !self
+                .items
+                .iter()
+                .any(|item| item.exclude && item.matches(path))
+    }
+
+    /// Work tree files the pathspec selects, sorted. Only the directories
+    /// the arguments can reach are walked, ignored files are left out
+    /// unless named literally.
+    pub fn work_tree_files(&self) -> Vec<String> {
+        let mut roots: BTreeSet<String> = self
+            .items
+            .iter()
+            .filter(|item| !item.exclude)
+            .map(PathspecItem::walk_root)
+            .collect();
+        if roots.is_empty() {
+            roots.insert(String::new());
+        }
+
+        let mut files = BTreeSet::new();
+        for root in roots {
+            let path = Path::new(if root.is_empty() { "." } else { &root });
+            if path.is_file() {
+                files.insert(root);
+            } else if path.is_dir() {
+                files.extend(list_files_recursively(path));
+            }
+        }
+
+        files
+            .into_iter()
+            .filter(|file| self.matches(file))
+            .collect()
+    }
+
+    /// Arguments that select none of `paths`, exclusions and arguments
+    /// matching everything never count.
+    pub fn unmatched<'a, I>(&self, paths: I) -> Vec<&str>
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let paths: Vec<&String> = paths.into_iter().collect();
+
+        self.items
+            .iter()
+            .filter(|item| !item.exclude && !item.pattern.is_empty())
+            .filter(|item| !paths.iter().any(|path| item.matches(path)))
+            .map(|item| item.original.as_str())
+            .collect()
+    }
+}
+
+impl PathspecItem {
+    fn parse(arg: &str) -> Result<Self, String> {
+        let mut item = PathspecItem {
+            original: arg.to_string(),
+            pattern: String::new(),
+            exclude: false,
+            icase: false,
+            literal: false,
+        };
+
+        let pattern = if let Some(long) = arg.strip_prefix(":(") {
+            let Some((magic, pattern)) = long.split_once(')') else {
+                return Err(format!(
+                    "Missing ')' at the end of pathspec magic in '{}'",
+                    arg
+                ));
+            };
+            for word in magic
+                .split(',')
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+            {
+                match word {
+                    "top" | "glob" => {}
+                    "exclude" => item.exclude = true,
+                    "icase" => item.icase = true,
+                    "literal" => item.literal = true,
+                    _ => return Err(format!("Invalid pathspec magic '{}' in '{}'", word, arg)),
+                }
+            }
+            pattern
+        } else if let Some(short) = arg.strip_prefix(':') {
+            let end = short
+                .find(|c| !matches!(c, '/' | '!' | '^'))
+                .unwrap_or(short.len());
+            item.exclude = short[..end].contains(['!', '^']);
+            let rest = &short[end..];
+            rest.strip_prefix(':').unwrap_or(rest)
+        } else {
+            arg
+        };
+
+        item.pattern = normalize(pattern);
+        if item.icase {
+            item.pattern = item.pattern.to_lowercase();
+        }
+        if !item.pattern.contains(['*', '?', '[', '\\']) {
+            item.literal = true;
+        }
+        Ok(item)
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        if self.pattern.is_empty() {
+            return true;
+        }
+
+        let lowered;
+        let path = if self.icase {
+            lowered = path.to_lowercase();
+            lowered.as_str()
+        } else {
+            path
+        };
+
+        let is_prefix = path
+            .strip_prefix(self.pattern.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+        if is_prefix {
+            return true;
+        }
+
+        !self.literal && wildmatch(self.pattern.as_bytes(), path.as_bytes())
+    }
+
+    /// The deepest directory (or file) every match lies in.
+    fn walk_root(&self) -> String {
+        if self.icase {
+            return String::new();
+        }
+        if self.literal {
+            return self.pattern.clone();
+        }
+
+        // Parts before the first one with a wildcard are whole directories
+        self.pattern
+            .split('/')
+            .take_while(|part| !part.contains(['*', '?', '[', '\\']))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Drop `./` parts, empty parts and a trailing `/`, `.` becomes empty.
+fn normalize(pattern: &str) -> String {
+    pattern
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Match `text` against a glob `pattern` where `*` matches any run of
+/// characters, `/` included, and `**/` may also match nothing at all.
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    // memo[p][t] caches the outcome for pattern[p..] against text[t..]
+    let mut memo = vec![vec![None; text.len() + 1]; pattern.len() + 1];
+    wildmatch_from(pattern, text, 0, 0, &mut memo)
+}
+
+fn wildmatch_from(
+    pattern: &[u8],
+    text: &[u8],
+    p: usize,
+    t: usize,
+    memo: &mut Vec<Vec<Option<bool>>>,
+) -> bool {
+    if let Some(result) = memo[p][t] {
+        return result;
+    }
+
+    let result = match pattern.get(p) {
+        None => t == text.len(),
+        Some(b'*') => {
+            let at_dir_start = p == 0 || pattern[p - 1] == b'/';
+            (pattern[p..].starts_with(b"**/")
+                && at_dir_start
+                && wildmatch_from(pattern, text, p + 3, t, memo))
+                || wildmatch_from(pattern, text, p + 1, t, memo)
+                || (t < text.len() && wildmatch_from(pattern, text, p, t + 1, memo))
+        }
+        Some(b'?') => t < text.len() && wildmatch_from(pattern, text, p + 1, t + 1, memo),
+        Some(b'[') => match (text.get(t), match_class(pattern, p, text.get(t).copied())) {
+            (Some(_), Some((true, next))) => wildmatch_from(pattern, text, next, t + 1, memo),
+            (_, Some((false, _))) | (None, _) => false,
+            // An unclosed `[` is an ordinary character
+            (Some(&c), None) => c == b'[' && wildmatch_from(pattern, text, p + 1, t + 1, memo),
+        },
+        Some(b'\\') if p + 1 < pattern.len() => {
+            text.get(t) == Some(&pattern[p + 1])
+                && wildmatch_from(pattern, text, p + 2, t + 1, memo)
+        }
+        Some(&c) => text.get(t) == Some(&c) && wildmatch_from(pattern, text, p + 1, t + 1, memo),
+    };
+
+    memo[p][t] = Some(result);
+    result
+}
+
+/// Match `c` against the `[...]` class starting at `pattern[start]`.
+/// Returns whether it matched and where the pattern goes on, `None` when
+/// the class is not closed.
+fn match_class(pattern: &[u8], start: usize, c: Option<u8>) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(pattern.get(i), Some(b'!' | b'^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while let Some(&b) = pattern.get(i) {
+        if b == b']' && !first {
+            return Some((c.is_some() && matched != negated, i + 1));
+        }
+        first = false;
+
+        if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|&end| end != b']') {
+            let end = pattern[i + 2];
+            matched |= c.is_some_and(|c| b <= c && c <= end);
+            i += 3;
+        } else {
+            matched |= c == Some(b);
+            i += 1;
+        }
+    }
+
+    None
+}
@@ -0,0 +1,120 @@
+use crate::utils::{
+    IndexEntry, RefStore, list_loose_objects, loose_object_path, parse_commit_data, parse_tag_data,
+    parse_tree_data, read_object_by_hash,
+};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{ErrorKind, Result},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+/// Every object reachable from refs, reflogs, stash entries and the index.
+///
+/// Objects that are referenced but missing from the store are skipped, a
+/// broken history must never cause other objects to be considered garbage.
+pub fn reachable_objects(vit_dir: &Path) -> Result<HashSet<[u8; 32]>> {
+    let objects_dir = vit_dir.join("objects");
+    let mut reachable: HashSet<[u8; 32]> = HashSet::new();
+    let mut pending: Vec<[u8; 32]> = root_objects(vit_dir)?;
+
+    while let Some(hash) = pending.pop() {
+        if hash == [0u8; 32] || !reachable.insert(hash) {
+            continue;
+        }
+
+        let (object_type, content) = match read_object_by_hash(&objects_dir, &hash) {
+            Ok(object) => object,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        match object_type.as_str() {
+            "commit" => {
+                let commit = parse_commit_data(&content)?;
+                pending.push(commit.tree);
+                pending.extend(commit.parents);
+            }
+            "tree" => {
+                for entry in parse_tree_data(&content)? {
+                    pending.push(entry.sha256);
+                }
+            }
+            "tag" => pending.push(parse_tag_data(&content)?.object),
+            _ => {}
+        }
+    }
+
+    Ok(reachable)
+}
+
+/// Loose objects that nothing references, with the time they were written.
+pub fn unreachable_loose_objects(vit_dir: &Path) -> Result<Vec<([u8; 32], i64)>> {
+    let objects_dir = vit_dir.join("objects");
+    let reachable = reachable_objects(vit_dir)?;
+    let mut unreachable = Vec::new();
+
+    for hash in list_loose_objects(&objects_dir)? {
+        if reachable.contains(&hash) {
+            continue;
+        }
+
+        let modified = fs::metadata(loose_object_path(&objects_dir, &hash))?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(0);
+        unreachable.push((hash, modified));
+    }
+
+    Ok(unreachable)
+}
+
+fn root_objects(vit_dir: &Path) -> Result<Vec<[u8; 32]>> {
+    let mut roots = Vec::new();
+
+    // Packed refs count as much as loose ones, a detached HEAD is not a ref
+    let refs = RefStore::new(vit_dir);
+    roots.extend(refs.read("HEAD"));
+    roots.extend(refs.list("refs/").into_iter().map(|(_, hash)| hash));
+
+    collect_reflogs(&vit_dir.join("logs"), &mut roots)?;
+
+    let index_path = vit_dir.join("index");
+    if index_path.exists() {
+        let mut file = File::open(index_path)?;
+        while let Some(entry) = IndexEntry::read(&mut file)? {
+            roots.push(entry.sha256);
+        }
+    }
+
+    Ok(roots)
+}
+
+/// Both the old and new value of every reflog record keep objects alive.
+fn collect_reflogs(dir: &Path, roots: &mut Vec<[u8; 32]>) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_reflogs(&path, roots)?;
+            continue;
+        }
+
+        for line in fs::read_to_string(&path)?.lines() {
+            let mut parts = line.splitn(3, ' ');
+            roots.extend(parts.next().and_then(parse_hash));
+            roots.extend(parts.next().and_then(parse_hash));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_hash(value: &str) -> Option<[u8; 32]> {
+    hex::decode(value).ok()?.try_into().ok()
+}
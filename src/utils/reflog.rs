@@ -0,0 +1,131 @@
+use crate::utils::{RefStore, local_timezone, reflog_identity};
+use chrono::Local;
+use std::{
+    fs::{self, OpenOptions},
+    io::{Result, Write},
+    path::Path,
+};
+
+/// One line of `.vit/logs/<ref>`.
+#[derive(Debug, Clone)]
+pub struct ReflogEntry {
+    pub old: [u8; 32],
+    pub new: [u8; 32],
+    pub identity: String, // "Name <email>"
+    pub timestamp: i64,
+    pub timezone: String,
+    pub message: String, // e.g. "commit: Fix typo"
+}
+
+impl ReflogEntry {
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {} {} {} {} {}\n",
+            hex::encode(self.old),
+            hex::encode(self.new),
+            self.identity,
+            self.timestamp,
+            self.timezone,
+            self.message
+        )
+    }
+
+    /// Parse `old new Name <email> timestamp timezone message`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let (old, rest) = line.split_once(' ')?;
+        let (new, rest) = rest.split_once(' ')?;
+        let ident_end = rest.find('>')? + 1;
+        let (identity, rest) = rest.split_at(ident_end);
+
+        let mut fields = rest.trim_start().splitn(3, ' ');
+        let timestamp = fields.next()?.parse().ok()?;
+        let timezone = fields.next().unwrap_or("").to_string();
+        let message = fields.next().unwrap_or("").to_string();
+
+        Some(ReflogEntry {
+            old: hex::decode(old).ok()?.try_into().ok()?,
+            new: hex::decode(new).ok()?.try_into().ok()?,
+            identity: identity.to_string(),
+            timestamp,
+            timezone,
+            message,
+        })
+    }
+}
+
+/// Record that `ref_name` moved from `old` to `new`, a zero hash stands for
+/// a ref that did not exist. Only the first line of `message` is kept.
+///
+/// Updates of the branch HEAD points at are logged for `HEAD` as well.
+pub fn append_reflog(
+    vit_dir: &Path,
+    ref_name: &str,
+    old: &[u8; 32],
+    new: &[u8; 32],
+    message: &str,
+) -> Result<()> {
+    let entry = ReflogEntry {
+        old: *old,
+        new: *new,
+        identity: reflog_identity(),
+        timestamp: Local::now().timestamp(),
+        timezone: local_timezone(),
+        message: message.lines().next().unwrap_or("").to_string(),
+    };
+
+    append_reflog_entry(vit_dir, ref_name, &entry)?;
+    let head_branch = RefStore::new(vit_dir).head_branch();
+    if ref_name != "HEAD" && head_branch.as_deref() == Some(ref_name) {
+        append_reflog_entry(vit_dir, "HEAD", &entry)?;
+    }
+
+    Ok(())
+}
+
+/// Entries of a reflog, oldest first. A missing log is empty.
+pub fn read_reflog(vit_dir: &Path, ref_name: &str) -> Vec<ReflogEntry> {
+    match fs::read_to_string(vit_dir.join("logs").join(ref_name)) {
+        Ok(data) => data.lines().filter_map(ReflogEntry::parse).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Replace a reflog with `entries`, oldest first.
+pub fn write_reflog(vit_dir: &Path, ref_name: &str, entries: &[ReflogEntry]) -> Result<()> {
+    let log_path = vit_dir.join("logs").join(ref_name);
+    let data: String = entries.iter().map(ReflogEntry::to_line).collect();
+    fs::write(log_path, data)
+}
+
+/// The hash `ref_name@{n}` stands for, 0 being the latest update.
+pub fn reflog_nth(vit_dir: &Path, ref_name: &str, n: usize) -> Option<[u8; 32]> {
+    let entries = read_reflog(vit_dir, ref_name);
+    entries.iter().rev().nth(n).map(|entry| entry.new)
+}
+
+fn append_reflog_entry(vit_dir: &Path, ref_name: &str, entry: &ReflogEntry) -> Result<()> {
+    let logs_dir = vit_dir.join("logs");
+    let log_path = logs_dir.join(ref_name);
+
+    // Logs of deleted refs stay around, drop any that sit in the way of
+    // this one, such as `logs/refs/heads/a` when creating `a/b`
+    for ancestor in log_path.ancestors().skip(1) {
+        if ancestor == logs_dir {
+            break;
+        }
+        if ancestor.is_file() {
+            fs::remove_file(ancestor)?;
+        }
+    }
+    if log_path.is_dir() {
+        fs::remove_dir_all(&log_path)?;
+    }
+
+    fs::create_dir_all(log_path.parent().unwrap())?;
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)?
+        .write_all(entry.to_line().as_bytes())
+}
@@ -0,0 +1,598 @@
+use crate::utils::append_reflog;
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{Error, ErrorKind, Result, Write},
+    path::{Path, PathBuf},
+};
+
+/// Symbolic refs pointing at symbolic refs are followed this deep at most.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// Check a ref name against git's `check-ref-format` rules.
+///
+/// Components are separated by `/`, none may start with `.` or end with
+/// `.lock`. The name may not contain `..`, `@{`, `//`, control characters,
+/// spaces or any of `~ ^ : ? * [ \`, may not start or end with `/`, may not
+/// end with `.` and may not be `@` alone.
+pub fn check_ref_format(name: &str) -> bool {
+    if name.is_empty()
+        || name == "@"
+        || name.starts_with('/')
+        || name.ends_with('/')
+        || name.ends_with('.')
+        || name.contains("..")
+        || name.contains("@{")
+        || name.contains("//")
+    {
+        return false;
+    }
+
+    if name
+        .chars()
+        .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+    {
+        return false;
+    }
+
+    name.split('/')
+        .all(|component| !component.starts_with('.') && !component.ends_with(".lock"))
+}
+
+/// Branch and tag names must be valid ref names that cannot be mistaken
+/// for an option or for `HEAD`.
+pub fn is_valid_ref_short_name(name: &str) -> bool {
+    check_ref_format(name) && !name.starts_with('-') && name != "HEAD"
+}
+
+/// Full ref names are `HEAD`, names below `refs/` or upper case pseudo refs
+/// such as `ORIG_HEAD`.
+pub fn is_valid_full_ref_name(name: &str) -> bool {
+    if !check_ref_format(name) {
+        return false;
+    }
+
+    name.starts_with("refs/") || name.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+}
+
+/// Names of all files below a directory such as `refs/heads` or `logs`,
+/// relative to it and sorted, e.g. `feature/login` and `main`.
+pub fn list_ref_names(vit_dir: &Path, prefix: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    collect_ref_names(&vit_dir.join(prefix), "", &mut names);
+    names.sort();
+    names
+}
+
+/// Remove a ref or log file and the directories it leaves empty, up to
+/// `stop`.
+pub fn remove_ref_file(path: &Path, stop: &Path) {
+    fs::remove_file(path).unwrap();
+    remove_empty_parents(path, stop);
+}
+
+fn remove_empty_parents(path: &Path, stop: &Path) {
+    let mut parent = path.parent();
+    while let Some(dir) = parent {
+        if dir == stop || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+}
+
+/// All refs of a repository.
+///
+/// A ref lives either as a loose file below `.vit`, holding a hash or
+/// `ref: <target>` for symbolic refs, or as a line of `.vit/packed-refs`.
+/// Loose refs take precedence, so updates only ever write loose files and
+/// `pack_refs` moves them into the packed file later.
+///
+/// Writes take a `<ref>.lock` file first and rename it into place, an
+/// expected old value can be given to update a ref only if nobody else
+/// moved it in the meantime. Every update is recorded in the reflog.
+pub struct RefStore {
+    vit_dir: PathBuf,
+}
+
+impl RefStore {
+    pub fn new(vit_dir: &Path) -> Self {
+        RefStore {
+            vit_dir: vit_dir.to_path_buf(),
+        }
+    }
+
+    /// The target of a symbolic ref such as `HEAD`, `None` when the ref
+    /// holds a hash or does not exist.
+    pub fn read_symbolic(&self, name: &str) -> Option<String> {
+        let content = fs::read_to_string(self.vit_dir.join(name)).ok()?;
+        content
+            .trim()
+            .strip_prefix("ref: ")
+            .map(|target| target.trim().to_string())
+    }
+
+    /// Follow symbolic refs from `name` to the ref that holds a hash, which
+    /// may not exist yet, as for the branch of a new repository.
+    pub fn resolve_name(&self, name: &str) -> Result<String> {
+        let mut name = name.to_string();
+
+        for _ in 0..MAX_SYMREF_DEPTH {
+            match self.read_symbolic(&name) {
+                Some(target) => name = target,
+                None => return Ok(name),
+            }
+        }
+
+        Err(Error::other(format!(
+            "symbolic ref '{}' nests too deep",
+            name
+        )))
+    }
+
+    /// The hash a ref points at, following symbolic refs.
+    pub fn read(&self, name: &str) -> Option<[u8; 32]> {
+        let name = self.resolve_name(name).ok()?;
+
+        match fs::read_to_string(self.vit_dir.join(&name)) {
+            Ok(content) => parse_hash(content.trim()),
+            Err(_) => self.read_packed().remove(&name),
+        }
+    }
+
+    /// The branch HEAD points at, e.g. `refs/heads/main`, `None` when HEAD
+    /// is detached.
+    pub fn head_branch(&self) -> Option<String> {
+        self.read_symbolic("HEAD")
+    }
+
+    /// The short name of the branch HEAD points at, e.g. `main`.
+    pub fn current_branch(&self) -> Option<String> {
+        self.head_branch()
+            .map(|target| target.trim_start_matches("refs/heads/").to_string())
+    }
+
+    /// Refs whose full name starts with `prefix`, such as `refs/heads/`,
+    /// with the hash they hold, sorted by name. Tags are not peeled.
+    pub fn list(&self, prefix: &str) -> Vec<(String, [u8; 32])> {
+        let mut refs = self.read_packed();
+
+        for name in list_ref_names(&self.vit_dir, "refs") {
+            let name = format!("refs/{}", name);
+            if name.ends_with(".lock") {
+                continue;
+            }
+            if let Some(hash) = self.read(&name) {
+                refs.insert(name, hash);
+            }
+        }
+
+        refs.into_iter()
+            .filter(|(name, _)| name.starts_with(prefix))
+            .collect()
+    }
+
+    /// An existing ref that would clash with creating `name`, as
+    /// `refs/heads/a` and `refs/heads/a/b` cannot both exist.
+    pub fn conflicting(&self, name: &str) -> Option<String> {
+        let nested = format!("{}/", name);
+
+        self.list("refs/")
+            .into_iter()
+            .map(|(other, _)| other)
+            .find(|other| other.starts_with(&nested) || name.starts_with(&format!("{}/", other)))
+    }
+
+    /// Point `name`, or the ref it symbolically refers to, at `new`.
+    ///
+    /// With `expected`, the update only happens while the ref still holds
+    /// that value, a zero hash expecting the ref not to exist yet.
+    pub fn update(
+        &self,
+        name: &str,
+        new: &[u8; 32],
+        expected: Option<[u8; 32]>,
+        message: &str,
+    ) -> Result<()> {
+        let name = self.resolve_name(name)?;
+        if !is_valid_full_ref_name(&name) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("'{}' is not a valid ref name", name),
+            ));
+        }
+
+        let lock = RefLock::acquire(&self.vit_dir.join(&name))?;
+        let old = self.read(&name).unwrap_or([0u8; 32]);
+        check_expected(&name, &old, expected)?;
+
+        lock.commit(format!("{}\n", hex::encode(new)).as_bytes())?;
+        append_reflog(&self.vit_dir, &name, &old, new, message)
+    }
+
+    /// Remove `name`, loose and packed. Its reflog keeps a record pointing
+    /// back at the old value so the commits can still be found.
+    pub fn delete(&self, name: &str, expected: Option<[u8; 32]>, message: &str) -> Result<()> {
+        let name = self.resolve_name(name)?;
+        let not_found = || {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("ref '{}' does not exist", name),
+            )
+        };
+        if self.read(&name).is_none() {
+            return Err(not_found());
+        }
+
+        // Check the old value under the lock, like `update`
+        let path = self.vit_dir.join(&name);
+        let lock = RefLock::acquire(&path)?;
+        let old = self.read(&name).ok_or_else(not_found)?;
+        check_expected(&name, &old, expected)?;
+
+        if self.read_packed().contains_key(&name) {
+            let packed_lock = RefLock::acquire(&self.vit_dir.join("packed-refs"))?;
+            let mut packed = self.read_packed();
+            packed.remove(&name);
+            packed_lock.commit(&format_packed_refs(&packed))?;
+        }
+
+        if path.is_file() {
+            fs::remove_file(&path)?;
+        }
+        drop(lock);
+        remove_empty_parents(&path, &self.namespace_dir(&name));
+
+        append_reflog(&self.vit_dir, &name, &old, &[0u8; 32], message)
+    }
+
+    /// Move `old` to `new` together with its reflog, replacing whatever
+    /// `new` held. HEAD follows when it points at `old`.
+    pub fn rename(&self, old: &str, new: &str, message: &str) -> Result<()> {
+        let Some(tip) = self.read(old) else {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("ref '{}' does not exist", old),
+            ));
+        };
+        if !is_valid_full_ref_name(new) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("'{}' is not a valid ref name", new),
+            ));
+        }
+
+        let logs_dir = self.vit_dir.join("logs");
+        let old_log = logs_dir.join(old);
+        let log = fs::read(&old_log).ok();
+
+        // Drop the old ref first, `a` may be renamed to `a/b`
+        let old_path = self.vit_dir.join(old);
+        let lock = RefLock::acquire(&old_path)?;
+        if self.read_packed().contains_key(old) {
+            let packed_lock = RefLock::acquire(&self.vit_dir.join("packed-refs"))?;
+            let mut packed = self.read_packed();
+            packed.remove(old);
+            packed_lock.commit(&format_packed_refs(&packed))?;
+        }
+        if old_path.is_file() {
+            fs::remove_file(&old_path)?;
+        }
+        drop(lock);
+        remove_empty_parents(&old_path, &self.namespace_dir(old));
+
+        if old_log.is_file() {
+            remove_ref_file(&old_log, &logs_dir);
+        }
+
+        // A stale log of a deleted `new` is replaced by the moved one
+        let new_log = logs_dir.join(new);
+        if new_log.is_file() {
+            fs::remove_file(&new_log)?;
+        }
+
+        let lock = RefLock::acquire(&self.vit_dir.join(new))?;
+        lock.commit(format!("{}\n", hex::encode(tip)).as_bytes())?;
+        append_reflog(&self.vit_dir, new, &tip, &tip, message)?;
+        if let Some(mut log) = log {
+            log.extend(fs::read(&new_log)?);
+            fs::write(&new_log, log)?;
+        }
+
+        if self.head_branch().as_deref() == Some(old) {
+            let lock = RefLock::acquire(&self.vit_dir.join("HEAD"))?;
+            lock.commit(format!("ref: {}\n", new).as_bytes())?;
+            append_reflog(&self.vit_dir, "HEAD", &tip, &tip, message)?;
+        }
+        Ok(())
+    }
+
+    /// Make `name` a symbolic ref to `target`, e.g. HEAD to a branch. Moves
+    /// of HEAD are logged.
+    pub fn set_symbolic(&self, name: &str, target: &str, message: &str) -> Result<()> {
+        if !is_valid_full_ref_name(name) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("'{}' is not a valid ref name", name),
+            ));
+        }
+        if !target.starts_with("refs/") || !check_ref_format(target) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("refusing to point {} outside of refs/: {}", name, target),
+            ));
+        }
+
+        // Read the old value under the lock so the reflog cannot miss a
+        // concurrent update
+        let lock = RefLock::acquire(&self.vit_dir.join(name))?;
+        let old = self.read(name).unwrap_or([0u8; 32]);
+        lock.commit(format!("ref: {}\n", target).as_bytes())?;
+
+        // Moving between unborn branches changes nothing worth logging
+        let new = self.read(target).unwrap_or([0u8; 32]);
+        if name == "HEAD" && (old != [0u8; 32] || new != [0u8; 32]) {
+            append_reflog(&self.vit_dir, "HEAD", &old, &new, message)?;
+        }
+        Ok(())
+    }
+
+    /// Remove a symbolic ref, the ref it points at is left alone.
+    pub fn delete_symbolic(&self, name: &str) -> Result<()> {
+        if self.read_symbolic(name).is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("'{}' is not a symbolic ref", name),
+            ));
+        }
+        if name == "HEAD" {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "deleting HEAD is not allowed",
+            ));
+        }
+
+        fs::remove_file(self.vit_dir.join(name))
+    }
+
+    /// Move every loose ref below `refs/` into `packed-refs` and return how
+    /// many were packed. Symbolic refs stay loose, as do refs another
+    /// process holds the lock of.
+    pub fn pack_refs(&self) -> Result<usize> {
+        let packed_lock = RefLock::acquire(&self.vit_dir.join("packed-refs"))?;
+        let mut packed = self.read_packed();
+        let mut loose = Vec::new();
+
+        for name in list_ref_names(&self.vit_dir, "refs") {
+            let name = format!("refs/{}", name);
+            if name.ends_with(".lock") {
+                continue;
+            }
+
+            // Each ref stays locked until its loose file is gone
+            let path = self.vit_dir.join(&name);
+            let lock = match RefLock::acquire(&path) {
+                Ok(lock) => lock,
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            };
+            let content = fs::read_to_string(&path)?;
+            if let Some(hash) = parse_hash(content.trim()) {
+                packed.insert(name.clone(), hash);
+                loose.push((name, lock));
+            }
+        }
+
+        packed_lock.commit(&format_packed_refs(&packed))?;
+
+        let count = loose.len();
+        for (name, lock) in loose {
+            let path = self.vit_dir.join(&name);
+            fs::remove_file(&path)?;
+            drop(lock);
+            remove_empty_parents(&path, &self.namespace_dir(&name));
+        }
+
+        Ok(count)
+    }
+
+    /// Directories such as `refs/heads` are kept even when they become empty.
+    fn namespace_dir(&self, name: &str) -> PathBuf {
+        let namespace: Vec<&str> = name.splitn(3, '/').take(2).collect();
+        self.vit_dir.join(namespace.join("/"))
+    }
+
+    fn read_packed(&self) -> BTreeMap<String, [u8; 32]> {
+        let Ok(content) = fs::read_to_string(self.vit_dir.join("packed-refs")) else {
+            return BTreeMap::new();
+        };
+
+        content
+            .lines()
+            // `#` starts the header, `^` holds the commit of the tag above
+            .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+            .filter_map(|line| {
+                let (hash, name) = line.split_once(' ')?;
+                Some((name.trim().to_string(), parse_hash(hash)?))
+            })
+            .collect()
+    }
+}
+
+/// A `<path>.lock` file, only one writer can hold it at a time. Dropping it
+/// without committing leaves the ref untouched.
+struct RefLock {
+    path: PathBuf,
+    lock_path: PathBuf,
+    committed: bool,
+}
+
+impl RefLock {
+    fn acquire(path: &Path) -> Result<Self> {
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        let mut lock_name = path.file_name().unwrap().to_os_string();
+        lock_name.push(".lock");
+        let lock_path = path.with_file_name(lock_name);
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(_) => Ok(RefLock {
+                path: path.to_path_buf(),
+                lock_path,
+                committed: false,
+            }),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "Unable to create '{}': File exists, another vit process seems to be running",
+                    lock_path.display()
+                ),
+            )),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Write `content` into the lock file and move it over the ref.
+    fn commit(mut self, content: &[u8]) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&self.lock_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+
+        fs::rename(&self.lock_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for RefLock {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+fn check_expected(name: &str, old: &[u8; 32], expected: Option<[u8; 32]>) -> Result<()> {
+    match expected {
+        Some(expected) if expected != *old => Err(Error::other(format!(
+            "cannot lock ref '{}': is at {} but expected {}",
+            name,
+            hex::encode(old),
+            hex::encode(expected)
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn format_packed_refs(refs: &BTreeMap<String, [u8; 32]>) -> Vec<u8> {
+    let mut content = String::from("# pack-refs with: sorted\n");
+    for (name, hash) in refs {
+        content.push_str(&format!("{} {}\n", hex::encode(hash), name));
+    }
+    content.into_bytes()
+}
+
+fn parse_hash(value: &str) -> Option<[u8; 32]> {
+    hex::decode(value).ok()?.try_into().ok()
+}
+
+fn collect_ref_names(dir: &Path, prefix: &str, names: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.path().is_dir() {
+            collect_ref_names(&entry.path(), &format!("{}/", name), names);
+        } else {
+            names.push(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn branch_and_tag_names_follow_check_ref_format() {
+        let valid = [
+            "main",
+            "feature/login",
+            "release/v1.2.3",
+            "a/b/c",
+            "fix-123",
+            "user@host",
+            "with.dot",
+        ];
+        let invalid = [
+            "",
+            "@",
+            "a..b",
+            "..",
+            "a@{1}",
+            "@{",
+            "topic.lock",
+            "feature/wip.lock",
+            "-leading-dash",
+            "--force",
+            "a//b",
+            "/leading",
+            "trailing/",
+            "trailing.",
+            ".hidden",
+            "feature/.hidden",
+            "tab\there",
+            "bell\x07",
+            "del\x7f",
+            "with space",
+            "a~1",
+            "a^2",
+            "a:b",
+            "what?",
+            "glob*",
+            "[bracket",
+            "back\\slash",
+            "HEAD",
+        ];
+
+        for name in valid {
+            assert!(is_valid_ref_short_name(name), "{:?} should be valid", name);
+        }
+        for name in invalid {
+            assert!(
+                !is_valid_ref_short_name(name),
+                "{:?} should be invalid",
+                name
+            );
+        }
+
+        // Leading dashes and HEAD are only refused as short names
+        assert!(check_ref_format("-"));
+        assert!(check_ref_format("-leading-dash"));
+        assert!(check_ref_format("HEAD"));
+        assert!(!is_valid_ref_short_name("-"));
+    }
+
+    #[test]
+    fn full_ref_names_live_below_refs_or_are_pseudo_refs() {
+        for name in ["HEAD", "ORIG_HEAD", "refs/heads/main", "refs/tags/v1.0"] {
+            assert!(is_valid_full_ref_name(name), "{:?} should be valid", name);
+        }
+        for name in ["main", "heads/main", "refs/heads/a..b", "refs/heads/"] {
+            assert!(
+                !is_valid_full_ref_name(name),
+                "{:?} should be invalid",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::utils::{
+    RefStore, list_loose_objects, list_packed_objects, loose_object_path, parse_tag_data,
+    read_commit_file, read_object_by_hash, reflog_nth,
+};
+use std::{
+    io::{Error, ErrorKind, Result},
+    path::Path,
+};
+
+/// Shortest abbreviated hash accepted on the command line.
+const MIN_ABBREV: usize = 4;
+
+/// Resolve a revision to a commit hash.
+///
+/// Accepts `HEAD`, tag and branch names, full ref paths such as `refs/stash`,
+/// full or abbreviated hashes and reflog entries such as `main@{2}`,
+/// followed by any number of `~n`, `^` and `^n` suffixes to walk to
+/// ancestors.
+pub fn resolve_revision(vit_dir: &Path, revision: &str) -> Result<[u8; 32]> {
+    let base_end = revision.find(['~', '^']).unwrap_or(revision.len());
+    let (name, mut suffix) = revision.split_at(base_end);
+
+    let objects_dir = vit_dir.join("objects");
+    let mut hash = resolve_name(vit_dir, name)
+        .ok_or_else(|| not_found(&format!("unknown revision '{}'", revision)))?;
+    hash = peel_to_commit(&objects_dir, &hash)?;
+    while let Some(operator) = suffix.chars().next() {
+        suffix = &suffix[1..];
+        let digits = suffix.chars().take_while(char::is_ascii_digit).count();
+        let count: usize = match &suffix[..digits] {
+            "" => 1,
+            value => value
+                .parse()
+                .map_err(|_| not_found(&format!("unknown revision '{}'", revision)))?,
+        };
+        suffix = &suffix[digits..];
+
+        // `~n` follows n first parents, `^n` picks the nth parent
+        let (steps, parent) = match operator {
+            '~' => (count, 0),
+            '^' if count == 0 => (0, 0),
+            '^' => (1, count - 1),
+            _ => return Err(not_found(&format!("unknown revision '{}'", revision))),
+        };
+
+        for _ in 0..steps {
+            let commit = read_commit_file(&loose_object_path(&objects_dir, &hash))?;
+            hash = *commit
+                .parents
+                .get(parent)
+                .ok_or_else(|| not_found(&format!("unknown revision '{}'", revision)))?;
+        }
+    }
+
+    Ok(hash)
+}
+
+/// Every ref below `refs/` with the commit it points at, sorted by name.
+/// Annotated tags are peeled to the commit they tag.
+pub fn list_refs(vit_dir: &Path) -> Vec<(String, [u8; 32])> {
+    let objects_dir = vit_dir.join("objects");
+    let mut refs = RefStore::new(vit_dir).list("refs/");
+
+    for (_, hash) in refs.iter_mut() {
+        if let Ok(commit) = peel_to_commit(&objects_dir, hash) {
+            *hash = commit;
+        }
+    }
+
+    refs
+}
+
+/// Follow tag objects until something that is not a tag is reached.
+pub fn peel_to_commit(objects_dir: &Path, hash: &[u8; 32]) -> Result<[u8; 32]> {
+    let mut hash = *hash;
+
+    loop {
+        let (object_type, content) = read_object_by_hash(objects_dir, &hash)?;
+        if object_type != "tag" {
+            return Ok(hash);
+        }
+        hash = parse_tag_data(&content)?.object;
+    }
+}
+
+/// The full name of the ref a short name such as `main` or `stash` stands
+/// for, refs without a file but with a reflog count as well.
+pub fn expand_ref_name(vit_dir: &Path, name: &str) -> Option<String> {
+    let name = if name.is_empty() || name == "@" {
+        "HEAD"
+    } else {
+        name
+    };
+
+    let store = RefStore::new(vit_dir);
+    ref_candidates(name).into_iter().find(|candidate| {
+        store.read(candidate).is_some() || vit_dir.join("logs").join(candidate).is_file()
+    })
+}
+
+fn resolve_name(vit_dir: &Path, name: &str) -> Option<[u8; 32]> {
+    // `<ref>@{n}` is the value the ref had n updates ago
+    if let Some((base, rest)) = name.split_once("@{") {
+        let n: usize = rest.strip_suffix('}')?.parse().ok()?;
+        let ref_name = expand_ref_name(vit_dir, base)?;
+        return reflog_nth(vit_dir, &ref_name, n);
+    }
+
+    let name = if name.is_empty() || name == "@" {
+        "HEAD"
+    } else {
+        name
+    };
+
+    let store = RefStore::new(vit_dir);
+    for candidate in &ref_candidates(name) {
+        if let Some(hash) = store.read(candidate) {
+            return Some(hash);
+        }
+    }
+
+    resolve_abbreviated_hash(&vit_dir.join("objects"), name)
+}
+
+/// Where a short ref name is looked up, in order.
+fn ref_candidates(name: &str) -> [String; 4] {
+    [
+        name.to_string(),
+        format!("refs/{}", name),
+        format!("refs/tags/{}", name),
+        format!("refs/heads/{}", name),
+    ]
+}
+
+/// Expand a hash prefix, it must match exactly one object.
+fn resolve_abbreviated_hash(objects_dir: &Path, prefix: &str) -> Option<[u8; 32]> {
+    if prefix.len() < MIN_ABBREV
+        || prefix.len() > 64
+        || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return None;
+    }
+
+    let prefix = prefix.to_lowercase();
+    let mut matches = list_loose_objects(objects_dir).ok()?;
+    matches.extend(list_packed_objects(objects_dir).ok()?);
+    matches.retain(|hash| hex::encode(hash).starts_with(&prefix));
+    matches.sort();
+    matches.dedup();
+
+    match matches.as_slice() {
+        [hash] => Some(*hash),
+        _ => None,
+    }
+}
+
+fn not_found(message: &str) -> Error {
+    Error::new(ErrorKind::NotFound, message)
+}
@@ -0,0 +1,95 @@
+use std::io;
+
+#[derive(Debug)]
+pub struct TagEntry {
+    pub object: [u8; 32],    // SHA-256 of the tagged object
+    pub object_type: String, // "commit", "tree", "blob" or "tag"
+    pub tag: String,         // Tag name, e.g. "v1.0"
+    pub tagger: String,      // "Name <email>"
+    pub timestamp: i64,      // UNIX timestamp
+    pub timezone: String,    // e.g., "+0530"
+    pub message: String,     // Tag message
+}
+
+impl TagEntry {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut content = Vec::new();
+
+        content.extend_from_slice(b"object ");
+        content.extend_from_slice(hex::encode(self.object).as_bytes());
+        content.push(b'\n');
+
+        content.extend_from_slice(b"type ");
+        content.extend_from_slice(self.object_type.as_bytes());
+        content.push(b'\n');
+
+        content.extend_from_slice(b"tag ");
+        content.extend_from_slice(self.tag.as_bytes());
+        content.push(b'\n');
+
+        content.extend_from_slice(b"tagger ");
+        content.extend_from_slice(self.tagger.as_bytes());
+        content.push(b' ');
+        content.extend_from_slice(self.timestamp.to_string().as_bytes());
+        content.push(b' ');
+        content.extend_from_slice(self.timezone.as_bytes());
+        content.push(b'\n');
+
+        content.push(b'\n');
+        content.extend_from_slice(self.message.as_bytes());
+
+        content
+    }
+}
+
+/// Parse the content of a tag object, without its header.
+pub fn parse_tag_data(content: &[u8]) -> io::Result<TagEntry> {
+    let content_str = String::from_utf8_lossy(content).into_owned();
+    let mut lines = content_str.lines();
+
+    let mut object = None;
+    let mut object_type = String::new();
+    let mut tag = String::new();
+    let mut tagger = String::new();
+    let mut timestamp = 0;
+    let mut timezone = String::new();
+
+    for line in lines.by_ref() {
+        if let Some(hash) = line.strip_prefix("object ") {
+            object = hex::decode(hash)
+                .ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok());
+        } else if let Some(value) = line.strip_prefix("type ") {
+            object_type = value.to_string();
+        } else if let Some(value) = line.strip_prefix("tag ") {
+            tag = value.to_string();
+        } else if let Some(tagger_info) = line.strip_prefix("tagger ") {
+            let ident_end = tagger_info.rfind('>').map_or(0, |end| end + 1);
+            let (ident, rest) = tagger_info.split_at(ident_end);
+            let mut fields = rest.split_whitespace();
+
+            tagger = ident.trim().to_string();
+            timestamp = fields
+                .next()
+                .and_then(|time| time.parse().ok())
+                .unwrap_or(0);
+            timezone = fields.next().unwrap_or("").to_string();
+        } else if line.is_empty() {
+            break; // message follows after this
+        }
+    }
+
+    let object =
+        object.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "tag without object"))?;
+    let message: String = lines.collect::<Vec<_>>().join("\n");
+
+    Ok(TagEntry {
+        object,
+        object_type,
+        tag,
+        tagger,
+        timestamp,
+        timezone,
+        message,
+    })
+}
@@ -0,0 +1,180 @@
+use crate::utils::{CommitEntry, loose_object_path, read_commit_file};
+use std::{
+    collections::{BinaryHeap, HashMap, HashSet},
+    io::Result,
+    path::{Path, PathBuf},
+};
+
+/// Walks commits newest first, following every parent.
+///
+/// Start points are added with `push`, commits reachable from a `hide`
+/// point are skipped together with all of their ancestors. Commits are
+/// read lazily so a caller that stops early never touches the rest of the
+/// history.
+pub struct CommitWalker {
+    objects_dir: PathBuf,
+    queue: BinaryHeap<(i64, u64, [u8; 32])>,
+    commits: HashMap<[u8; 32], CommitEntry>,
+    seen: HashSet<[u8; 32]>,
+    hidden: HashSet<[u8; 32]>,
+    sequence: u64,
+}
+
+impl CommitWalker {
+    pub fn new(objects_dir: &Path) -> Self {
+        CommitWalker {
+            objects_dir: objects_dir.to_path_buf(),
+            queue: BinaryHeap::new(),
+            commits: HashMap::new(),
+            seen: HashSet::new(),
+            hidden: HashSet::new(),
+            sequence: 0,
+        }
+    }
+
+    pub fn push(&mut self, hash: [u8; 32]) -> Result<()> {
+        if !self.seen.insert(hash) {
+            return Ok(());
+        }
+
+        let commit = read_commit_file(&loose_object_path(&self.objects_dir, &hash))?;
+
+        // Newer commits by committer date first, ties keep the order they were queued in
+        self.sequence += 1;
+        self.queue
+            .push((commit.committer_timestamp, u64::MAX - self.sequence, hash));
+        self.commits.insert(hash, commit);
+        Ok(())
+    }
+
+    /// Exclude `hash` and everything reachable from it.
+    pub fn hide(&mut self, hash: [u8; 32]) -> Result<()> {
+        let ancestors = ancestors(&self.objects_dir, hash)?;
+        self.hidden.extend(ancestors);
+        Ok(())
+    }
+
+    /// Exclude exactly the given commits, without walking their history.
+    pub fn hide_commits(&mut self, hashes: impl IntoIterator<Item = [u8; 32]>) {
+        self.hidden.extend(hashes);
+    }
+}
+
+impl Iterator for CommitWalker {
+    type Item = Result<([u8; 32], CommitEntry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((_, _, hash)) = self.queue.pop() {
+            let commit = self.commits.remove(&hash).unwrap();
+
+            // Ancestors of a hidden commit are hidden as well
+            if self.hidden.contains(&hash) {
+                continue;
+            }
+
+            for parent in &commit.parents {
+                if let Err(err) = self.push(*parent) {
+                    return Some(Err(err));
+                }
+            }
+
+            return Some(Ok((hash, commit)));
+        }
+
+        None
+    }
+}
+
+/// Every commit reachable from `hash`, including itself.
+pub fn ancestors(objects_dir: &Path, hash: [u8; 32]) -> Result<HashSet<[u8; 32]>> {
+    let mut found = HashSet::new();
+    let mut pending = vec![hash];
+
+    while let Some(hash) = pending.pop() {
+        if !found.insert(hash) {
+            continue;
+        }
+
+        let commit = read_commit_file(&loose_object_path(objects_dir, &hash))?;
+        pending.extend(commit.parents);
+    }
+
+    Ok(found)
+}
+
+/// Orders accepted by `sort_commits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Newest commit first among those whose children were all shown
+    Date,
+    /// Like `Date`, but a line of history is shown in one go before
+    /// switching to another branch
+    Topo,
+}
+
+/// Reorder commits so no commit comes before any of its children.
+pub fn sort_commits(
+    commits: Vec<([u8; 32], CommitEntry)>,
+    order: SortOrder,
+) -> Vec<([u8; 32], CommitEntry)> {
+    let mut children: HashMap<[u8; 32], usize> = HashMap::new();
+    for (_, commit) in &commits {
+        for parent in &commit.parents {
+            *children.entry(*parent).or_default() += 1;
+        }
+    }
+
+    let positions: HashMap<[u8; 32], usize> = commits
+        .iter()
+        .enumerate()
+        .map(|(i, (hash, _))| (*hash, i))
+        .collect();
+    let mut entries: Vec<Option<([u8; 32], CommitEntry)>> = commits.into_iter().map(Some).collect();
+
+    // Commits without children among the set are ready right away
+    let mut ready: BinaryHeap<(i64, u64, usize)> = BinaryHeap::new();
+    let mut stack: Vec<usize> = Vec::new();
+    for (i, entry) in entries.iter().enumerate().rev() {
+        let (hash, commit) = entry.as_ref().unwrap();
+        if !children.contains_key(hash) {
+            ready.push((commit.committer_timestamp, u64::MAX - i as u64, i));
+            stack.push(i);
+        }
+    }
+
+    let mut sorted = Vec::with_capacity(entries.len());
+    loop {
+        let next = match order {
+            SortOrder::Date => ready.pop().map(|(_, _, i)| i),
+            SortOrder::Topo => stack.pop(),
+        };
+        let Some(i) = next else {
+            break;
+        };
+
+        let (hash, commit) = entries[i].take().unwrap();
+
+        // Push in reverse so the first parent is the next one popped
+        for parent in commit.parents.iter().rev() {
+            let Some(&parent_index) = positions.get(parent) else {
+                continue;
+            };
+            let remaining = children.get_mut(parent).unwrap();
+            *remaining -= 1;
+
+            if *remaining == 0 {
+                let parent_commit = entries[parent_index].as_ref().unwrap();
+                ready.push((
+                    parent_commit.1.committer_timestamp,
+                    u64::MAX - parent_index as u64,
+                    parent_index,
+                ));
+                stack.push(parent_index);
+            }
+        }
+
+        sorted.push((hash, commit));
+    }
+
+    sorted
+}
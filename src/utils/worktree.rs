@@ -0,0 +1,76 @@
+use crate::utils::{FileStatus, IndexEntry, TreeEntry, loose_object_path, write_blob_to_file};
+use std::{collections::BTreeMap, fs, io::Result, path::Path};
+
+/// Move the work tree from the content of `from` to the content of `to`.
+///
+/// Both maps come from `flatten_tree`. Files only in `from` are removed
+/// together with directories left empty, files whose blob differs are
+/// written from the object store, everything else is left untouched.
+pub fn update_work_tree(
+    objects_dir: &Path,
+    from: &BTreeMap<String, TreeEntry>,
+    to: &BTreeMap<String, TreeEntry>,
+) -> Result<()> {
+    for path in from.keys() {
+        if !to.contains_key(path) {
+            remove_work_tree_file(path)?;
+        }
+    }
+
+    for (path, entry) in to {
+        if from.get(path).map(|e| e.sha256) != Some(entry.sha256) {
+            write_work_tree_file(objects_dir, path, &entry.sha256)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a blob to a work tree path, creating missing directories.
+pub fn write_work_tree_file(objects_dir: &Path, path: &str, sha256: &[u8; 32]) -> Result<()> {
+    let file_path = Path::new(path);
+    if let Some(parent) = file_path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    write_blob_to_file(&loose_object_path(objects_dir, sha256), file_path)?;
+    Ok(())
+}
+
+/// Remove a work tree file and any parent directories it leaves empty.
+pub fn remove_work_tree_file(path: &str) -> Result<()> {
+    let file_path = Path::new(path);
+    if file_path.exists() {
+        fs::remove_file(file_path)?;
+    }
+
+    let mut parent = file_path.parent();
+    while let Some(dir) = parent {
+        if dir.as_os_str().is_empty() || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+
+    Ok(())
+}
+
+/// Build a clean index matching `tree`, with stat data from the work tree.
+pub fn index_from_tree(tree: &BTreeMap<String, TreeEntry>) -> Vec<IndexEntry> {
+    let mut entries = Vec::with_capacity(tree.len());
+
+    for (path, tree_entry) in tree {
+        let Ok(metadata) = fs::metadata(path) else {
+            continue;
+        };
+
+        let mut entry = IndexEntry::from_metadata(path, &metadata, tree_entry.sha256);
+        entry.mode = tree_entry.mode.parse().unwrap_or(0o100644); // Trees store the mode in decimal
+        entry.status = FileStatus::Unchanged;
+        entries.push(entry);
+    }
+
+    entries
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::*;
+use std::{collections::BTreeMap, fs, path::Path};
+use version_it::utils::{flatten_tree, parse_commit_data, read_object_by_hash};
+
+/// Path and blob hash of every file in the tree of `rev_name`.
+fn committed_blobs(dir: &Path, rev_name: &str) -> BTreeMap<String, [u8; 32]> {
+    let objects_dir = dir.join("work/.vit/objects");
+    let commit_hash: [u8; 32] = hex::decode(rev(dir, rev_name)).unwrap().try_into().unwrap();
+    let (_, data) = read_object_by_hash(&objects_dir, &commit_hash).unwrap();
+    let commit = parse_commit_data(&data).unwrap();
+
+    flatten_tree(&objects_dir, &commit.tree)
+        .into_iter()
+        .map(|(path, entry)| (path, entry.sha256))
+        .collect()
+}
+
+#[test]
+fn parallel_add_stages_every_file_with_its_blob() {
+    let dir = temp_repo("add-parallel");
+
+    let mut expected = BTreeMap::new();
+    for i in 0..200 {
+        let name = format!("dir_{}/sub_{}/file_{}.txt", i % 7, i % 3, i);
+        let content = format!("file {}\n", i).repeat(i % 13 + 1);
+        write_file(&dir, &name, &content);
+        expected.insert(name, blob_hash(&content));
+    }
+
+    vit(&dir, &["add", "."]);
+    vit(&dir, &["commit", "-m", "many files"]);
+
+    assert_eq!(committed_blobs(&dir, "HEAD"), expected);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unreadable_files_are_reported_and_the_rest_is_staged() {
+    let dir = temp_repo("add-unreadable");
+
+    write_file(&dir, "a.txt", "first\n");
+    write_file(&dir, "b.txt", "first\n");
+    write_file(&dir, "c", "first\n");
+    vit(&dir, &["add", "."]);
+    vit(&dir, &["commit", "-m", "first"]);
+
+    // A directory where a tracked file was cannot be read as a file
+    fs::remove_file(dir.join("work/c")).unwrap();
+    fs::create_dir(dir.join("work/c")).unwrap();
+    write_file(&dir, "a.txt", "second\n");
+    write_file(&dir, "b.txt", "second\n");
+
+    let output = vit(&dir, &["commit", "-a", "-m", "second"]);
+    assert!(stderr(output).contains("Unable to add c:"));
+
+    let blobs = committed_blobs(&dir, "HEAD");
+    assert_eq!(blobs["a.txt"], blob_hash("second\n"));
+    assert_eq!(blobs["b.txt"], blob_hash("second\n"));
+    assert_eq!(blobs["c"], blob_hash("first\n"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
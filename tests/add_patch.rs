@@ -0,0 +1,99 @@
+mod common;
+
+use common::*;
+use std::{fs, os::unix::fs::PermissionsExt};
+
+fn numbered_lines(replace: &[(usize, &str)]) -> String {
+    (1..=20)
+        .map(|n| {
+            let line = replace
+                .iter()
+                .find(|(at, _)| *at == n)
+                .map_or(n.to_string(), |(_, text)| text.to_string());
+            format!("{}\n", line)
+        })
+        .collect()
+}
+
+#[test]
+fn chosen_hunks_are_staged_and_the_rest_stays_in_the_work_tree() {
+    let dir = temp_repo("add-patch");
+
+    write_file(&dir, "a.txt", &numbered_lines(&[]));
+    vit(&dir, &["add", "a.txt"]);
+    vit(&dir, &["commit", "-m", "first"]);
+
+    let edited = numbered_lines(&[(2, "two"), (18, "eighteen")]);
+    write_file(&dir, "a.txt", &edited);
+    let prompts = stdout(vit_with_input(&dir, &["add", "-p"], "n\ny\n"));
+    assert!(prompts.contains("(1/2) Stage this hunk"), "{}", prompts);
+    assert!(prompts.contains("+eighteen"), "{}", prompts);
+
+    vit(&dir, &["commit", "-m", "second"]);
+    let patch = stdout(vit(&dir, &["log", "-p", "-n", "1"]));
+    assert!(patch.contains("+eighteen"), "{}", patch);
+    assert!(!patch.contains("+two"), "{}", patch);
+
+    // The unstaged hunk is still in the work tree and shows as modified
+    assert_eq!(fs::read_to_string(dir.join("work/a.txt")).unwrap(), edited);
+    let status = stdout(vit(&dir, &["status"]));
+    assert!(status.contains("modified: a.txt"), "{}", status);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn hunks_can_be_split_and_deletions_staged() {
+    let dir = temp_repo("add-patch-split");
+
+    write_file(&dir, "a.txt", &numbered_lines(&[]));
+    write_file(&dir, "b.txt", "gone\n");
+    vit(&dir, &["add", "."]);
+    vit(&dir, &["commit", "-m", "first"]);
+
+    write_file(&dir, "a.txt", &numbered_lines(&[(5, "five"), (9, "nine")]));
+    fs::remove_file(dir.join("work/b.txt")).unwrap();
+    let prompts = stdout(vit_with_input(&dir, &["add", "-p"], "s\nn\ny\ny\n"));
+    assert!(prompts.contains("Split into 2 hunks."), "{}", prompts);
+    assert!(prompts.contains("Stage deletion"), "{}", prompts);
+
+    vit(&dir, &["commit", "-m", "second"]);
+    let patch = stdout(vit(&dir, &["log", "-p", "-n", "1"]));
+    assert!(patch.contains("+nine"), "{}", patch);
+    assert!(!patch.contains("+five"), "{}", patch);
+    assert!(patch.contains("deleted file"), "{}", patch);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_failing_file_is_skipped_and_the_rest_still_staged() {
+    let dir = temp_repo("add-patch-failure");
+
+    write_file(&dir, "a.txt", "a\n");
+    write_file(&dir, "b.txt", "b\n");
+    vit(&dir, &["add", "."]);
+    vit(&dir, &["commit", "-m", "first"]);
+
+    // The edited hunk of a.txt cannot be read back
+    let editor = dir.join("editor.sh");
+    fs::write(&editor, "#!/bin/sh\nprintf '\\377\\n' > \"$1\"\n").unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+    vit(
+        &dir,
+        &["config", "set", "core.editor", editor.to_str().unwrap()],
+    );
+
+    write_file(&dir, "a.txt", "A\n");
+    write_file(&dir, "b.txt", "B\n");
+    let output = vit_with_input(&dir, &["add", "-p"], "e\ny\n");
+    assert!(stderr(output).contains("Unable to stage a.txt"));
+
+    let status = stdout(vit(&dir, &["status"]));
+    let (staged, unstaged) = status.split_once("Changes not staged").unwrap();
+    assert!(staged.contains("modified: b.txt"), "{}", status);
+    assert!(!staged.contains("a.txt"), "{}", status);
+    assert!(unstaged.contains("modified: a.txt"), "{}", status);
+
+    fs::remove_dir_all(dir).unwrap();
+}
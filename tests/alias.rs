@@ -0,0 +1,29 @@
+mod common;
+
+use common::*;
+use std::fs;
+
+#[test]
+fn aliases_expand_to_commands_and_shell_aliases_get_arguments() {
+    let dir = temp_repo("alias-expand");
+    write_file(&dir, "a.txt", "first\n");
+    vit(&dir, &["add", "."]);
+    vit(&dir, &["commit", "-m", "first"]);
+
+    vit(
+        &dir,
+        &["config", "set", "--global", "alias.lg", "log --format=%s"],
+    );
+    vit(&dir, &["config", "set", "alias.last", "lg -n 1"]);
+    vit(&dir, &["config", "set", "alias.greet", "!echo hello"]);
+
+    // Aliases may refer to other aliases and keep trailing arguments
+    assert_eq!(stdout(vit(&dir, &["--no-pager", "last"])), "first");
+    assert_eq!(stdout(vit(&dir, &["greet", "a", "b"])), "hello a b");
+
+    // Built in commands win over aliases of the same name
+    vit(&dir, &["config", "set", "alias.log", "status"]);
+    assert_eq!(stdout(vit(&dir, &["log", "--format=%s"])), "first");
+
+    fs::remove_dir_all(dir).unwrap();
+}
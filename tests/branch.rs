@@ -0,0 +1,158 @@
+mod common;
+
+use common::*;
+use std::{fs, path::Path};
+
+fn branches(dir: &Path, args: &[&str]) -> Vec<String> {
+    let args = [&["branch"], args].concat();
+    stdout(vit(dir, &args))
+        .lines()
+        .map(|line| line.trim_end_matches(" *").to_string())
+        .collect()
+}
+
+/// Subjects of the reflog of `name`, newest first.
+fn reflog(dir: &Path, name: &str) -> Vec<String> {
+    stdout(vit(dir, &["reflog", "show", name]))
+        .lines()
+        .map(|line| line.split_once(": ").unwrap().1.to_string())
+        .collect()
+}
+
+#[test]
+fn unmerged_branches_are_only_deleted_by_force() {
+    let dir = temp_repo("branch-delete");
+
+    commit_file(&dir, "a.txt", "first\n", "first");
+    vit(&dir, &["branch", "merged"]);
+    vit(&dir, &["branch", "topic"]);
+    vit(&dir, &["checkout", "topic"]);
+    commit_file(&dir, "b.txt", "topic\n", "topic work");
+    vit(&dir, &["checkout", "main"]);
+
+    let output = vit(&dir, &["branch", "-d", "topic"]);
+    assert!(stderr(output).contains("The branch 'topic' is not fully merged."));
+    assert_eq!(branches(&dir, &[]), ["main", "merged", "topic"]);
+
+    let output = vit(&dir, &["branch", "-d", "main"]);
+    assert!(stderr(output).contains("Can not delete active branch"));
+
+    vit(&dir, &["branch", "-d", "merged"]);
+    let output = vit(&dir, &["branch", "-D", "topic"]);
+    assert!(stdout(output).starts_with("Branch 'topic' deleted (was "));
+    assert_eq!(branches(&dir, &[]), ["main"]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn renaming_the_current_branch_moves_head_and_reflog() {
+    let dir = temp_repo("branch-rename");
+
+    commit_file(&dir, "a.txt", "first\n", "first");
+    commit_file(&dir, "a.txt", "second\n", "second");
+    let tip = rev(&dir, "main");
+
+    vit(&dir, &["branch", "-m", "trunk"]);
+    assert_eq!(
+        stdout(vit(&dir, &["symbolic-ref", "HEAD"])),
+        "refs/heads/trunk"
+    );
+    assert_eq!(branches(&dir, &[]), ["trunk"]);
+    assert_eq!(rev(&dir, "trunk"), tip);
+
+    let log = reflog(&dir, "trunk");
+    assert_eq!(
+        log[0],
+        "Branch: renamed refs/heads/main to refs/heads/trunk"
+    );
+    assert_eq!(&log[1..], ["commit: second", "commit (initial): first"]);
+    assert!(!dir.join("work/.vit/logs/refs/heads/main").exists());
+
+    // The new name must be free unless forced
+    vit(&dir, &["branch", "other"]);
+    let output = vit(&dir, &["branch", "-m", "other"]);
+    assert!(stderr(output).contains("already exists"));
+    vit(&dir, &["branch", "-M", "other"]);
+    assert_eq!(branches(&dir, &[]), ["other"]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn copying_a_branch_copies_its_reflog() {
+    let dir = temp_repo("branch-copy");
+
+    commit_file(&dir, "a.txt", "first\n", "first");
+    commit_file(&dir, "a.txt", "second\n", "second");
+
+    vit(&dir, &["branch", "-c", "main", "backup"]);
+    assert_eq!(branches(&dir, &[]), ["backup", "main"]);
+    assert_eq!(rev(&dir, "backup"), rev(&dir, "main"));
+    assert_eq!(
+        stdout(vit(&dir, &["symbolic-ref", "HEAD"])),
+        "refs/heads/main"
+    );
+
+    let log = reflog(&dir, "backup");
+    assert_eq!(
+        log[0],
+        "Branch: copied refs/heads/main to refs/heads/backup"
+    );
+    assert_eq!(&log[1..], reflog(&dir, "main"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn merged_no_merged_and_contains_filter_the_list() {
+    let dir = temp_repo("branch-filters");
+
+    let first = commit_file(&dir, "a.txt", "first\n", "first");
+    vit(&dir, &["branch", "old"]);
+    vit(&dir, &["branch", "topic"]);
+    let second = commit_file(&dir, "a.txt", "second\n", "second");
+    vit(&dir, &["checkout", "topic"]);
+    commit_file(&dir, "b.txt", "topic\n", "topic work");
+    vit(&dir, &["checkout", "main"]);
+
+    assert_eq!(branches(&dir, &["--merged", "main"]), ["main", "old"]);
+    assert_eq!(branches(&dir, &["--no-merged", "main"]), ["topic"]);
+    assert_eq!(
+        branches(&dir, &["--contains", &first]),
+        ["main", "old", "topic"]
+    );
+    assert_eq!(branches(&dir, &["--contains", &second]), ["main"]);
+
+    let verbose = stdout(vit(&dir, &["branch", "-v", "--merged", "main"]));
+    let lines: Vec<&str> = verbose.lines().collect();
+    assert_eq!(lines[0], format!("* main {} second", &second[..7]));
+    assert_eq!(lines[1], format!("  old  {} first", &first[..7]));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn checking_out_a_broken_branch_leaves_head_alone() {
+    let dir = temp_repo("branch-broken-checkout");
+
+    let tip = commit_file(&dir, "a.txt", "first\n", "first");
+    let refs = dir.join("work/.vit/refs/heads");
+
+    // update-ref refuses these, a damaged repository may still have them
+    let blob = hex::encode(blob_hash("first\n"));
+    for (name, target) in [("missing", "ab".repeat(32)), ("blob", blob)] {
+        fs::write(refs.join(name), format!("{}\n", target)).unwrap();
+
+        let output = vit(&dir, &["checkout", name]);
+        assert!(stderr(output).contains("fatal: unable to read commit"));
+        assert_eq!(
+            stdout(vit(&dir, &["symbolic-ref", "HEAD"])),
+            "refs/heads/main"
+        );
+        assert_eq!(rev(&dir, "HEAD"), tip);
+        assert_eq!(read_file(&dir, "a.txt"), "first\n");
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,297 @@
+mod common;
+
+use common::*;
+use std::{fs, path::Path, process::Command, thread, time::Duration};
+use version_it::utils::{CommitEntry, parse_commit_data, read_object_by_hash};
+
+#[test]
+fn empty_commits_are_refused_unless_allowed() {
+    let dir = temp_repo("commit-empty");
+
+    let output = vit(&dir, &["commit", "-m", "nothing"]);
+    assert!(stderr(output).contains("nothing to commit"));
+    assert!(!dir.join("work/.vit/refs/heads/main").exists());
+
+    write_file(&dir, "a.txt", "first\n");
+    vit(&dir, &["add", "a.txt"]);
+    vit(&dir, &["commit", "-m", "first"]);
+    let first = rev(&dir, "refs/heads/main");
+
+    let output = vit(&dir, &["commit", "-m", "again"]);
+    assert!(stderr(output).contains("nothing to commit"));
+    assert_eq!(rev(&dir, "refs/heads/main"), first);
+
+    vit(&dir, &["commit", "--allow-empty", "-m", "marker"]);
+    assert_ne!(rev(&dir, "refs/heads/main"), first);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn amend_replaces_head_and_keeps_its_parents() {
+    let dir = temp_repo("commit-amend");
+
+    write_file(&dir, "a.txt", "first\n");
+    vit(&dir, &["add", "a.txt"]);
+    vit(&dir, &["commit", "-m", "first"]);
+    let first = rev(&dir, "refs/heads/main");
+
+    write_file(&dir, "b.txt", "second\n");
+    vit(&dir, &["add", "b.txt"]);
+    vit(&dir, &["commit", "-m", "second"]);
+
+    write_file(&dir, "c.txt", "third\n");
+    vit(&dir, &["add", "c.txt"]);
+    vit(&dir, &["commit", "--amend", "--no-edit"]);
+
+    let log = stdout(vit(&dir, &["log", "--oneline"]));
+    let subjects: Vec<&str> = log
+        .lines()
+        .map(|line| line.split_once(' ').unwrap().1)
+        .collect();
+    assert_eq!(subjects, ["second", "first"]);
+    let parent = stdout(vit(&dir, &["log", "--pretty=%P", "-n", "1"]));
+    assert_eq!(parent, first);
+
+    let reflog = stdout(vit(&dir, &["reflog"]));
+    assert!(
+        reflog
+            .lines()
+            .next()
+            .unwrap()
+            .contains("commit (amend): second"),
+        "{}",
+        reflog
+    );
+
+    fs::write(dir.join("message.txt"), "second, reworded\n").unwrap();
+    vit(
+        &dir,
+        &["commit", "--amend", "--allow-empty", "-F", "../message.txt"],
+    );
+    let subject = stdout(vit(&dir, &["log", "--pretty=%s", "-n", "1"]));
+    assert_eq!(subject, "second, reworded");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+fn head_commit(dir: &Path) -> CommitEntry {
+    let hash: [u8; 32] = hex::decode(rev(dir, "HEAD")).unwrap().try_into().unwrap();
+    let (_, data) = read_object_by_hash(&dir.join("work/.vit/objects"), &hash).unwrap();
+    parse_commit_data(&data).unwrap()
+}
+
+#[test]
+fn amend_keeps_the_author_and_author_date() {
+    let dir = temp_repo("commit-amend-author");
+
+    vit(&dir, &["config", "set", "user.name", "Ada Lovelace"]);
+    vit(&dir, &["config", "set", "user.email", "ada@example.com"]);
+    commit_file(&dir, "a.txt", "first\n", "first");
+    let original = head_commit(&dir);
+
+    // A second later, and as someone else
+    thread::sleep(Duration::from_secs(1));
+    vit(&dir, &["config", "set", "user.name", "Charles Babbage"]);
+    vit(
+        &dir,
+        &["config", "set", "user.email", "charles@example.com"],
+    );
+    write_file(&dir, "a.txt", "fixed\n");
+    vit(&dir, &["add", "a.txt"]);
+    vit(&dir, &["commit", "--amend", "-m", "first, fixed"]);
+
+    let amended = head_commit(&dir);
+    assert_eq!(amended.author, "Ada Lovelace <ada@example.com>");
+    assert_eq!(amended.timestamp, original.timestamp);
+    assert_eq!(amended.timezone, original.timezone);
+    assert_eq!(amended.committer, "Charles Babbage <charles@example.com>");
+    assert!(amended.committer_timestamp > original.committer_timestamp);
+
+    let log = stdout(vit(&dir, &["log", "--format=%an|%at|%s"]));
+    assert_eq!(
+        log,
+        format!("Ada Lovelace|{}|first, fixed", original.timestamp)
+    );
+
+    // A new commit is authored by whoever makes it
+    commit_file(&dir, "b.txt", "b\n", "second");
+    assert_eq!(
+        head_commit(&dir).author,
+        "Charles Babbage <charles@example.com>"
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn all_stages_tracked_changes_and_dry_run_commits_nothing() {
+    let dir = temp_repo("commit-all");
+
+    write_file(&dir, "a.txt", "first\n");
+    write_file(&dir, "b.txt", "first\n");
+    vit(&dir, &["add", "."]);
+    vit(&dir, &["commit", "-m", "first"]);
+    let first = rev(&dir, "refs/heads/main");
+
+    write_file(&dir, "a.txt", "changed\n");
+    fs::remove_file(dir.join("work/b.txt")).unwrap();
+    write_file(&dir, "untracked.txt", "new\n");
+
+    let dry_run = stdout(vit(&dir, &["commit", "-a", "--dry-run"]));
+    assert!(dry_run.contains("modified: a.txt"), "{}", dry_run);
+    assert!(dry_run.contains("deleted:  b.txt"), "{}", dry_run);
+    assert!(!dry_run.contains("untracked.txt"), "{}", dry_run);
+    assert_eq!(rev(&dir, "refs/heads/main"), first);
+
+    vit(&dir, &["commit", "-a", "-m", "second"]);
+    let stat = stdout(vit(&dir, &["log", "--stat", "-n", "1"]));
+    assert!(stat.contains("a.txt"), "{}", stat);
+    assert!(stat.contains("b.txt"), "{}", stat);
+    assert!(!stat.contains("untracked.txt"), "{}", stat);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+fn write_editor(dir: &Path, script: &str) -> String {
+    let path = dir.join("editor.sh");
+    fs::write(&path, format!("#!/bin/sh\n{}", script)).unwrap();
+    let path = path.to_str().unwrap().to_string();
+    Command::new("chmod").args(["+x", &path]).status().unwrap();
+    path
+}
+
+#[test]
+fn editor_template_lists_status_and_verbose_diff_is_cut_off() {
+    let dir = temp_repo("commit-editor");
+
+    write_file(&dir, "a.txt", "first line\n");
+    write_file(&dir, "untracked.txt", "new\n");
+    vit(&dir, &["add", "a.txt"]);
+
+    // Save what the editor was shown, then write the message above it
+    let seen = dir.join("seen.txt");
+    let editor = write_editor(
+        &dir,
+        &format!(
+            "cp \"$1\" {0}\n{{ printf 'subject  \\n\\n\\n\\nbody\\n'; cat {0}; }} > \"$1\"\n",
+            seen.display()
+        ),
+    );
+    vit(&dir, &["config", "set", "core.editor", &editor]);
+    vit(&dir, &["commit", "-v"]);
+
+    let template = fs::read_to_string(&seen).unwrap();
+    assert!(template.contains("# On branch main"), "{}", template);
+    assert!(
+        template.contains("# Changes to be committed:\n#\tnew file:   a.txt"),
+        "{}",
+        template
+    );
+    assert!(
+        template.contains("# Untracked files:\n#\tuntracked.txt"),
+        "{}",
+        template
+    );
+    assert!(
+        template.contains("# ------------------------ >8 ------------------------"),
+        "{}",
+        template
+    );
+    assert!(template.contains("+first line"), "{}", template);
+
+    let message = stdout(vit(&dir, &["log", "--pretty=format:%s|%b", "-n", "1"]));
+    assert_eq!(message, "subject|body");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn editor_failures_and_untouched_templates_abort_cleanly() {
+    let dir = temp_repo("commit-editor-abort");
+
+    write_file(&dir, "a.txt", "first\n");
+    vit(&dir, &["add", "a.txt"]);
+
+    vit(&dir, &["config", "set", "core.editor", "false"]);
+    let output = vit(&dir, &["commit"]);
+    assert!(stderr(output).contains("There was a problem with the editor 'false'"));
+
+    fs::write(dir.join("template.txt"), "Ticket: \n").unwrap();
+    vit(
+        &dir,
+        &["config", "set", "commit.template", "../template.txt"],
+    );
+    vit(&dir, &["config", "set", "core.editor", "true"]);
+    let output = vit(&dir, &["commit"]);
+    assert!(stderr(output).contains("you did not edit the message"));
+
+    assert!(!dir.join("work/.vit/refs/heads/main").exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cleanup_modes_decide_what_is_kept() {
+    let dir = temp_repo("commit-cleanup");
+
+    write_file(&dir, "a.txt", "first\n");
+    vit(&dir, &["add", "a.txt"]);
+    vit(&dir, &["commit", "-m", "subject\n\n\n# kept  "]);
+    let message = stdout(vit(&dir, &["log", "--pretty=format:%s|%b", "-n", "1"]));
+    assert_eq!(message, "subject|# kept");
+
+    vit(
+        &dir,
+        &[
+            "commit",
+            "--amend",
+            "--cleanup=strip",
+            "-m",
+            "subject\n# dropped",
+        ],
+    );
+    let message = stdout(vit(&dir, &["log", "--pretty=format:%s|%b", "-n", "1"]));
+    assert_eq!(message, "subject|");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn commits_stashes_and_tags_need_an_identity() {
+    let dir = temp_repo("commit-identity");
+    fs::remove_file(dir.join("global.cfg")).unwrap();
+
+    write_file(&dir, "a.txt", "first\n");
+    vit(&dir, &["add", "a.txt"]);
+    let output = vit(&dir, &["commit", "-m", "first"]);
+    assert!(stderr(output).contains("Please tell me who you are"));
+    assert!(!dir.join("work/.vit/refs/heads/main").exists());
+
+    // A name alone is not enough
+    vit(&dir, &["config", "set", "user.name", "Ada Lovelace"]);
+    let output = vit(&dir, &["commit", "-m", "first"]);
+    assert!(stderr(output).contains("Please tell me who you are"));
+
+    vit(&dir, &["config", "set", "user.email", "ada@example.com"]);
+    vit(&dir, &["commit", "-m", "first"]);
+    let author = stdout(vit(&dir, &["log", "--format=%an <%ae>"]));
+    assert_eq!(author, "Ada Lovelace <ada@example.com>");
+
+    vit(&dir, &["config", "unset", "user.email"]);
+    write_file(&dir, "a.txt", "changed\n");
+    let output = vit(&dir, &["stash"]);
+    assert!(stderr(output).contains("Please tell me who you are"));
+    assert_eq!(read_file(&dir, "a.txt"), "changed\n");
+    let output = vit(&dir, &["tag", "-a", "-m", "Release", "v1.0"]);
+    assert!(stderr(output).contains("Please tell me who you are"));
+    assert!(!dir.join("work/.vit/refs/tags/v1.0").exists());
+
+    // Lightweight tags and branches record no identity
+    vit(&dir, &["tag", "v1.0"]);
+    vit(&dir, &["branch", "topic"]);
+    assert!(dir.join("work/.vit/refs/tags/v1.0").exists());
+    assert!(dir.join("work/.vit/refs/heads/topic").exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
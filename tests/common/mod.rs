@@ -0,0 +1,210 @@
+//! Helpers shared by the integration tests. Every test gets its own
+//! directory holding the work tree in `work` and the global config in
+//! `global.cfg`, so the config of whoever runs the tests does not leak in.
+
+#![allow(dead_code)]
+
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fs::{self, File},
+    io::{BufRead, BufReader, Read, Write},
+    os::fd::FromRawFd,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Output, Stdio},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+/// A fresh test directory with an empty `work` directory in it and a global
+/// config that only sets the identity commits are made with.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("vit-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("work")).unwrap();
+    fs::write(
+        dir.join("global.cfg"),
+        "[user]\n\tname = Test User\n\temail = test@example.com\n",
+    )
+    .unwrap();
+    dir
+}
+
+/// A test directory with a repository initialised in `work`.
+pub fn temp_repo(name: &str) -> PathBuf {
+    let dir = temp_dir(name);
+    vit(&dir, &["init"]);
+    dir
+}
+
+fn command(dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_version_it"));
+    command
+        .args(args)
+        .current_dir(dir.join("work"))
+        .env("VIT_CONFIG_GLOBAL", dir.join("global.cfg"))
+        .env("VIT_CONFIG_NOSYSTEM", "1")
+        .env_remove("VIT_PAGER")
+        .env_remove("PAGER");
+    command
+}
+
+/// Runs vit in `<dir>/work` and asserts it exits successfully.
+pub fn vit(dir: &Path, args: &[&str]) -> Output {
+    vit_with_input(dir, args, "")
+}
+
+/// Runs vit in `<dir>/work` with a reader that closes standard output after
+/// the first line, like `vit log | head -1`. Returns the exit status and
+/// standard error.
+pub fn vit_into_closed_pipe(dir: &Path, args: &[&str]) -> (ExitStatus, String) {
+    let mut child = command(dir, args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status,
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+/// Like [`vit`], with `input` on standard input.
+pub fn vit_with_input(dir: &Path, args: &[&str], input: &str) -> Output {
+    let mut child = command(dir, args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "vit {:?} failed: {:?}",
+        args,
+        output
+    );
+    output
+}
+
+/// Like [`vit`] with extra environment variables.
+pub fn vit_with_env(dir: &Path, args: &[&str], vars: &[(&str, &str)]) -> Output {
+    let output = command(dir, args)
+        .envs(vars.iter().copied())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "vit {:?} failed: {:?}",
+        args,
+        output
+    );
+    output
+}
+
+/// Runs vit with stdout on a terminal and returns what reached it, the
+/// pager only starts on one. Stderr is left out.
+pub fn vit_on_terminal(dir: &Path, args: &[&str], vars: &[(&str, &str)]) -> String {
+    let (mut master, slave) = open_terminal();
+    let child = command(dir, args)
+        .envs(vars.iter().copied())
+        .stdin(Stdio::null())
+        .stdout(slave)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Reading fails with EIO once every process let go of the terminal
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    while let Ok(read @ 1..) = master.read(&mut chunk) {
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "vit {:?} failed: {:?}",
+        args,
+        output
+    );
+    String::from_utf8_lossy(&buffer).replace("\r\n", "\n")
+}
+
+/// Both ends of a new pseudo terminal.
+fn open_terminal() -> (File, File) {
+    let (mut master, mut slave) = (0, 0);
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    assert_eq!(result, 0, "openpty failed");
+    unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) }
+}
+
+/// Writes `<dir>/work/<name>`, creating missing directories.
+///
+/// Change detection compares modification times at second granularity, so
+/// every write gets a later time than the one before to be noticed.
+pub fn write_file(dir: &Path, name: &str, content: &str) {
+    static WRITES: AtomicU64 = AtomicU64::new(1);
+
+    let path = dir.join("work").join(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, content).unwrap();
+    let later = Duration::from_secs(WRITES.fetch_add(1, Ordering::Relaxed));
+    let file = File::options().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::now() + later).unwrap();
+}
+
+pub fn read_file(dir: &Path, name: &str) -> String {
+    fs::read_to_string(dir.join("work").join(name)).unwrap()
+}
+
+pub fn stdout(output: Output) -> String {
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+pub fn stderr(output: Output) -> String {
+    String::from_utf8_lossy(&output.stderr).trim().to_string()
+}
+
+/// The hash of a blob holding `content`.
+pub fn blob_hash(content: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("blob {}\0", content.len()).as_bytes());
+    hasher.update(content.as_bytes());
+    hasher.finalize().into()
+}
+
+/// The hash `name` resolves to.
+pub fn rev(dir: &Path, name: &str) -> String {
+    stdout(vit(dir, &["log", "--format=%H", "-n", "1", name]))
+}
+
+/// Makes a commit writing `content` to `name`, returns its hash.
+pub fn commit_file(dir: &Path, name: &str, content: &str, message: &str) -> String {
+    write_file(dir, name, content);
+    vit(dir, &["add", name]);
+    vit(dir, &["commit", "-m", message]);
+    rev(dir, "HEAD")
+}
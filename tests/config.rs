@@ -0,0 +1,60 @@
+mod common;
+
+use common::*;
+use std::fs;
+
+#[test]
+fn repository_config_overrides_global_and_includes_are_followed() {
+    let dir = temp_dir("config-scopes");
+
+    vit(
+        &dir,
+        &["config", "set", "--global", "init.defaultBranch", "trunk"],
+    );
+    vit(
+        &dir,
+        &["config", "set", "--global", "user.name", "Global Name"],
+    );
+    vit(
+        &dir,
+        &["config", "set", "--global", "include.path", "extra.cfg"],
+    );
+    fs::write(
+        dir.join("extra.cfg"),
+        "[user]\n\temail = included@example.com\n",
+    )
+    .unwrap();
+
+    vit(&dir, &["init"]);
+    let head = fs::read_to_string(dir.join("work/.vit/HEAD")).unwrap();
+    assert_eq!(head, "ref: refs/heads/trunk\n");
+
+    vit(&dir, &["config", "set", "user.name", "Local Name"]);
+    assert_eq!(
+        stdout(vit(&dir, &["config", "get", "user.name"])),
+        "Local Name"
+    );
+    assert_eq!(
+        stdout(vit(&dir, &["config", "get", "--global", "user.name"])),
+        "Global Name"
+    );
+
+    write_file(&dir, "a.txt", "first\n");
+    vit(&dir, &["add", "."]);
+    vit(&dir, &["commit", "-m", "first"]);
+
+    let log = stdout(vit(&dir, &["log", "-n", "1"]));
+    assert!(
+        log.contains("Author: Local Name <included@example.com>"),
+        "{}",
+        log
+    );
+
+    vit(&dir, &["config", "unset", "user.name"]);
+    assert_eq!(
+        stdout(vit(&dir, &["config", "get", "user.name"])),
+        "Global Name"
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,77 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+fn write_hook(dir: &Path, name: &str, script: &str) {
+    fs::create_dir_all(dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, script).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+fn head_subject(dir: &Path) -> String {
+    stdout(vit(dir, &["log", "--oneline", "-n", "1"]))
+        .split_once(' ')
+        .map_or("", |(_, subject)| subject)
+        .to_string()
+}
+
+#[test]
+fn failing_pre_commit_blocks_commit_unless_no_verify() {
+    let dir = temp_repo("hooks-pre-commit");
+    let work = dir.join("work");
+    write_hook(
+        &work.join(".vit/hooks"),
+        "pre-commit",
+        "#!/bin/sh\necho nope >&2\nexit 1\n",
+    );
+
+    write_file(&dir, "a.txt", "first\n");
+    vit(&dir, &["add", "a.txt"]);
+    let output = vit(&dir, &["commit", "-m", "blocked"]);
+    assert!(stderr(output).contains("pre-commit hook failed"));
+    assert!(!work.join(".vit/refs/heads/main").exists());
+
+    vit(&dir, &["commit", "--no-verify", "-m", "forced"]);
+    assert_eq!(head_subject(&dir), "forced");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn commit_msg_hook_rewrites_message_and_post_hooks_run() {
+    let dir = temp_repo("hooks-commit-msg");
+    let work = dir.join("work");
+
+    // core.hooksPath relative to the work tree
+    vit(&dir, &["config", "set", "core.hooksPath", "githooks"]);
+    let hooks = work.join("githooks");
+    write_hook(
+        &hooks,
+        "commit-msg",
+        "#!/bin/sh\nprintf 'rewritten: %s' \"$(cat \"$1\")\" > \"$1\"\n",
+    );
+    write_hook(&hooks, "post-commit", "#!/bin/sh\ntouch post-commit-ran\n");
+    write_hook(
+        &hooks,
+        "post-checkout",
+        "#!/bin/sh\necho \"$1 $2 $3\" > post-checkout-args\n",
+    );
+
+    write_file(&dir, "a.txt", "first\n");
+    vit(&dir, &["add", "a.txt"]);
+    vit(&dir, &["commit", "-m", "original"]);
+    assert_eq!(head_subject(&dir), "rewritten: original");
+    assert!(work.join("post-commit-ran").exists());
+
+    vit(&dir, &["branch", "topic"]);
+    vit(&dir, &["checkout", "topic"]);
+    let args = fs::read_to_string(work.join("post-checkout-args")).unwrap();
+    let head = fs::read_to_string(work.join(".vit/refs/heads/topic")).unwrap();
+    assert_eq!(args.trim(), format!("{} {} 1", head.trim(), head.trim()));
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,307 @@
+mod common;
+
+use chrono::{DateTime, FixedOffset};
+use common::*;
+use std::{fs, path::Path, thread, time::Duration};
+
+/// Subjects `log` prints for `args`, in order.
+fn subjects(dir: &Path, args: &[&str]) -> Vec<String> {
+    let args = [&["log", "--format=%s"], args].concat();
+    stdout(vit(dir, &args))
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// `template` expanded for HEAD.
+fn rev_format(dir: &Path, template: &str, args: &[&str]) -> String {
+    let format = format!("--format={}", template);
+    let args = [&["log", "-n", "1", &format], args].concat();
+    let output = vit(dir, &args);
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .trim_end_matches('\n')
+        .to_string()
+}
+
+/// Like [`subjects`], sorted where the order is not the point.
+fn sorted_subjects(dir: &Path, args: &[&str]) -> Vec<String> {
+    let mut subjects = subjects(dir, args);
+    subjects.sort();
+    subjects
+}
+
+/// Commit timestamps have second granularity, start the next one in a new
+/// second so the commits are ordered by date.
+fn next_second() {
+    thread::sleep(Duration::from_secs(1));
+}
+
+/// main: a - b - e, topic branching off b: c - d.
+fn diverged_history(dir: &Path) {
+    commit_file(dir, "main.txt", "a\n", "a");
+    commit_file(dir, "main.txt", "b\n", "b");
+    vit(dir, &["branch", "topic"]);
+    vit(dir, &["checkout", "topic"]);
+    commit_file(dir, "topic.txt", "c\n", "c");
+    vit(dir, &["checkout", "main"]);
+    commit_file(dir, "main.txt", "e\n", "e");
+    vit(dir, &["checkout", "topic"]);
+    commit_file(dir, "topic.txt", "d\n", "d");
+    vit(dir, &["checkout", "main"]);
+}
+
+#[test]
+fn ranges_and_exclusions_select_commits() {
+    let dir = temp_repo("log-ranges");
+    diverged_history(&dir);
+
+    assert_eq!(sorted_subjects(&dir, &["main..topic"]), ["c", "d"]);
+    assert_eq!(sorted_subjects(&dir, &["topic..main"]), ["e"]);
+    assert_eq!(sorted_subjects(&dir, &["..topic"]), ["c", "d"]);
+    assert_eq!(sorted_subjects(&dir, &["main...topic"]), ["c", "d", "e"]);
+    assert_eq!(sorted_subjects(&dir, &["topic", "^main"]), ["c", "d"]);
+    assert_eq!(sorted_subjects(&dir, &["^topic", "main"]), ["e"]);
+    assert_eq!(
+        sorted_subjects(&dir, &["--all", "^main~1"]),
+        ["c", "d", "e"]
+    );
+    assert_eq!(sorted_subjects(&dir, &["main"]), ["a", "b", "e"]);
+
+    // Counts too large for a number are unknown revisions, not a crash
+    for revision in [
+        "main~99999999999999999999999",
+        "main^99999999999999999999999",
+    ] {
+        let output = vit(&dir, &["log", revision]);
+        assert!(stderr(output).contains("unknown revision"));
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn topo_and_date_order_and_date_limits() {
+    let dir = temp_repo("log-order");
+
+    let mut timestamps = Vec::new();
+    for (branch, name, message) in [
+        ("main", "main.txt", "a"),
+        ("main", "main.txt", "b"),
+        ("topic", "topic.txt", "c"),
+        ("main", "main.txt", "e"),
+        ("topic", "topic.txt", "d"),
+    ] {
+        if message == "c" {
+            vit(&dir, &["branch", "topic"]);
+        }
+        vit(&dir, &["checkout", branch]);
+        next_second();
+        commit_file(&dir, name, &format!("{}\n", message), message);
+        timestamps.push(stdout(vit(&dir, &["log", "--format=%at", "-n", "1"])));
+    }
+    vit(&dir, &["checkout", "main"]);
+
+    // By date the branches interleave, topologically each stays together
+    let all = ["main", "topic"];
+    let date_order = [&all[..], &["--date-order"]].concat();
+    let topo_order = [&all[..], &["--topo-order"]].concat();
+    assert_eq!(subjects(&dir, &date_order), ["d", "e", "c", "b", "a"]);
+    assert_eq!(subjects(&dir, &topo_order), ["d", "c", "e", "b", "a"]);
+
+    let since = format!("--since=@{}", timestamps[2]);
+    let until = format!("--until=@{}", timestamps[2]);
+    assert_eq!(subjects(&dir, &["--all", &since]), ["d", "e", "c"]);
+    assert_eq!(subjects(&dir, &["--all", &until]), ["c", "b", "a"]);
+    assert_eq!(subjects(&dir, &["--all", &since, &until]), ["c"]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn author_and_grep_filter_commits() {
+    let dir = temp_repo("log-author-grep");
+
+    vit(&dir, &["config", "set", "user.name", "Ada Lovelace"]);
+    vit(&dir, &["config", "set", "user.email", "ada@example.com"]);
+    commit_file(&dir, "a.txt", "a\n", "Add the engine");
+    vit(&dir, &["config", "set", "user.name", "Charles Babbage"]);
+    vit(
+        &dir,
+        &["config", "set", "user.email", "charles@example.com"],
+    );
+    commit_file(&dir, "a.txt", "b\n", "Fix the engine\n\nThe gears slipped.");
+    commit_file(&dir, "b.txt", "c\n", "Write notes");
+
+    assert_eq!(subjects(&dir, &["--author=Ada"]), ["Add the engine"]);
+    assert_eq!(
+        sorted_subjects(&dir, &["--author=charles@example"]),
+        ["Fix the engine", "Write notes"]
+    );
+    assert_eq!(
+        sorted_subjects(&dir, &["--grep=engine"]),
+        ["Add the engine", "Fix the engine"]
+    );
+
+    // The body is searched too, and the filters combine
+    assert_eq!(subjects(&dir, &["--grep=gears"]), ["Fix the engine"]);
+    assert_eq!(
+        subjects(&dir, &["--grep=^(Add|Write)"]),
+        ["Write notes", "Add the engine"]
+    );
+    assert_eq!(
+        subjects(&dir, &["--author=Babbage", "--grep=^(Add|Write)"]),
+        ["Write notes"]
+    );
+    assert!(subjects(&dir, &["--author=Nobody"]).is_empty());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn date_modes_render_the_commit_time() {
+    let dir = temp_repo("log-date");
+
+    // Commits record the offset they were made in, +0530 here
+    let tz = [("TZ", "IST-5:30")];
+    write_file(&dir, "a.txt", "a\n");
+    vit_with_env(&dir, &["add", "a.txt"], &tz);
+    vit_with_env(&dir, &["commit", "-m", "first"], &tz);
+
+    let timestamp: i64 = rev_format(&dir, "%at", &[]).parse().unwrap();
+    let utc = DateTime::from_timestamp(timestamp, 0).unwrap();
+    let recorded = utc.with_timezone(&FixedOffset::east_opt(5 * 3600 + 1800).unwrap());
+
+    let date = |mode: &str| {
+        let mode = format!("--date={}", mode);
+        let output = vit_with_env(&dir, &["log", "--format=%ad", &mode], &[("TZ", "UTC")]);
+        stdout(output)
+    };
+    assert_eq!(
+        date("default"),
+        recorded.format("%a %b %-d %H:%M:%S %Y +0530").to_string()
+    );
+    assert_eq!(
+        date("iso"),
+        recorded.format("%Y-%m-%d %H:%M:%S +0530").to_string()
+    );
+    assert_eq!(date("short"), recorded.format("%Y-%m-%d").to_string());
+    assert_eq!(date("unix"), timestamp.to_string());
+    assert_eq!(
+        date("local"),
+        utc.format("%a %b %-d %H:%M:%S %Y").to_string()
+    );
+    assert!(date("relative").ends_with(" ago"), "{}", date("relative"));
+
+    let output = vit(&dir, &["log", "--date=yesterday"]);
+    assert!(stderr(output).contains("Unknown date format 'yesterday'"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn format_placeholders_expand() {
+    let dir = temp_repo("log-format");
+
+    vit(&dir, &["config", "set", "user.name", "Ada Lovelace"]);
+    vit(&dir, &["config", "set", "user.email", "ada@example.com"]);
+    let first = commit_file(&dir, "a.txt", "a\n", "first");
+    let second = commit_file(&dir, "a.txt", "b\n", "Subject line\n\nBody text.");
+    vit(&dir, &["tag", "v1.0"]);
+
+    let tree = stdout(vit(&dir, &["log", "--format=%T", "-n", "1"]));
+    assert_eq!(tree.len(), 64);
+    let placeholders = [
+        ("%H", second.clone()),
+        ("%h", second[..7].to_string()),
+        ("%t", tree[..7].to_string()),
+        ("%P", first.clone()),
+        ("%p", first[..7].to_string()),
+        ("%an", "Ada Lovelace".to_string()),
+        ("%ae", "ada@example.com".to_string()),
+        ("%s", "Subject line".to_string()),
+        ("%b", "Body text.".to_string()),
+        ("%D", "HEAD -> main, tag: v1.0".to_string()),
+        ("%d", " (HEAD -> main, tag: v1.0)".to_string()),
+        ("100%%", "100%".to_string()),
+        ("%s%n%an", "Subject line\nAda Lovelace".to_string()),
+        ("<%s> %x", "<Subject line> %x".to_string()),
+    ];
+    for (placeholder, expected) in placeholders {
+        assert_eq!(
+            rev_format(&dir, placeholder, &["--decorate"]),
+            expected,
+            "{}",
+            placeholder
+        );
+    }
+
+    // Every commit gets a line, the root commit has no parents
+    let output = vit(&dir, &["log", "--pretty=format:%h %p|%s"]);
+    assert_eq!(
+        stdout(output),
+        format!(
+            "{} {}|Subject line\n{} |first",
+            &second[..7],
+            &first[..7],
+            &first[..7]
+        )
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn graph_draws_branches_and_merges() {
+    let dir = temp_repo("log-graph");
+    vit(&dir, &["config", "set", "user.name", "Ada"]);
+
+    commit_file(&dir, "main.txt", "a\n", "a");
+    commit_file(&dir, "main.txt", "b\n", "b");
+    vit(&dir, &["branch", "topic"]);
+    vit(&dir, &["checkout", "topic"]);
+    next_second();
+    commit_file(&dir, "topic.txt", "c\n", "c");
+    vit(&dir, &["checkout", "main"]);
+    next_second();
+    let e = commit_file(&dir, "main.txt", "e\n", "e");
+
+    // A stash commit merges the index commit into HEAD
+    next_second();
+    write_file(&dir, "main.txt", "wip\n");
+    vit(&dir, &["stash"]);
+
+    let output = vit(
+        &dir,
+        &["log", "--graph", "--all", "--decorate", "--format=%s%d"],
+    );
+    let expected = format!(
+        "\
+*   WIP on main: {e} e (refs/stash)
+|\\
+| * index on main: {e} e
+|/
+* e (HEAD -> main)
+| * c (topic)
+|/
+* b
+* a",
+        e = &e[..7]
+    );
+    assert_eq!(stdout(output), expected);
+
+    // Lines of a commit after the first continue its lanes
+    let output = stdout(vit(
+        &dir,
+        &["log", "--graph", "--format=%s%n%an", "main", "topic"],
+    ));
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "* e", "| Ada", "| * c", "|/  Ada", "* b", "| Ada", "* a", "  Ada"
+        ]
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
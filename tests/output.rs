@@ -0,0 +1,97 @@
+mod common;
+
+use common::*;
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+/// A pager announcing `name` before passing the output through.
+fn pager(dir: &Path, name: &str) -> String {
+    let path = dir.join(format!("pager-{}", name));
+    fs::write(
+        &path,
+        format!("#!/bin/sh\necho \"paged by {}\"\nexec cat\n", name),
+    )
+    .unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn pager_is_taken_from_vit_pager_then_core_pager_then_pager() {
+    let dir = temp_repo("output-pager");
+    commit_file(&dir, "a.txt", "a\n", "first");
+
+    let (vit_pager, config_pager, env_pager) = (
+        pager(&dir, "vit"),
+        pager(&dir, "config"),
+        pager(&dir, "env"),
+    );
+    let log = ["log", "--format=%s"];
+
+    let output = vit_on_terminal(&dir, &log, &[("PAGER", &env_pager)]);
+    assert_eq!(output, "paged by env\nfirst\n");
+
+    vit(&dir, &["config", "set", "core.pager", &config_pager]);
+    let output = vit_on_terminal(&dir, &log, &[("PAGER", &env_pager)]);
+    assert_eq!(output, "paged by config\nfirst\n");
+
+    let vars = [("PAGER", env_pager.as_str()), ("VIT_PAGER", &vit_pager)];
+    let output = vit_on_terminal(&dir, &log, &vars);
+    assert_eq!(output, "paged by vit\nfirst\n");
+
+    // No pager with --no-pager, for `cat` or an empty one, or off a terminal
+    let output = vit_on_terminal(&dir, &["--no-pager", "log", "--format=%s"], &vars);
+    assert_eq!(output, "first\n");
+    let output = vit_on_terminal(&dir, &log, &[("VIT_PAGER", "cat")]);
+    assert_eq!(output, "first\n");
+    let output = vit_on_terminal(&dir, &log, &[("VIT_PAGER", "")]);
+    assert_eq!(output, "first\n");
+    assert_eq!(stdout(vit_with_env(&dir, &log, &vars)), "first");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn color_follows_the_flag_then_color_ui_then_the_terminal() {
+    let dir = temp_repo("output-color");
+    commit_file(&dir, "a.txt", "a\n", "first");
+
+    let colored = |output: &str| output.contains("\x1b[");
+    let no_pager = [("VIT_PAGER", "cat")];
+    let log = ["log", "--oneline"];
+    let always = ["log", "--oneline", "--color=always"];
+    let never = ["log", "--oneline", "--color=never"];
+    let auto = ["log", "--oneline", "--color=auto"];
+
+    assert!(colored(&vit_on_terminal(&dir, &log, &no_pager)));
+    assert!(!colored(&stdout(vit(&dir, &log))));
+    assert!(colored(&stdout(vit(&dir, &always))));
+    assert!(!colored(&vit_on_terminal(&dir, &never, &no_pager)));
+
+    vit(&dir, &["config", "set", "color.ui", "never"]);
+    assert!(!colored(&vit_on_terminal(&dir, &log, &no_pager)));
+    assert!(colored(&vit_on_terminal(&dir, &auto, &no_pager)));
+
+    vit(&dir, &["config", "set", "color.ui", "always"]);
+    assert!(colored(&stdout(vit(&dir, &log))));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_reader_that_goes_away_ends_output_quietly() {
+    let dir = temp_repo("output-broken-pipe");
+
+    // More history than fits in a pipe buffer
+    for n in 0..20 {
+        let content: String = (0..1000).map(|line| format!("{} {}\n", n, line)).collect();
+        commit_file(&dir, "a.txt", &content, &format!("commit {}", n));
+    }
+
+    for args in [&["log", "-p"][..], &["log", "--stat"], &["reflog"]] {
+        let (status, stderr) = vit_into_closed_pipe(&dir, args);
+        assert_ne!(status.code(), Some(101), "{:?}: {}", args, stderr);
+        assert!(!stderr.contains("panicked"), "{:?}: {}", args, stderr);
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
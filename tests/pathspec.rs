@@ -0,0 +1,74 @@
+mod common;
+
+use common::*;
+use std::{fs, path::Path};
+
+fn staged(dir: &Path) -> Vec<String> {
+    let status = stdout(vit(dir, &["status"]));
+    status
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("new file: "))
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn globs_and_magic_select_what_add_stages() {
+    let dir = temp_repo("pathspec-add");
+
+    write_file(&dir, "main.rs", "main\n");
+    write_file(&dir, "src/mod.rs", "top\n");
+    write_file(&dir, "src/deep/mod.rs", "deep\n");
+    write_file(&dir, "src/deep/lib.rs", "lib\n");
+    write_file(&dir, "README", "readme\n");
+    write_file(&dir, "notes.txt", "notes\n");
+    write_file(&dir, "skip.txt", "skip\n");
+
+    vit(&dir, &["add", "src/**/mod.rs"]);
+    assert_eq!(staged(&dir), ["src/deep/mod.rs", "src/mod.rs"]);
+
+    vit(&dir, &["add", "*.rs", ":!src/deep"]);
+    assert_eq!(staged(&dir), ["main.rs", "src/deep/mod.rs", "src/mod.rs"]);
+
+    vit(&dir, &["add", ":(icase)readme", ":/notes.txt"]);
+    assert_eq!(
+        staged(&dir),
+        [
+            "README",
+            "main.rs",
+            "notes.txt",
+            "src/deep/mod.rs",
+            "src/mod.rs"
+        ]
+    );
+
+    let output = vit(&dir, &["add", "*.md"]);
+    assert!(stderr(output).contains("pathspec '*.md' did not match any files"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn status_and_log_are_limited_by_pathspecs() {
+    let dir = temp_repo("pathspec-status-log");
+
+    write_file(&dir, "a.rs", "a\n");
+    vit(&dir, &["add", "a.rs"]);
+    vit(&dir, &["commit", "-m", "rust"]);
+    write_file(&dir, "b.txt", "b\n");
+    vit(&dir, &["add", "b.txt"]);
+    vit(&dir, &["commit", "-m", "text"]);
+
+    let log = stdout(vit(&dir, &["log", "--pretty=%s", "--", "*.rs"]));
+    assert_eq!(log, "rust");
+    let log = stdout(vit(&dir, &["log", "--pretty=%s", "--", ":!*.rs"]));
+    assert_eq!(log, "text");
+
+    write_file(&dir, "c.rs", "c\n");
+    write_file(&dir, "d.txt", "d\n");
+    let status = stdout(vit(&dir, &["status", "*.txt"]));
+    assert!(status.contains("d.txt"), "{}", status);
+    assert!(!status.contains("c.rs"), "{}", status);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use common::*;
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+use version_it::utils::{invalidate_pack_cache, read_object_by_hash, store_blob_from_reader};
+
+/// Whether the object store holds a blob of `content`, loose or packed.
+fn has_blob(dir: &Path, content: &str) -> bool {
+    // gc replaces packs behind the back of this process
+    invalidate_pack_cache();
+    read_object_by_hash(&dir.join("work/.vit/objects"), &blob_hash(content)).is_ok()
+}
+
+/// gc, then prune everything unreachable regardless of its age.
+fn collect_garbage(dir: &Path) {
+    vit(dir, &["gc"]);
+    vit(dir, &["prune", "--expire=all"]);
+}
+
+#[test]
+fn reflogs_keep_objects_until_they_expire() {
+    let dir = temp_repo("prune-reflog");
+
+    commit_file(&dir, "a.txt", "first\n", "first");
+    vit(&dir, &["branch", "topic"]);
+    vit(&dir, &["checkout", "topic"]);
+    commit_file(&dir, "lost.txt", "only in the reflog\n", "lost");
+    vit(&dir, &["checkout", "main"]);
+    vit(&dir, &["branch", "-D", "topic"]);
+
+    collect_garbage(&dir);
+    assert!(has_blob(&dir, "only in the reflog\n"));
+
+    vit(&dir, &["reflog", "expire", "--expire=now", "--all"]);
+    collect_garbage(&dir);
+    assert!(!has_blob(&dir, "only in the reflog\n"));
+    assert!(has_blob(&dir, "first\n"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn stash_entries_keep_their_objects() {
+    let dir = temp_repo("prune-stash");
+
+    commit_file(&dir, "a.txt", "first\n", "first");
+    write_file(&dir, "a.txt", "older stash\n");
+    vit(&dir, &["stash"]);
+    write_file(&dir, "a.txt", "newer stash\n");
+    vit(&dir, &["stash"]);
+
+    // Only the stash reflog still knows stash@{1}
+    vit(&dir, &["reflog", "expire", "--expire=now", "HEAD", "main"]);
+    collect_garbage(&dir);
+
+    assert!(has_blob(&dir, "older stash\n"));
+    assert!(has_blob(&dir, "newer stash\n"));
+    vit(&dir, &["stash", "pop", "stash@{1}"]);
+    assert_eq!(read_file(&dir, "a.txt"), "older stash\n");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn staged_blobs_are_kept_and_replaced_ones_pruned() {
+    let dir = temp_repo("prune-index");
+
+    commit_file(&dir, "a.txt", "first\n", "first");
+    write_file(&dir, "b.txt", "staged once\n");
+    vit(&dir, &["add", "b.txt"]);
+    write_file(&dir, "b.txt", "staged twice\n");
+    vit(&dir, &["add", "b.txt"]);
+
+    // Unreachable objects are only pruned after the grace period
+    vit(&dir, &["gc"]);
+    assert!(has_blob(&dir, "staged once\n"));
+
+    vit(&dir, &["prune", "--expire=all"]);
+    assert!(!has_blob(&dir, "staged once\n"));
+    assert!(has_blob(&dir, "staged twice\n"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn annotated_tags_keep_the_tagged_history() {
+    let dir = temp_repo("prune-tag");
+
+    commit_file(&dir, "a.txt", "first\n", "first");
+    vit(&dir, &["branch", "topic"]);
+    vit(&dir, &["checkout", "topic"]);
+    let tagged = commit_file(&dir, "b.txt", "only tagged\n", "tagged");
+    vit(&dir, &["tag", "-a", "-m", "Release", "v1.0"]);
+    vit(&dir, &["checkout", "main"]);
+    vit(&dir, &["branch", "-D", "topic"]);
+
+    vit(&dir, &["reflog", "expire", "--expire=now", "--all"]);
+    collect_garbage(&dir);
+
+    assert!(has_blob(&dir, "only tagged\n"));
+    assert_eq!(rev(&dir, "v1.0"), tagged);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Hands out `valid` bytes, then fails.
+struct FailingReader {
+    valid: usize,
+}
+
+impl Read for FailingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.valid == 0 {
+            return Err(io::Error::other("disk went away"));
+        }
+        let len = buf.len().min(self.valid);
+        buf[..len].fill(b'a');
+        self.valid -= len;
+        Ok(len)
+    }
+}
+
+#[test]
+fn temporary_objects_are_removed_on_failure_and_pruned_when_stale() {
+    let dir = temp_repo("prune-temp");
+    let vit_dir = dir.join("work/.vit");
+    let objects_dir = vit_dir.join("objects");
+
+    let is_temp = |name: &str| name.starts_with("tmp_obj_");
+    let temp_objects = || -> Vec<String> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&objects_dir).unwrap() {
+            let entry = entry.unwrap();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type().unwrap().is_dir() {
+                for file in fs::read_dir(entry.path()).unwrap() {
+                    let file = file.unwrap().file_name().to_string_lossy().to_string();
+                    if is_temp(&file) {
+                        names.push(format!("{}/{}", name, file));
+                    }
+                }
+            } else if is_temp(&name) {
+                names.push(name);
+            }
+        }
+        names.sort();
+        names
+    };
+
+    let mut reader = FailingReader { valid: 100 };
+    assert!(store_blob_from_reader(&vit_dir, 1000, &mut reader).is_err());
+    assert!(temp_objects().is_empty());
+
+    // What a writer killed before its rename leaves behind
+    commit_file(&dir, "a.txt", "a\n", "first");
+    fs::write(objects_dir.join("tmp_obj_1_0"), "partial").unwrap();
+    fs::create_dir_all(objects_dir.join("ab")).unwrap();
+    fs::write(objects_dir.join("ab/tmp_obj_1_1"), "partial").unwrap();
+
+    vit(&dir, &["prune"]);
+    assert_eq!(temp_objects(), ["ab/tmp_obj_1_1", "tmp_obj_1_0"]);
+
+    let output = stdout(vit(&dir, &["prune", "-n", "--expire=all"]));
+    assert!(output.contains("tmp_obj_1_0"), "{}", output);
+    assert_eq!(temp_objects().len(), 2);
+
+    vit(&dir, &["prune", "--expire=all"]);
+    assert!(temp_objects().is_empty());
+    assert!(has_blob(&dir, "a\n"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
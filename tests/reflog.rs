@@ -0,0 +1,37 @@
+mod common;
+
+use common::*;
+use std::fs;
+
+#[test]
+fn deleted_branch_can_be_recovered_from_its_reflog() {
+    let repo = temp_repo("reflog-recover");
+
+    write_file(&repo, "a.txt", "first\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "first"]);
+
+    vit(&repo, &["branch", "topic"]);
+    vit(&repo, &["checkout", "topic"]);
+    write_file(&repo, "a.txt", "second\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "second"]);
+    let tip = stdout(vit(&repo, &["log", "--format=%H", "-n", "1"]));
+
+    vit(&repo, &["checkout", "main"]);
+    vit(&repo, &["branch", "-D", "topic"]);
+
+    // Both the branch log and HEAD's log still know the lost commit
+    let head_log = stdout(vit(&repo, &["reflog"]));
+    assert!(
+        head_log.contains("HEAD@{1}: commit: second"),
+        "{}",
+        head_log
+    );
+
+    vit(&repo, &["branch", "rescued", "topic@{1}"]);
+    let rescued = stdout(vit(&repo, &["log", "--format=%H", "-n", "1", "rescued"]));
+    assert_eq!(rescued, tip);
+
+    fs::remove_dir_all(repo).unwrap();
+}
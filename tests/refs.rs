@@ -0,0 +1,103 @@
+mod common;
+
+use common::*;
+use std::{fs, path::Path};
+
+fn show_ref(dir: &Path, name: &str) -> String {
+    stdout(vit(dir, &["show-ref", "--hash", "--verify", name]))
+}
+
+#[test]
+fn packed_refs_are_read_and_updated_only_when_expected() {
+    let repo = temp_repo("refs-packed");
+
+    write_file(&repo, "a.txt", "first\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "first"]);
+    vit(&repo, &["branch", "topic"]);
+    let first = show_ref(&repo, "refs/heads/main");
+
+    // gc moves every loose ref into packed-refs
+    vit(&repo, &["gc"]);
+    assert!(!repo.join("work/.vit/refs/heads/main").exists());
+    let packed = fs::read_to_string(repo.join("work/.vit/packed-refs")).unwrap();
+    assert!(packed.contains(&format!("{} refs/heads/topic", first)));
+
+    write_file(&repo, "b.txt", "second\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "second"]);
+    let second = show_ref(&repo, "refs/heads/main");
+    assert_ne!(first, second);
+
+    // A stale expected value leaves the ref alone
+    vit(&repo, &["update-ref", "refs/heads/topic", "main", &second]);
+    assert_eq!(show_ref(&repo, "refs/heads/topic"), first);
+
+    vit(&repo, &["update-ref", "refs/heads/topic", "main", &first]);
+    assert_eq!(show_ref(&repo, "refs/heads/topic"), second);
+
+    let output = vit(&repo, &["symbolic-ref", "--short", "HEAD"]);
+    assert_eq!(stdout(output), "main");
+
+    fs::remove_dir_all(repo).unwrap();
+}
+
+#[test]
+fn locked_refs_are_neither_deleted_nor_packed() {
+    let repo = temp_repo("refs-locked");
+
+    let first = commit_file(&repo, "a.txt", "first\n", "first");
+    vit(&repo, &["branch", "topic"]);
+    let second = commit_file(&repo, "b.txt", "second\n", "second");
+
+    // Deleting checks the expected value, and only while holding the lock
+    let output = vit(&repo, &["update-ref", "-d", "refs/heads/topic", &second]);
+    assert!(stderr(output).contains("expected"));
+    assert_eq!(show_ref(&repo, "refs/heads/topic"), first);
+
+    let lock = repo.join("work/.vit/refs/heads/topic.lock");
+    fs::write(&lock, "").unwrap();
+    let output = vit(&repo, &["update-ref", "-d", "refs/heads/topic", &first]);
+    assert!(stderr(output).contains("File exists"));
+    assert_eq!(show_ref(&repo, "refs/heads/topic"), first);
+
+    // gc packs what it can lock and leaves the rest loose
+    vit(&repo, &["gc"]);
+    assert!(lock.exists());
+    assert!(repo.join("work/.vit/refs/heads/topic").is_file());
+    assert!(!repo.join("work/.vit/refs/heads/main").exists());
+    let packed = fs::read_to_string(repo.join("work/.vit/packed-refs")).unwrap();
+    assert!(packed.contains(&format!("{} refs/heads/main", second)));
+    assert!(!packed.contains("refs/heads/topic"));
+
+    fs::remove_file(&lock).unwrap();
+    vit(&repo, &["update-ref", "-d", "refs/heads/topic", &first]);
+    assert!(stdout(vit(&repo, &["show-ref"])).contains("refs/heads/main"));
+    assert!(!stdout(vit(&repo, &["show-ref"])).contains("refs/heads/topic"));
+
+    fs::remove_dir_all(repo).unwrap();
+}
+
+#[test]
+fn refs_only_point_at_existing_objects_and_branches_at_commits() {
+    let repo = temp_repo("refs-targets");
+
+    let first = commit_file(&repo, "a.txt", "first\n", "first");
+    let missing = "ab".repeat(32);
+    let blob = hex::encode(blob_hash("first\n"));
+
+    let output = vit(&repo, &["update-ref", "refs/heads/bad", &missing]);
+    assert!(stderr(output).contains("nonexistent object"));
+    let output = vit(&repo, &["update-ref", "refs/heads/bad", &blob]);
+    assert!(stderr(output).contains("non-commit object"));
+    let output = vit(&repo, &["update-ref", "HEAD", &blob]);
+    assert!(stderr(output).contains("non-commit object"));
+    assert!(!stdout(vit(&repo, &["show-ref"])).contains("refs/heads/bad"));
+    assert_eq!(show_ref(&repo, "refs/heads/main"), first);
+
+    // Other refs may point at any object that exists
+    vit(&repo, &["update-ref", "refs/tags/blob", &blob]);
+    assert_eq!(show_ref(&repo, "refs/tags/blob"), blob);
+
+    fs::remove_dir_all(repo).unwrap();
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::*;
+use std::fs;
+
+fn numbered_lines(replace: &[(usize, &str)]) -> String {
+    (1..=20)
+        .map(|n| {
+            let line = replace
+                .iter()
+                .find(|(at, _)| *at == n)
+                .map_or(n.to_string(), |(_, text)| text.to_string());
+            format!("{}\n", line)
+        })
+        .collect()
+}
+
+/// The parts of `status` before and after "Changes not staged for commit".
+fn staged_and_unstaged(status: &str) -> (String, String) {
+    let (staged, unstaged) = status
+        .split_once("Changes not staged for commit:")
+        .unwrap_or((status, ""));
+    (staged.to_string(), unstaged.to_string())
+}
+
+#[test]
+fn reset_unstages_and_leaves_the_work_tree_alone() {
+    let dir = temp_repo("reset");
+
+    write_file(&dir, "a.txt", "a\n");
+    vit(&dir, &["add", "a.txt"]);
+    vit(&dir, &["commit", "-m", "first"]);
+
+    write_file(&dir, "a.txt", "changed\n");
+    write_file(&dir, "new.txt", "new\n");
+    vit(&dir, &["add", "."]);
+    vit(&dir, &["reset", "a.txt"]);
+
+    let status = stdout(vit(&dir, &["status"]));
+    let (staged, unstaged) = staged_and_unstaged(&status);
+    assert!(staged.contains("new.txt"), "{}", status);
+    assert!(!staged.contains("a.txt"), "{}", status);
+    assert!(unstaged.contains("modified: a.txt"), "{}", status);
+
+    // Without paths everything is unstaged, added files become untracked
+    vit(&dir, &["reset"]);
+    let status = stdout(vit(&dir, &["status"]));
+    assert!(!status.contains("Changes to be committed"), "{}", status);
+    assert!(status.contains("Untracked files"), "{}", status);
+    assert_eq!(read_file(&dir, "a.txt"), "changed\n");
+    assert_eq!(read_file(&dir, "new.txt"), "new\n");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reset_patch_unstages_the_chosen_hunks() {
+    let dir = temp_repo("reset-patch");
+
+    write_file(&dir, "a.txt", &numbered_lines(&[]));
+    vit(&dir, &["add", "a.txt"]);
+    vit(&dir, &["commit", "-m", "first"]);
+
+    let edited = numbered_lines(&[(2, "two"), (18, "eighteen")]);
+    write_file(&dir, "a.txt", &edited);
+    vit(&dir, &["add", "a.txt"]);
+    let prompts = stdout(vit_with_input(&dir, &["reset", "-p"], "n\ny\n"));
+    assert!(prompts.contains("(1/2) Unstage this hunk"), "{}", prompts);
+    assert!(prompts.contains("-eighteen"), "{}", prompts);
+
+    vit(&dir, &["commit", "-m", "second"]);
+    let patch = stdout(vit(&dir, &["log", "-p", "-n", "1"]));
+    assert!(patch.contains("+two"), "{}", patch);
+    assert!(!patch.contains("+eighteen"), "{}", patch);
+    assert_eq!(read_file(&dir, "a.txt"), edited);
+
+    let output = vit_with_input(&dir, &["reset", "-p"], "");
+    assert_eq!(stdout(output), "No changes.");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn restore_discards_work_tree_changes() {
+    let dir = temp_repo("restore");
+
+    write_file(&dir, "a.txt", &numbered_lines(&[]));
+    write_file(&dir, "b.txt", "b\n");
+    vit(&dir, &["add", "."]);
+    vit(&dir, &["commit", "-m", "first"]);
+
+    write_file(
+        &dir,
+        "a.txt",
+        &numbered_lines(&[(2, "two"), (18, "eighteen")]),
+    );
+    fs::remove_file(dir.join("work/b.txt")).unwrap();
+    let prompts = stdout(vit_with_input(&dir, &["restore", "-p"], "n\ny\ny\n"));
+    assert!(prompts.contains("Discard this hunk"), "{}", prompts);
+    assert!(prompts.contains("Discard deletion"), "{}", prompts);
+
+    assert_eq!(read_file(&dir, "a.txt"), numbered_lines(&[(2, "two")]));
+    assert_eq!(read_file(&dir, "b.txt"), "b\n");
+
+    vit(&dir, &["restore", "a.txt"]);
+    assert_eq!(read_file(&dir, "a.txt"), numbered_lines(&[]));
+    let status = stdout(vit(&dir, &["status"]));
+    assert!(status.contains("Everything is up to date"), "{}", status);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,219 @@
+mod common;
+
+use common::*;
+use std::{fs, path::Path};
+use version_it::utils::{flatten_tree, parse_commit_data, read_object_by_hash};
+
+#[test]
+fn stash_pop_keeps_blobs_referenced_by_history() {
+    let repo = temp_repo("stash-pop-shared");
+
+    write_file(&repo, "a.txt", "first\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "first"]);
+
+    // Stash "second", then commit the very same content
+    write_file(&repo, "a.txt", "second\n");
+    vit(&repo, &["stash"]);
+    write_file(&repo, "a.txt", "second\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "second"]);
+
+    vit(&repo, &["stash", "pop"]);
+
+    let objects_dir = repo.join("work/.vit/objects");
+    assert!(read_object_by_hash(&objects_dir, &blob_hash("second\n")).is_ok());
+    assert!(read_object_by_hash(&objects_dir, &blob_hash("first\n")).is_ok());
+
+    fs::remove_dir_all(repo).unwrap();
+}
+
+#[test]
+fn stash_clear_keeps_blobs_referenced_by_index() {
+    let repo = temp_repo("stash-clear-shared");
+
+    write_file(&repo, "a.txt", "first\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "first"]);
+
+    write_file(&repo, "a.txt", "second\n");
+    vit(&repo, &["stash"]);
+    write_file(&repo, "a.txt", "second\n");
+    vit(&repo, &["add", "."]);
+
+    vit(&repo, &["stash", "clear"]);
+
+    let objects_dir = repo.join("work/.vit/objects");
+    assert!(read_object_by_hash(&objects_dir, &blob_hash("second\n")).is_ok());
+
+    fs::remove_dir_all(repo).unwrap();
+}
+
+#[test]
+fn stash_pop_merges_with_work_tree_edits() {
+    let repo = temp_repo("stash-pop-merge");
+
+    write_file(&repo, "a.txt", "one\ntwo\nthree\nfour\nfive\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "first"]);
+
+    write_file(&repo, "a.txt", "ONE\ntwo\nthree\nfour\nfive\n");
+    vit(&repo, &["stash"]);
+    write_file(&repo, "a.txt", "one\ntwo\nthree\nfour\nFIVE\n");
+
+    vit(&repo, &["stash", "pop"]);
+
+    let content = read_file(&repo, "a.txt");
+    assert_eq!(content, "ONE\ntwo\nthree\nfour\nFIVE\n");
+    assert!(vit(&repo, &["stash", "list"]).stdout.is_empty());
+
+    fs::remove_dir_all(repo).unwrap();
+}
+
+#[test]
+fn stash_pop_keeps_entry_on_conflict() {
+    let repo = temp_repo("stash-pop-conflict");
+
+    write_file(&repo, "a.txt", "one\ntwo\nthree\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "first"]);
+
+    write_file(&repo, "a.txt", "one\nstashed\nthree\n");
+    vit(&repo, &["stash"]);
+    write_file(&repo, "a.txt", "one\nedited\nthree\n");
+
+    vit(&repo, &["stash", "pop"]);
+
+    let content = read_file(&repo, "a.txt");
+    assert_eq!(
+        content,
+        "one\n<<<<<<< Updated upstream\nedited\n=======\nstashed\n>>>>>>> Stashed changes\nthree\n"
+    );
+    assert!(!vit(&repo, &["stash", "list"]).stdout.is_empty());
+
+    fs::remove_dir_all(repo).unwrap();
+}
+
+#[test]
+fn stash_entries_are_read_with_multi_word_identities() {
+    let repo = temp_repo("stash-identity");
+    vit(&repo, &["config", "set", "user.name", "Jane Q Doe"]);
+    vit(&repo, &["config", "set", "user.email", "jane@example.com"]);
+
+    commit_file(&repo, "a.txt", "first\n", "first");
+    write_file(&repo, "a.txt", "one\n");
+    vit(&repo, &["stash", "save", "one"]);
+    write_file(&repo, "a.txt", "two\n");
+    vit(&repo, &["stash", "save", "two"]);
+
+    let newest = stdout(vit(
+        &repo,
+        &["show-ref", "--hash", "--verify", "refs/stash"],
+    ));
+    let list = stdout(vit(&repo, &["stash", "list"]));
+    let lines: Vec<&str> = list.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", list);
+    assert_eq!(
+        lines[0],
+        format!("stash@{{0}}: {}: On main: two", &newest[..8])
+    );
+    assert!(lines[1].starts_with("stash@{1}: "), "{}", list);
+    assert!(lines[1].ends_with(": On main: one"), "{}", list);
+
+    vit(&repo, &["stash", "drop", "stash@{1}"]);
+    let list = stdout(vit(&repo, &["stash", "list"]));
+    assert_eq!(list, format!("stash@{{0}}: {}: On main: two", &newest[..8]));
+
+    vit(&repo, &["stash", "pop"]);
+    assert_eq!(read_file(&repo, "a.txt"), "two\n");
+    assert!(vit(&repo, &["stash", "list"]).stdout.is_empty());
+
+    fs::remove_dir_all(repo).unwrap();
+}
+
+/// Parents of `hash` and the content of every file in its tree.
+fn commit_contents(dir: &Path, hash: &[u8; 32]) -> (Vec<[u8; 32]>, Vec<(String, String)>) {
+    let objects_dir = dir.join("work/.vit/objects");
+    let (_, data) = read_object_by_hash(&objects_dir, hash).unwrap();
+    let commit = parse_commit_data(&data).unwrap();
+    let files = flatten_tree(&objects_dir, &commit.tree)
+        .into_iter()
+        .map(|(path, entry)| {
+            let (_, blob) = read_object_by_hash(&objects_dir, &entry.sha256).unwrap();
+            (path, String::from_utf8(blob).unwrap())
+        })
+        .collect();
+    (commit.parents, files)
+}
+
+fn files(entries: &[(&str, &str)]) -> Vec<(String, String)> {
+    entries
+        .iter()
+        .map(|(path, content)| (path.to_string(), content.to_string()))
+        .collect()
+}
+
+#[test]
+fn stash_records_index_and_untracked_files_and_applies_them() {
+    let dir = temp_repo("stash-index-untracked");
+
+    write_file(&dir, "src/a.txt", "first\n");
+    write_file(&dir, "b.txt", "first\n");
+    write_file(&dir, "gone.txt", "first\n");
+    vit(&dir, &["add", "."]);
+    vit(&dir, &["commit", "-m", "first"]);
+    let head = rev(&dir, "HEAD");
+
+    write_file(&dir, "src/a.txt", "staged\n");
+    vit(&dir, &["add", "src/a.txt"]);
+    write_file(&dir, "b.txt", "unstaged\n");
+    fs::remove_file(dir.join("work/gone.txt")).unwrap();
+    write_file(&dir, "new.txt", "untracked\n");
+
+    vit(&dir, &["stash", "-u"]);
+    assert_eq!(read_file(&dir, "src/a.txt"), "first\n");
+    assert_eq!(read_file(&dir, "b.txt"), "first\n");
+    assert!(!dir.join("work/new.txt").exists());
+
+    // HEAD, the index and the untracked files are the stash's parents
+    let stash: [u8; 32] = hex::decode(rev(&dir, "stash@{0}"))
+        .unwrap()
+        .try_into()
+        .unwrap();
+    let (parents, tree) = commit_contents(&dir, &stash);
+    assert_eq!(parents.len(), 3);
+    assert_eq!(hex::encode(parents[0]), head);
+    assert_eq!(
+        tree,
+        files(&[("b.txt", "unstaged\n"), ("src/a.txt", "staged\n")])
+    );
+
+    let (_, index) = commit_contents(&dir, &parents[1]);
+    assert_eq!(
+        index,
+        files(&[
+            ("b.txt", "first\n"),
+            ("gone.txt", "first\n"),
+            ("src/a.txt", "staged\n")
+        ])
+    );
+    let (untracked_parents, untracked) = commit_contents(&dir, &parents[2]);
+    assert!(untracked_parents.is_empty());
+    assert_eq!(untracked, files(&[("new.txt", "untracked\n")]));
+
+    vit(&dir, &["stash", "apply", "--index"]);
+    assert_eq!(read_file(&dir, "src/a.txt"), "staged\n");
+    assert_eq!(read_file(&dir, "b.txt"), "unstaged\n");
+    assert_eq!(read_file(&dir, "new.txt"), "untracked\n");
+    assert!(!dir.join("work/gone.txt").exists());
+
+    let status = stdout(vit(&dir, &["status"]));
+    let (staged, unstaged) = status.split_once("Changes not staged").unwrap();
+    assert!(staged.contains("modified: src/a.txt"), "{}", status);
+    assert!(!staged.contains("b.txt"), "{}", status);
+    assert!(unstaged.contains("modified: b.txt"), "{}", status);
+    assert!(unstaged.contains("gone.txt"), "{}", status);
+    assert!(unstaged.contains("new.txt"), "{}", status);
+
+    fs::remove_dir_all(dir).unwrap();
+}
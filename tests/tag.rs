@@ -0,0 +1,26 @@
+mod common;
+
+use common::*;
+use std::fs;
+
+#[test]
+fn annotated_tag_resolves_to_commit_after_gc() {
+    let repo = temp_repo("tag-annotated-gc");
+
+    write_file(&repo, "a.txt", "first\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "first"]);
+    vit(&repo, &["tag", "-a", "-m", "Release", "v1.0"]);
+
+    // The ref holds the tag object, not the commit
+    let head = fs::read_to_string(repo.join("work/.vit/refs/heads/main")).unwrap();
+    let tag = fs::read_to_string(repo.join("work/.vit/refs/tags/v1.0")).unwrap();
+    assert_ne!(head.trim(), tag.trim());
+
+    vit(&repo, &["gc"]);
+
+    let output = vit(&repo, &["log", "--format=%H", "v1.0"]);
+    assert_eq!(stdout(output), head.trim());
+
+    fs::remove_dir_all(repo).unwrap();
+}
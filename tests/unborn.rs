@@ -0,0 +1,30 @@
+mod common;
+
+use common::*;
+use std::fs;
+
+#[test]
+fn unborn_branch_is_reported_instead_of_panicking() {
+    let repo = temp_dir("unborn");
+    vit(&repo, &["init", "--initial-branch", "trunk"]);
+
+    let head = fs::read_to_string(repo.join("work/.vit/HEAD")).unwrap();
+    assert_eq!(head, "ref: refs/heads/trunk\n");
+
+    let status = vit(&repo, &["status"]);
+    assert!(stdout(status).contains("No commits yet"));
+
+    let log = vit(&repo, &["log"]);
+    assert!(stderr(log).contains("does not have any commits yet"));
+
+    let branches = vit(&repo, &["branch"]);
+    assert!(branches.stdout.is_empty());
+
+    // The first commit creates the branch HEAD was naming all along
+    write_file(&repo, "a.txt", "first\n");
+    vit(&repo, &["add", "."]);
+    vit(&repo, &["commit", "-m", "first"]);
+    assert!(repo.join("work/.vit/refs/heads/trunk").is_file());
+
+    fs::remove_dir_all(repo).unwrap();
+}